@@ -0,0 +1,94 @@
+use core::cell::RefCell;
+
+use avr_device::interrupt::{CriticalSection, Mutex};
+use avr_device::{atmega328p, interrupt};
+use ofs_support::analog::{process_stick, Oversampler, RAW_MAX};
+use ofs_support::calibration::CALIBRATED_AXES;
+use ofs_support::fightstick::Fightstick;
+
+use crate::fightstick::STICK_CONFIG;
+use crate::settings::SETTINGS;
+use crate::support::clock::free;
+
+/// ADC0 and ADC1 are the left stick, ADC2 and ADC3 the right stick.
+const CHANNELS: usize = CALIBRATED_AXES;
+
+const REFS_AVCC: u8 = 1 << 6;
+const ADEN: u8 = 1 << 7;
+const ADSC: u8 = 1 << 6;
+const ADIE: u8 = 1 << 3;
+const ADPS_128: u8 = 0b111;
+
+struct Sampler {
+  channel: u8,
+  oversamplers: [Oversampler; CHANNELS],
+}
+
+static G_ADC: Mutex<RefCell<Option<atmega328p::ADC>>> = Mutex::new(RefCell::new(None));
+static SAMPLER: Mutex<RefCell<Sampler>> = Mutex::new(RefCell::new(Sampler {
+  channel: 0,
+  oversamplers: [Oversampler::new(); CHANNELS],
+}));
+static READINGS: Mutex<RefCell<[u16; CHANNELS]>> = Mutex::new(RefCell::new([RAW_MAX / 2; CHANNELS]));
+
+fn select_channel(adc: &atmega328p::ADC, channel: u8) {
+  adc.admux.write(|w| unsafe { w.bits(REFS_AVCC | channel) });
+}
+
+fn start_conversion(adc: &atmega328p::ADC) {
+  // ADC clock of 125 kHz at 16 MHz, every conversion raises the ADC interrupt
+  adc.adcsra.write(|w| unsafe { w.bits(ADEN | ADSC | ADIE | ADPS_128) });
+}
+
+pub fn setup_adc(cs: &CriticalSection, adc: atmega328p::ADC) {
+  // Digital input buffers only add noise to the analog pins
+  adc.didr0.write(|w| unsafe { w.bits((1 << CHANNELS) - 1) });
+  select_channel(&adc, 0);
+  start_conversion(&adc);
+
+  G_ADC.borrow(cs).replace(Some(adc));
+}
+
+/// Latest oversampled readings of every axis.
+pub fn read_axes(cs: &CriticalSection) -> [u16; CHANNELS] {
+  *READINGS.borrow(cs).borrow()
+}
+
+/// Fills in the analog axes. The left stick only drives X and Y while the
+/// lever is centred, so a hybrid stick can use either.
+pub fn apply_analog(fightstick: &mut Fightstick) {
+  let axes = free(read_axes);
+  let [left, right] = match SETTINGS.with(|settings| settings.sticks) {
+    Some(sticks) => sticks,
+    None => return,
+  };
+
+  if fightstick.x == 0 && fightstick.y == 0 {
+    let (x, y) = process_stick([axes[0], axes[1]], &left, &STICK_CONFIG);
+    fightstick.x = x;
+    fightstick.y = y;
+  }
+
+  let (rx, ry) = process_stick([axes[2], axes[3]], &right, &STICK_CONFIG);
+  fightstick.rx = rx;
+  fightstick.ry = ry;
+}
+
+#[interrupt(atmega328p)]
+fn ADC() {
+  interrupt::free(|cs| {
+    if let Some(adc) = G_ADC.borrow(cs).borrow().as_ref() {
+      let sample = adc.adc.read().bits();
+      let mut sampler = SAMPLER.borrow(cs).borrow_mut();
+      let channel = sampler.channel;
+
+      if let Some(reading) = sampler.oversamplers[channel as usize].push(sample) {
+        READINGS.borrow(cs).borrow_mut()[channel as usize] = reading;
+        sampler.channel = (channel + 1) % CHANNELS as u8;
+        select_channel(adc, sampler.channel);
+      }
+
+      start_conversion(adc);
+    }
+  });
+}
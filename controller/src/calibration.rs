@@ -0,0 +1,40 @@
+use ofs_support::calibration::{CalibrationEvent, CalibrationMachine};
+use ofs_support::input::PhysicalButtons;
+use ofs_support::resource::Resource;
+
+use crate::analog::read_axes;
+use crate::fightstick::CALIBRATION_CONFIG;
+use crate::settings::{save_settings, SETTINGS};
+use crate::support::clock::free;
+
+static CALIBRATION: Resource<CalibrationMachine> = Resource::new();
+
+pub fn setup_calibration() {
+  CALIBRATION.init(CalibrationMachine::new(CALIBRATION_CONFIG));
+}
+
+pub fn is_calibrating() -> bool {
+  CALIBRATION.with(|calibration| calibration.is_active()).unwrap_or(false)
+}
+
+/// Feeds a scan into the calibration routine, saving the sticks once a
+/// calibration is accepted. Returns whether calibration owns the buttons.
+pub fn update_calibration(buttons: PhysicalButtons, elapsed_ms: u16) -> bool {
+  let axes = free(read_axes);
+  let event = CALIBRATION
+    .with(|calibration| calibration.update(buttons, axes, elapsed_ms))
+    .flatten();
+
+  if let Some(CalibrationEvent::Calibrated(sticks)) = event {
+    SETTINGS.with(|settings| {
+      for (stored, calibrated) in settings.sticks.iter_mut().zip(sticks.iter()) {
+        if let Some(calibrated) = calibrated {
+          *stored = *calibrated;
+        }
+      }
+    });
+    save_settings();
+  }
+
+  is_calibrating()
+}
@@ -0,0 +1,142 @@
+use avr_device::atmega328p::{EXINT, PORTB, PORTC, PORTD};
+use ofs_support::debounce::Debouncer;
+use ofs_support::input::{determine_axis, PhysicalButtons};
+use ofs_support::resource::Resource;
+
+use crate::fightstick::{PhysicalInputs, DEBOUNCE_CONFIG, DIRECT_PINS};
+use crate::support::clock::{free, now};
+use crate::G_PORTB;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DirectPort {
+  /// Shares PCINT0 with the spinner.
+  B,
+  C,
+  D,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DirectInput {
+  Button(u8),
+  Up,
+  Down,
+  Left,
+  Right,
+}
+
+/// A switch wired between a pin and ground, read with the internal pull-up.
+#[derive(Clone, Copy)]
+pub struct DirectPin {
+  pub port: DirectPort,
+  pub bit: u8,
+  pub input: DirectInput,
+}
+
+impl DirectPin {
+  pub const fn new(port: DirectPort, bit: u8, input: DirectInput) -> DirectPin {
+    DirectPin { port, bit, input }
+  }
+}
+
+static G_PORTC: Resource<PORTC> = Resource::new();
+static G_PORTD: Resource<PORTD> = Resource::new();
+static DEBOUNCER: Resource<Debouncer> = Resource::new();
+
+fn port_mask(port: DirectPort) -> u8 {
+  DIRECT_PINS
+    .iter()
+    .filter(|pin| pin.port == port)
+    .fold(0, |mask, pin| mask | (1 << pin.bit))
+}
+
+/// Pulls up every direct pin and raises a pin change interrupt on each edge.
+/// Must run before `portb` is handed over to `G_PORTB`.
+pub fn setup_direct(exint: &EXINT, portb: &PORTB, portc: PORTC, portd: PORTD) {
+  let (mask_b, mask_c, mask_d) = (
+    port_mask(DirectPort::B),
+    port_mask(DirectPort::C),
+    port_mask(DirectPort::D),
+  );
+
+  portb.ddrb.modify(|r, w| unsafe { w.bits(r.bits() & !mask_b) });
+  portb.portb.modify(|r, w| unsafe { w.bits(r.bits() | mask_b) });
+  portc.ddrc.modify(|r, w| unsafe { w.bits(r.bits() & !mask_c) });
+  portc.portc.modify(|r, w| unsafe { w.bits(r.bits() | mask_c) });
+  portd.ddrd.modify(|r, w| unsafe { w.bits(r.bits() & !mask_d) });
+  portd.portd.modify(|r, w| unsafe { w.bits(r.bits() | mask_d) });
+
+  exint.pcmsk0.modify(|r, w| unsafe { w.bits(r.bits() | mask_b) });
+  exint.pcmsk1.modify(|r, w| unsafe { w.bits(r.bits() | mask_c) });
+  exint.pcmsk2.modify(|r, w| unsafe { w.bits(r.bits() | mask_d) });
+
+  let pcie = [mask_b, mask_c, mask_d]
+    .iter()
+    .enumerate()
+    .filter(|(_, &mask)| mask != 0)
+    .fold(0, |pcie, (index, _)| pcie | (1 << index));
+  exint.pcicr.modify(|r, w| unsafe { w.bits(r.bits() | pcie) });
+
+  G_PORTC.init(portc);
+  G_PORTD.init(portd);
+  DEBOUNCER.init(Debouncer::new(DEBOUNCE_CONFIG));
+}
+
+/// Reads every direct pin, one bit per entry of `DIRECT_PINS`, set while the
+/// switch is closed. Port B is shared with the spinner interrupt.
+fn read_lines() -> u16 {
+  let pinb = free(|cs| {
+    G_PORTB
+      .borrow(cs)
+      .borrow()
+      .as_ref()
+      .map_or(0xFF, |p| p.pinb.read().bits())
+  });
+  let pinc = G_PORTC.with(|p| p.pinc.read().bits()).unwrap_or(0xFF);
+  let pind = G_PORTD.with(|p| p.pind.read().bits()).unwrap_or(0xFF);
+
+  DIRECT_PINS.iter().enumerate().fold(0, |lines, (line, pin)| {
+    let levels = match pin.port {
+      DirectPort::B => pinb,
+      DirectPort::C => pinc,
+      DirectPort::D => pind,
+    };
+    let closed = (levels >> pin.bit) & 1 == 0;
+    lines | ((closed as u16) << line)
+  })
+}
+
+/// Samples the direct pins into the debouncer, returning whether a debounced
+/// input changed. Called on every pin change and again on each scan, which
+/// catches changes that happened during a lockout.
+pub fn sample_direct() -> bool {
+  let lines = read_lines();
+  let now = free(now);
+  DEBOUNCER
+    .with(|debouncer| debouncer.sample(lines, now))
+    .unwrap_or(false)
+}
+
+/// The debounced state of the direct pins.
+pub fn direct_inputs() -> PhysicalInputs {
+  let lines = DEBOUNCER.with(|debouncer| debouncer.state()).unwrap_or(0);
+  let closed = |input: DirectInput| {
+    DIRECT_PINS
+      .iter()
+      .enumerate()
+      .any(|(line, pin)| pin.input == input && (lines >> line) & 1 == 1)
+  };
+
+  let buttons = DIRECT_PINS
+    .iter()
+    .enumerate()
+    .fold(PhysicalButtons::NONE, |buttons, (line, pin)| match pin.input {
+      DirectInput::Button(index) => buttons.with(index, (lines >> line) & 1 == 1),
+      _ => buttons,
+    });
+
+  PhysicalInputs {
+    x: determine_axis(closed(DirectInput::Right), closed(DirectInput::Left)),
+    y: determine_axis(closed(DirectInput::Down), closed(DirectInput::Up)),
+    buttons,
+  }
+}
@@ -1,13 +1,140 @@
-use core::cell::RefCell;
-
 use avr_device::asm::nop;
 use avr_device::atmega328p::PORTD;
-use avr_device::interrupt::{CriticalSection, Mutex};
-use ofs_support::fightstick::Fightstick;
+use ofs_support::analog::{Curve, Deadzone, StickConfig};
+use ofs_support::calibration::CalibrationConfig;
+use ofs_support::debounce::DebounceConfig;
+use ofs_support::fightstick::{Button, Fightstick, OutputMode, UNREADABLE_FIGHTSTICK};
+use ofs_support::input::{determine_axis, PhysicalButtons};
+use ofs_support::lock::LockConfig;
+use ofs_support::quadrature::Sensitivity;
+use ofs_support::remap::RemapConfig;
+use ofs_support::resource::Resource;
+use ofs_support::settings::Profile;
+use ofs_support::time::Duration;
+
+use crate::direct::{direct_inputs, sample_direct, DirectInput, DirectPin, DirectPort};
+
+pub const U_A: u8 = 0;
+pub const U_B: u8 = 1;
+pub const U_C: u8 = 2;
+pub const U_D: u8 = 3;
+pub const D_A: u8 = 4;
+pub const D_B: u8 = 5;
+pub const D_C: u8 = 6;
+pub const D_D: u8 = 7;
+pub const START: u8 = 8;
+
+/// How the stick presents its buttons to the host.
+pub const OUTPUT_MODE: OutputMode = OutputMode::Pc;
+
+/// Logical button reported for each physical button until remapped, a Vewlix
+/// layout with punches on the top row and kicks on the bottom row.
+pub const DEFAULT_PROFILE: Profile = Profile::new([
+  Some(Button::LightPunch),  // U_A
+  Some(Button::MediumPunch), // U_B
+  Some(Button::HeavyPunch),  // U_C
+  Some(Button::L1),          // U_D
+  Some(Button::LightKick),   // D_A
+  Some(Button::MediumKick),  // D_B
+  Some(Button::HeavyKick),   // D_C
+  Some(Button::L2),          // D_D
+  Some(Button::Start),       // START
+  None,
+  None,
+  None,
+  None,
+  None,
+  None,
+  None,
+]);
+
+/// Hold Start and U_A for three seconds to enter remap mode, Start cancels.
+pub const REMAP_CONFIG: RemapConfig = RemapConfig {
+  combo: PhysicalButtons::from_indices(&[START, U_A]),
+  cancel: START,
+  hold_ms: 3000,
+  timeout_ms: 10000,
+};
+
+/// Hold Start, U_D and D_D for five seconds to toggle tournament lock, which
+/// masks Start, Select and Home.
+pub const LOCK_CONFIG: LockConfig = LockConfig {
+  combo: PhysicalButtons::from_indices(&[START, U_D, D_D]),
+  hold_ms: 5000,
+  blocked_buttons: Button::mask(&[Button::Start, Button::Select, Button::Home]),
+};
+
+/// Analog sticks on ADC0 through ADC3, see `analog`. Leave disabled unless
+/// sticks are wired, floating inputs read as noise.
+pub const ANALOG_ENABLED: bool = false;
+
+pub const STICK_CONFIG: StickConfig = StickConfig {
+  deadzone: Deadzone::Radial(8),
+  curve: Curve::Linear,
+};
+
+/// Hold Start and D_A for three seconds with the sticks at rest, then rotate
+/// each stick fully and release to calibrate. Only runs with `ANALOG_ENABLED`.
+pub const CALIBRATION_CONFIG: CalibrationConfig = CalibrationConfig {
+  combo: PhysicalButtons::from_indices(&[START, D_A]),
+  hold_ms: 3000,
+  min_travel_percent: 40,
+};
+
+/// Spinner or other quadrature encoder on PB0 and PB1 (pins 8 and 9), see
+/// `spinner`. Leave disabled unless an encoder is wired.
+pub const SPINNER_ENABLED: bool = false;
+
+/// Dial units reported per encoder count. Lower the ratio for encoders with
+/// many counts per turn.
+pub const SPINNER_SENSITIVITY: Sensitivity = Sensitivity::UNIT;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum InputWiring {
+  /// Buttons share lines, PD2 and PD3 select which group PD4 to PD7 read.
+  /// Scanned every `SCAN_PERIOD_MS`.
+  Matrix,
+  /// Every switch has its own pin from `DIRECT_PINS`, read on each edge.
+  Direct,
+}
+
+pub const INPUT_WIRING: InputWiring = InputWiring::Matrix;
+
+/// Pins used with `InputWiring::Direct`. Port C is shared with the analog
+/// sticks, so `ANALOG_ENABLED` must stay off with this layout.
+pub const DIRECT_PINS: [DirectPin; 13] = [
+  DirectPin::new(DirectPort::D, 2, DirectInput::Up),
+  DirectPin::new(DirectPort::D, 3, DirectInput::Down),
+  DirectPin::new(DirectPort::D, 4, DirectInput::Left),
+  DirectPin::new(DirectPort::D, 5, DirectInput::Right),
+  DirectPin::new(DirectPort::D, 6, DirectInput::Button(U_A)),
+  DirectPin::new(DirectPort::D, 7, DirectInput::Button(U_B)),
+  DirectPin::new(DirectPort::C, 0, DirectInput::Button(U_C)),
+  DirectPin::new(DirectPort::C, 1, DirectInput::Button(U_D)),
+  DirectPin::new(DirectPort::C, 2, DirectInput::Button(D_A)),
+  DirectPin::new(DirectPort::C, 3, DirectInput::Button(D_B)),
+  DirectPin::new(DirectPort::C, 4, DirectInput::Button(D_C)),
+  DirectPin::new(DirectPort::C, 5, DirectInput::Button(D_D)),
+  DirectPin::new(DirectPort::B, 2, DirectInput::Button(START)),
+];
+
+/// Bounces following an accepted edge on a direct pin are ignored for this
+/// long.
+pub const DEBOUNCE_CONFIG: DebounceConfig = DebounceConfig {
+  lockout: Duration::from_millis(5),
+};
+
+/// Raw state of the stick before any profile is applied.
+pub struct PhysicalInputs {
+  /// Lever axes, right and down positive whichever way the stick is wired.
+  pub x: i8,
+  pub y: i8,
+  pub buttons: PhysicalButtons,
+}
 
-static G_PORTD: Mutex<RefCell<Option<PORTD>>> = Mutex::new(RefCell::new(None));
+static G_PORTD: Resource<PORTD> = Resource::new();
 
-pub fn setup_ports(cs: &CriticalSection, portd: PORTD) {
+pub fn setup_ports(portd: PORTD) {
   portd.ddrd.modify(|_, w| {
     w.pd2()
       .set_bit()
@@ -38,7 +165,7 @@ pub fn setup_ports(cs: &CriticalSection, portd: PORTD) {
       .set_bit()
   });
 
-  G_PORTD.borrow(cs).replace(Some(portd));
+  G_PORTD.init(portd);
 }
 
 fn get_line_group(portd: &PORTD, group: u8) -> [bool; 4] {
@@ -57,68 +184,52 @@ fn get_line_group(portd: &PORTD, group: u8) -> [bool; 4] {
   ]
 }
 
-fn determine_axis(pos: bool, neg: bool) -> i8 {
-  let go_pos = pos && !neg;
-  let go_neg = neg && !pos;
-
-  if go_pos {
-    127
-  } else if go_neg {
-    -127
-  } else {
-    0
+pub fn read_inputs() -> Option<PhysicalInputs> {
+  if INPUT_WIRING == InputWiring::Direct {
+    sample_direct();
+    return Some(direct_inputs());
   }
+
+  G_PORTD.with(|portd| {
+    let group_0 = get_line_group(portd, 0);
+    let group_1 = get_line_group(portd, 1);
+    let group_2 = get_line_group(portd, 2);
+    let group_3 = get_line_group(portd, 3);
+
+    // Lines read low while held
+    let joystick_up = !group_0[2];
+    let joystick_right = !group_3[2];
+    let joystick_down = !group_1[2];
+    let joystick_left = !group_2[2];
+
+    let buttons = PhysicalButtons::NONE
+      .with(U_A, !group_0[1])
+      .with(U_B, !group_2[1])
+      .with(U_C, !group_3[0])
+      .with(U_D, !group_1[0])
+      .with(D_A, !group_1[1])
+      .with(D_B, !group_3[1])
+      .with(D_C, !group_2[0])
+      .with(D_D, !group_0[0])
+      .with(START, !group_0[3]);
+
+    let x = determine_axis(joystick_right, joystick_left);
+    let y = determine_axis(joystick_down, joystick_up);
+
+    PhysicalInputs { x, y, buttons }
+  })
 }
 
-pub fn build_fightstick_data(cs: &CriticalSection) -> Fightstick {
-  let portd = G_PORTD.borrow(cs).borrow();
-
-  if let Some(portd) = portd.as_ref() {
-    let group_0 = get_line_group(&portd, 0);
-    let group_1 = get_line_group(&portd, 1);
-    let group_2 = get_line_group(&portd, 2);
-    let group_3 = get_line_group(&portd, 3);
-
-    let joystick_up = group_0[2];
-    let joystick_right = group_3[2];
-    let joystick_down = group_1[2];
-    let joystick_left = group_2[2];
-
-    let u_a = !group_0[1];
-    let u_b = !group_2[1];
-    let u_c = !group_3[0];
-    let u_d = !group_1[0];
-
-    let d_a = !group_1[1];
-    let d_b = !group_3[1];
-    let d_c = !group_2[0];
-    let d_d = !group_0[0];
-
-    let start = !group_0[3];
-
-    let x = determine_axis(joystick_left, joystick_right);
-    let y = determine_axis(joystick_up, joystick_down);
-
-    Fightstick {
-      x,
-      y,
-      button_0: u_a,
-      button_1: u_b,
-      button_9: d_a,
-      button_3: d_b,
-
-      button_4: u_c,
-      button_2: u_d,
-      button_6: d_c,
-      button_7: d_d,
-
-      button_8: start,
+pub fn build_fightstick_data(inputs: Option<&PhysicalInputs>, profile: &Profile) -> Fightstick {
+  if let Some(inputs) = inputs {
+    let mut fightstick = Fightstick {
+      x: inputs.x,
+      y: inputs.y,
       ..Default::default()
-    }
+    };
+    profile.apply(inputs.buttons, &mut fightstick);
+    fightstick
   } else {
-    Fightstick {
-      button_1: true,
-      ..Default::default()
-    }
+    UNREADABLE_FIGHTSTICK
   }
 }
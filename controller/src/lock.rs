@@ -0,0 +1,32 @@
+use ofs_support::fightstick::Fightstick;
+use ofs_support::input::PhysicalButtons;
+use ofs_support::lock::TournamentLock;
+use ofs_support::resource::Resource;
+
+use crate::fightstick::LOCK_CONFIG;
+use crate::settings::{save_settings, SETTINGS};
+
+static LOCK: Resource<TournamentLock> = Resource::new();
+
+/// Restores the lock state from the loaded settings.
+pub fn setup_lock() {
+  let mut lock = TournamentLock::new(LOCK_CONFIG);
+  lock.set_locked(SETTINGS.with(|settings| settings.locked).unwrap_or(false));
+  LOCK.init(lock);
+}
+
+pub fn allows_configuration() -> bool {
+  LOCK.with(|lock| lock.allows_configuration()).unwrap_or(false)
+}
+
+/// Feeds a scan into the lock combo, persisting the lock whenever it toggles.
+pub fn update_lock(buttons: PhysicalButtons, elapsed_ms: u16) {
+  if let Some(Some(locked)) = LOCK.with(|lock| lock.update(buttons, elapsed_ms)) {
+    SETTINGS.with(|settings| settings.locked = locked);
+    save_settings();
+  }
+}
+
+pub fn apply_lock(fightstick: &mut Fightstick) {
+  LOCK.with(|lock| lock.apply(fightstick));
+}
@@ -8,19 +8,65 @@ extern crate alloc;
 use alloc::vec::Vec;
 use core::cell::RefCell;
 
+use analog::{apply_analog, setup_adc};
+use avr_device::asm::sleep;
 use avr_device::atmega328p::{portb, Peripherals, PORTB, TC1};
-use avr_device::interrupt::Mutex;
+use avr_device::interrupt::{CriticalSection, Mutex};
 use avr_device::{entry, interrupt};
-use fightstick::{build_fightstick_data, setup_ports};
-use ofs_support::fightstick::{FightstickDescriptor, IDLE_FIGHTSTICK};
+use calibration::{is_calibrating, setup_calibration, update_calibration};
+use direct::{direct_inputs, sample_direct, setup_direct};
+use fightstick::{
+  build_fightstick_data, read_inputs, setup_ports, InputWiring, PhysicalInputs, ANALOG_ENABLED, INPUT_WIRING,
+  OUTPUT_MODE, SPINNER_ENABLED,
+};
+use lock::{allows_configuration, apply_lock, setup_lock, update_lock};
+use ofs_support::fightstick::{
+  Fightstick, FightstickDescriptor, IDLE_FIGHTSTICK, STATUS_CALIBRATING, STATUS_OVER_BUDGET, STATUS_REMAPPING,
+};
+use ofs_support::input::PhysicalButtons;
+use ofs_support::queue::ByteQueue;
+use ofs_support::scheduler::{Scheduler, TaskId};
+use ofs_support::timing::{TimerConfig, CPU_HZ, TIMER16_TOP};
 use ofs_support::usart::UsartCommand;
 use panic_halt as _;
+use power::{check_wakeup, is_suspended, resume, setup_power, suspend};
+use remap::{is_remapping, setup_remap, update_remap};
+use settings::{handle_save, load_settings, SETTINGS};
+use spinner::{setup_spinner, take_dial, update_spinner};
 use support::alloc::ALLOCATOR;
+use support::clock::{free, is_over_budget, setup_clock};
+use support::eeprom::setup_eeprom;
 use support::serial::{BAUD_9600, SERIAL};
 
+pub mod analog;
+pub mod calibration;
+pub mod direct;
 pub mod fightstick;
+pub mod lock;
+pub mod power;
+pub mod remap;
+pub mod settings;
+pub mod spinner;
 pub mod support;
 
+/// Time between scans
+const SCAN_PERIOD_MS: u16 = 32;
+const SCAN_TIMER: TimerConfig = TimerConfig::from_period_us(CPU_HZ, SCAN_PERIOD_MS as u32 * 1000, TIMER16_TOP);
+
+const WGM12: u8 = 1 << 3;
+
+/// Tasks of the main loop, highest priority first. Replies to the usb
+/// firmware go out before the report is refreshed, and settings are saved
+/// when nothing else is waiting.
+const PROTOCOL_TASK: TaskId = TaskId(0);
+const INPUT_EDGE_TASK: TaskId = TaskId(1);
+const SCAN_TASK: TaskId = TaskId(2);
+const SAVE_TASK: TaskId = TaskId(3);
+
+static SCHEDULER: Scheduler = Scheduler::new();
+/// Bytes from the usb firmware waiting for `PROTOCOL_TASK`.
+static RECEIVED: Mutex<RefCell<ByteQueue>> = Mutex::new(RefCell::new(ByteQueue::new()));
+
 static G_PORTB: Mutex<RefCell<Option<PORTB>>> = Mutex::new(RefCell::new(None));
 static G_TC1: Mutex<RefCell<Option<TC1>>> = Mutex::new(RefCell::new(None));
 static QUEUE: Mutex<RefCell<Option<Vec<u8>>>> = Mutex::new(RefCell::new(None));
@@ -28,7 +74,7 @@ static FIGHTSTICK: Mutex<RefCell<FightstickDescriptor>> = Mutex::new(RefCell::ne
 
 fn configure_portb(portb: &portb::RegisterBlock) {
   portb.ddrb.modify(|_, w| w.pb5().set_bit());
-  portb.portb.write(|w| w.pb5().clear_bit());
+  portb.portb.modify(|_, w| w.pb5().clear_bit());
 }
 
 fn sei() {
@@ -38,10 +84,14 @@ fn sei() {
 }
 
 fn configure_timer(tc1: &TC1) {
-  tc1.ocr1a.write(|w| unsafe { w.bits(500) });
+  // CTC mode, the counter clears itself on reaching OCR1A
+  tc1.ocr1a.write(|w| unsafe { w.bits(SCAN_TIMER.compare) });
   tc1.tcnt1.write(|w| unsafe { w.bits(0) });
   tc1.timsk1.write(|w| w.ocie1a().set_bit());
-  tc1.tccr1b.write(|w| w.cs1().prescale_1024());
+  tc1.tccr1a.write(|w| unsafe { w.bits(0) });
+  tc1
+    .tccr1b
+    .write(|w| unsafe { w.bits(WGM12 | SCAN_TIMER.prescaler.clock_select()) });
 }
 
 #[entry]
@@ -53,16 +103,40 @@ fn main() -> ! {
 
   interrupt::free(|cs| {
     QUEUE.borrow(cs).replace(Some(Vec::new()));
-    G_PORTB.borrow(cs).replace(Some(peripherals.PORTB));
 
-    // Configure Serial Singleton (USART0)
+    // Configure Serial Singleton (USART0), before PORTD is handed to the
+    // input wiring
     SERIAL
       .borrow(cs)
       .borrow_mut()
       .setup(cs, peripherals.USART0, &peripherals.PORTD);
     SERIAL.borrow(cs).borrow().configure_uart(cs, BAUD_9600);
 
-    setup_ports(cs, peripherals.PORTD);
+    if SPINNER_ENABLED {
+      setup_spinner(cs, &peripherals.EXINT, &peripherals.PORTB);
+    }
+    match INPUT_WIRING {
+      InputWiring::Matrix => setup_ports(peripherals.PORTD),
+      InputWiring::Direct => setup_direct(
+        &peripherals.EXINT,
+        &peripherals.PORTB,
+        peripherals.PORTC,
+        peripherals.PORTD,
+      ),
+    }
+    G_PORTB.borrow(cs).replace(Some(peripherals.PORTB));
+
+    setup_clock(cs, peripherals.TC0);
+    setup_power(cs, peripherals.CPU);
+    setup_eeprom(peripherals.EEPROM);
+    load_settings();
+    setup_lock();
+    setup_remap();
+    setup_calibration();
+
+    if ANALOG_ENABLED {
+      setup_adc(cs, peripherals.ADC);
+    }
 
     configure_timer(&peripherals.TC1);
     G_TC1.borrow(cs).replace(Some(peripherals.TC1));
@@ -81,52 +155,216 @@ fn main() -> ! {
     }
   });
 
-  loop {}
+  loop {
+    let ran = SCHEDULER.run_next(|task| match task {
+      PROTOCOL_TASK => run_protocol(),
+      INPUT_EDGE_TASK => direct_pin_change(),
+      SCAN_TASK => scan(),
+      SAVE_TASK => handle_save(),
+      _ => {},
+    });
+
+    // While the host sleeps, so does the main loop between interrupts. A
+    // task signalled just before sleeping waits for the next scan at worst.
+    if !ran && free(is_suspended) {
+      sleep();
+    }
+  }
 }
 
-#[interrupt(atmega328p)]
-fn TIMER1_COMPA() {
-  interrupt::free(|cs| {
-    let tc1 = G_TC1.borrow(cs).borrow();
-    tc1.as_ref().unwrap().tccr1b.write(|w| w.cs1().no_clock());
+/// Rebuilds the report from a scan. While suspended the scan only looks for a
+/// press to wake the host.
+fn scan() {
+  let inputs = read_inputs();
+  if free(is_suspended) {
+    if let Some(inputs) = inputs {
+      wake_on_press(inputs.buttons);
+    }
+    return;
+  }
 
-    if let Ok(mut fightstick) = FIGHTSTICK.borrow(cs).try_borrow_mut() {
-      *fightstick = build_fightstick_data(cs).into();
+  refresh_fightstick(inputs, SCAN_PERIOD_MS);
+}
+
+fn wake_on_press(buttons: PhysicalButtons) {
+  free(|cs| {
+    if check_wakeup(cs, buttons) {
+      if let Ok(mut serial) = SERIAL.borrow(cs).try_borrow_mut() {
+        serial.write_and_queue(cs, UsartCommand::Wakeup.into());
+      }
+    }
+  });
+}
+
+/// Runs the configuration modes on the latest inputs and rebuilds the report
+/// sent to the usb firmware. Only reading the analog sticks and handing the
+/// report over take interrupts off.
+fn refresh_fightstick(inputs: Option<PhysicalInputs>, elapsed_ms: u16) {
+  let mut remapping = is_remapping();
+  let mut calibrating = is_calibrating();
+  if let Some(inputs) = inputs.as_ref() {
+    if !remapping && !calibrating {
+      update_lock(inputs.buttons, elapsed_ms);
     }
+    if allows_configuration() {
+      if !calibrating {
+        remapping = update_remap(inputs.buttons, elapsed_ms);
+      }
+      if ANALOG_ENABLED && !remapping {
+        calibrating = update_calibration(inputs.buttons, elapsed_ms);
+      }
+    }
+  }
 
-    tc1.as_ref().unwrap().tcnt1.write(|w| unsafe { w.bits(0) });
-    tc1.as_ref().unwrap().tccr1b.write(|w| w.cs1().prescale_1024());
+  if remapping || calibrating {
+    free(|cs| {
+      let portb = G_PORTB.borrow(cs).borrow();
+      portb.as_ref().unwrap().portb.modify(|_, w| w.pb5().set_bit());
+    });
+  }
+
+  let mut fightstick = if remapping || calibrating {
+    // Buttons are configuring the stick, keep them from reaching the host
+    Fightstick {
+      status: if remapping {
+        STATUS_REMAPPING
+      } else {
+        STATUS_CALIBRATING
+      },
+      ..Default::default()
+    }
+  } else {
+    let mut fightstick = SETTINGS
+      .with(|settings| build_fightstick_data(inputs.as_ref(), settings.active_profile()))
+      .unwrap_or_default();
+    if ANALOG_ENABLED {
+      apply_analog(&mut fightstick);
+    }
+    fightstick
+  };
+  apply_lock(&mut fightstick);
+  if free(is_over_budget) {
+    fightstick.status |= STATUS_OVER_BUDGET;
+  }
+
+  let report = fightstick.to_descriptor(OUTPUT_MODE);
+  free(|cs| {
+    if let Ok(mut descriptor) = FIGHTSTICK.borrow(cs).try_borrow_mut() {
+      *descriptor = report;
+    }
   });
 }
 
-#[interrupt(atmega328p)]
-fn USART_RX() {
-  interrupt::free(|cs| {
-    let command: UsartCommand = SERIAL.borrow(cs).borrow().read(cs).into();
-    match command {
-      UsartCommand::Introduction => {
+/// Direct pins changed, rebuild the report straight away instead of waiting
+/// for the next scan.
+fn direct_pin_change() {
+  if INPUT_WIRING == InputWiring::Direct && sample_direct() {
+    if free(is_suspended) {
+      wake_on_press(direct_inputs().buttons);
+    } else {
+      refresh_fightstick(Some(direct_inputs()), 0);
+    }
+  }
+}
+
+/// Answers every command received since the last run.
+fn run_protocol() {
+  while let Some(data) = free(|cs| RECEIVED.borrow(cs).borrow_mut().pop()) {
+    handle_command(data.into());
+  }
+}
+
+fn handle_command(command: UsartCommand) {
+  match command {
+    UsartCommand::Introduction => free(|cs| {
+      let portb = G_PORTB.borrow(cs).borrow();
+      portb.as_ref().unwrap().portb.modify(|_, w| w.pb5().set_bit());
+      if let Ok(mut serial) = SERIAL.borrow(cs).try_borrow_mut() {
+        serial.write_and_queue(cs, UsartCommand::Introduction.into());
+      }
+    }),
+    UsartCommand::SendData => {
+      let configuring = is_remapping() || is_calibrating();
+      free(|cs| send_data(cs, configuring));
+    },
+    UsartCommand::Suspend => {
+      let held = read_inputs().map(|inputs| inputs.buttons).unwrap_or_default();
+      free(|cs| {
+        suspend(cs, held);
         let portb = G_PORTB.borrow(cs).borrow();
-        portb.as_ref().unwrap().portb.write(|w| w.pb5().set_bit());
+        portb.as_ref().unwrap().portb.modify(|_, w| w.pb5().clear_bit());
         if let Ok(mut serial) = SERIAL.borrow(cs).try_borrow_mut() {
-          serial.write_and_queue(cs, UsartCommand::Introduction.into());
+          serial.write_and_queue(cs, UsartCommand::Suspend.into());
         }
-      },
-      UsartCommand::SendData => {
-        if let Ok(mut serial) = SERIAL.borrow(cs).try_borrow_mut() {
-          if let Ok(fightstick) = FIGHTSTICK.borrow(cs).try_borrow() {
-            let portb = G_PORTB.borrow(cs).borrow();
-            portb.as_ref().unwrap().portb.modify(|r, w| w.pb5().bit(!r.pb5().bit()));
-            serial.queue_many(cs, |serial| {
-              serial.write(UsartCommand::SendData.into());
-              serial.write(fightstick.0[0]);
-              serial.write(fightstick.0[1]);
-              serial.write(fightstick.0[2]);
-              serial.write(fightstick.0[3]);
-            });
-          }
+      });
+    },
+    UsartCommand::Resume => free(|cs| {
+      resume(cs);
+      if let Ok(mut serial) = SERIAL.borrow(cs).try_borrow_mut() {
+        serial.write_and_queue(cs, UsartCommand::Resume.into());
+      }
+    }),
+    _ => {}, // noop
+  }
+}
+
+/// Queues the latest report for the usb firmware, blinking the LED unless a
+/// configuration mode holds it on.
+fn send_data(cs: &CriticalSection, configuring: bool) {
+  if let Ok(mut serial) = SERIAL.borrow(cs).try_borrow_mut() {
+    if let Ok(fightstick) = FIGHTSTICK.borrow(cs).try_borrow() {
+      let mut report = fightstick.clone();
+      if SPINNER_ENABLED {
+        // Relative motion is drained once per report, not per scan
+        report.set_dial(take_dial(cs));
+      }
+
+      if !configuring {
+        let portb = G_PORTB.borrow(cs).borrow();
+        portb.as_ref().unwrap().portb.modify(|r, w| w.pb5().bit(!r.pb5().bit()));
+      }
+      serial.queue_many(cs, |serial| {
+        for &data in report.build_send_data_message().iter() {
+          serial.write(data);
         }
-      },
-      _ => {}, // noop
+      });
     }
+  }
+}
+
+// Interrupts only record what happened, the work runs from the main loop
+
+#[interrupt(atmega328p)]
+fn TIMER1_COMPA() {
+  SCHEDULER.signal(SCAN_TASK);
+}
+
+#[interrupt(atmega328p)]
+fn PCINT0() {
+  if SPINNER_ENABLED {
+    // Decoded straight away, as the next edge may come before the main loop
+    interrupt::free(update_spinner);
+  }
+  if INPUT_WIRING == InputWiring::Direct {
+    SCHEDULER.signal(INPUT_EDGE_TASK);
+  }
+}
+
+#[interrupt(atmega328p)]
+fn PCINT1() {
+  SCHEDULER.signal(INPUT_EDGE_TASK);
+}
+
+#[interrupt(atmega328p)]
+fn PCINT2() {
+  SCHEDULER.signal(INPUT_EDGE_TASK);
+}
+
+#[interrupt(atmega328p)]
+fn USART_RX() {
+  interrupt::free(|cs| {
+    let data = SERIAL.borrow(cs).borrow().read(cs);
+    RECEIVED.borrow(cs).borrow_mut().push(data);
   });
+  SCHEDULER.signal(PROTOCOL_TASK);
 }
@@ -0,0 +1,44 @@
+use core::cell::RefCell;
+
+use avr_device::atmega328p::CPU;
+use avr_device::interrupt::{CriticalSection, Mutex};
+use ofs_support::input::PhysicalButtons;
+use ofs_support::power::SuspendState;
+
+/// Sleep enable in `SMCR`. The mode bits stay zero, for idle sleep, which
+/// keeps the timers and pin change interrupts running.
+const SE: u8 = 1 << 0;
+
+static SUSPEND: Mutex<RefCell<SuspendState>> = Mutex::new(RefCell::new(SuspendState::new()));
+static G_CPU: Mutex<RefCell<Option<CPU>>> = Mutex::new(RefCell::new(None));
+
+pub fn setup_power(cs: &CriticalSection, cpu: CPU) {
+  G_CPU.borrow(cs).replace(Some(cpu));
+}
+
+pub fn is_suspended(cs: &CriticalSection) -> bool {
+  SUSPEND.borrow(cs).borrow().is_suspended()
+}
+
+/// Lets the main loop sleep between interrupts until the host resumes.
+pub fn suspend(cs: &CriticalSection, held: PhysicalButtons) {
+  SUSPEND.borrow(cs).borrow_mut().suspend(held);
+  set_sleep_enabled(cs, true);
+}
+
+pub fn resume(cs: &CriticalSection) {
+  SUSPEND.borrow(cs).borrow_mut().resume();
+  set_sleep_enabled(cs, false);
+}
+
+/// Feeds a scan taken while suspended, returning whether to ask the usb
+/// firmware to wake the host.
+pub fn check_wakeup(cs: &CriticalSection, buttons: PhysicalButtons) -> bool {
+  SUSPEND.borrow(cs).borrow_mut().update(buttons)
+}
+
+fn set_sleep_enabled(cs: &CriticalSection, enabled: bool) {
+  let cpu = G_CPU.borrow(cs).borrow();
+  let smcr = if enabled { SE } else { 0 };
+  cpu.as_ref().unwrap().smcr.write(|w| unsafe { w.bits(smcr) });
+}
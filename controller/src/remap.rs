@@ -0,0 +1,32 @@
+use ofs_support::input::PhysicalButtons;
+use ofs_support::remap::{RemapEvent, RemapMachine};
+use ofs_support::resource::Resource;
+
+use crate::fightstick::REMAP_CONFIG;
+use crate::settings::{save_settings, SETTINGS};
+
+static REMAP: Resource<RemapMachine> = Resource::new();
+
+pub fn setup_remap() {
+  REMAP.init(RemapMachine::new(REMAP_CONFIG));
+}
+
+pub fn is_remapping() -> bool {
+  REMAP.with(|remap| remap.is_active()).unwrap_or(false)
+}
+
+/// Feeds a scan into remap mode, saving the active profile whenever a button
+/// is reassigned. Returns whether remap mode owns the buttons.
+pub fn update_remap(buttons: PhysicalButtons, elapsed_ms: u16) -> bool {
+  let event = SETTINGS
+    .with(|settings| REMAP.with(|remap| remap.update(buttons, elapsed_ms, settings.active_profile())))
+    .flatten()
+    .flatten();
+
+  if let Some(RemapEvent::Remapped { physical, logical }) = event {
+    SETTINGS.with(|settings| settings.active_profile_mut().assign(physical, logical));
+    save_settings();
+  }
+
+  is_remapping()
+}
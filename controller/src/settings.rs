@@ -0,0 +1,67 @@
+use ofs_support::resource::Resource;
+use ofs_support::settings::{Settings, SETTINGS_SIZE};
+
+use crate::fightstick::DEFAULT_PROFILE;
+use crate::support::eeprom;
+use crate::{SAVE_TASK, SCHEDULER};
+
+const SETTINGS_ADDRESS: u16 = 0;
+
+pub static SETTINGS: Resource<Settings> = Resource::new();
+static SAVE: Resource<Save> = Resource::new();
+
+/// The settings image being written to EEPROM one byte at a time.
+struct Save {
+  image: [u8; SETTINGS_SIZE],
+  next: usize,
+}
+
+/// Loads settings from EEPROM, keeping the defaults if none were saved.
+pub fn load_settings() {
+  let mut bytes = [0; SETTINGS_SIZE];
+  eeprom::read(SETTINGS_ADDRESS, &mut bytes);
+
+  let settings = Settings::from_bytes(&bytes).unwrap_or_else(|| Settings::new(DEFAULT_PROFILE));
+  SETTINGS.init(settings);
+  SAVE.init(Save {
+    image: bytes,
+    next: SETTINGS_SIZE,
+  });
+}
+
+/// Starts writing the settings to EEPROM, in the background.
+pub fn save_settings() {
+  if let Some(image) = SETTINGS.with(|settings| settings.to_bytes()) {
+    SAVE.with(|save| {
+      save.image = image;
+      save.next = 0;
+    });
+    SCHEDULER.signal(SAVE_TASK);
+  }
+}
+
+/// Writes the next byte of the settings that differs from EEPROM. An EEPROM
+/// write takes a few milliseconds, so the task comes back rather than waiting
+/// on one.
+pub fn handle_save() {
+  let more = SAVE.with(|save| {
+    if eeprom::is_writing() {
+      return true;
+    }
+
+    while save.next < SETTINGS_SIZE {
+      let address = SETTINGS_ADDRESS + save.next as u16;
+      let byte = save.image[save.next];
+      save.next += 1;
+
+      if eeprom::update_byte(address, byte) {
+        return save.next < SETTINGS_SIZE;
+      }
+    }
+    false
+  });
+
+  if more == Some(true) {
+    SCHEDULER.signal(SAVE_TASK);
+  }
+}
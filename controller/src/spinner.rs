@@ -0,0 +1,47 @@
+use core::cell::RefCell;
+
+use avr_device::atmega328p;
+use avr_device::interrupt::{CriticalSection, Mutex};
+use ofs_support::quadrature::QuadratureDecoder;
+
+use crate::fightstick::SPINNER_SENSITIVITY;
+use crate::G_PORTB;
+
+/// Encoder A on PB0 (PCINT0), B on PB1 (PCINT1).
+const SPINNER_PINS: u8 = 0b11;
+const PCIE0: u8 = 1 << 0;
+
+static DECODER: Mutex<RefCell<QuadratureDecoder>> = Mutex::new(RefCell::new(QuadratureDecoder::new()));
+
+/// Pulls up the encoder lines and raises PCINT0 on every edge of either.
+/// Must run before `portb` is handed over to `G_PORTB`.
+pub fn setup_spinner(cs: &CriticalSection, exint: &atmega328p::EXINT, portb: &atmega328p::PORTB) {
+  portb.ddrb.modify(|r, w| unsafe { w.bits(r.bits() & !SPINNER_PINS) });
+  portb.portb.modify(|r, w| unsafe { w.bits(r.bits() | SPINNER_PINS) });
+
+  // Start from the current position so the first edge is not misread
+  let pins = portb.pinb.read();
+  DECODER
+    .borrow(cs)
+    .borrow_mut()
+    .update(pins.pb0().bit(), pins.pb1().bit());
+
+  exint.pcmsk0.modify(|r, w| unsafe { w.bits(r.bits() | SPINNER_PINS) });
+  exint.pcicr.modify(|r, w| unsafe { w.bits(r.bits() | PCIE0) });
+}
+
+/// Drains the spinner motion since the last call into a dial value.
+pub fn take_dial(cs: &CriticalSection) -> i8 {
+  DECODER.borrow(cs).borrow_mut().take_report(SPINNER_SENSITIVITY)
+}
+
+/// Feeds the encoder lines to the decoder, on every PCINT0.
+pub fn update_spinner(cs: &CriticalSection) {
+  if let Some(portb) = G_PORTB.borrow(cs).borrow().as_ref() {
+    let pins = portb.pinb.read();
+    DECODER
+      .borrow(cs)
+      .borrow_mut()
+      .update(pins.pb0().bit(), pins.pb1().bit());
+  }
+}
@@ -0,0 +1,54 @@
+use avr_device::atmega328p::EEPROM;
+use ofs_support::resource::Resource;
+
+use crate::support::clock;
+
+static G_EEPROM: Resource<EEPROM> = Resource::new();
+
+pub fn setup_eeprom(eeprom: EEPROM) {
+  G_EEPROM.init(eeprom);
+}
+
+fn read_byte(eeprom: &EEPROM, address: u16) -> u8 {
+  while eeprom.eecr.read().eepe().bit_is_set() {}
+  eeprom.eear.write(|w| unsafe { w.bits(address) });
+  eeprom.eecr.write(|w| w.eere().set_bit());
+  eeprom.eedr.read().bits()
+}
+
+pub fn read(address: u16, buffer: &mut [u8]) {
+  G_EEPROM.with(|eeprom| {
+    for (offset, byte) in buffer.iter_mut().enumerate() {
+      *byte = read_byte(eeprom, address + offset as u16);
+    }
+  });
+}
+
+/// Whether a write is still in progress. Writes take a few milliseconds.
+pub fn is_writing() -> bool {
+  G_EEPROM
+    .with(|eeprom| eeprom.eecr.read().eepe().bit_is_set())
+    .unwrap_or(false)
+}
+
+/// Starts writing `data` at `address` unless it already holds that value,
+/// to save EEPROM wear. Returns whether a write was started. Reading waits
+/// out a write in progress, so check `is_writing` first not to block.
+pub fn update_byte(address: u16, data: u8) -> bool {
+  G_EEPROM
+    .with(|eeprom| {
+      if read_byte(eeprom, address) == data {
+        return false;
+      }
+
+      eeprom.eear.write(|w| unsafe { w.bits(address) });
+      eeprom.eedr.write(|w| unsafe { w.bits(data) });
+      // EEPE must be set within four cycles of EEMPE
+      clock::free(|_| {
+        eeprom.eecr.write(|w| w.eempe().set_bit());
+        eeprom.eecr.write(|w| w.eempe().set_bit().eepe().set_bit());
+      });
+      true
+    })
+    .unwrap_or(false)
+}
@@ -1,2 +1,4 @@
 pub mod alloc;
+pub mod clock;
+pub mod eeprom;
 pub mod serial;
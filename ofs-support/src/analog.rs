@@ -0,0 +1,219 @@
+//! Hardware-free processing of analog stick readings, from oversampled ADC
+//! values to report axes.
+//!
+//! Readings are first normalised around their calibrated centre to
+//! `-AXIS_MAX..=AXIS_MAX`, then the deadzone and output curve are applied,
+//! and finally the result is narrowed to the `i8` carried in the report.
+
+/// Each averaged reading is built from `4^OVERSAMPLE_BITS` conversions and
+/// gains `OVERSAMPLE_BITS` bits of resolution over the 10 bit ADC.
+pub const OVERSAMPLE_BITS: u8 = 2;
+
+/// Largest value an oversampled reading can take.
+pub const RAW_MAX: u16 = (1 << (10 + OVERSAMPLE_BITS)) - 1;
+
+/// Full deflection of a normalised axis.
+pub const AXIS_MAX: i16 = i16::MAX;
+
+/// Accumulates conversions of one channel into an oversampled reading.
+#[derive(Clone, Copy, Default)]
+pub struct Oversampler {
+  sum: u32,
+  count: u8,
+}
+
+impl Oversampler {
+  pub const fn new() -> Oversampler {
+    Oversampler { sum: 0, count: 0 }
+  }
+
+  /// Adds a 10 bit conversion, returning the reading once enough have been
+  /// collected.
+  pub fn push(&mut self, sample: u16) -> Option<u16> {
+    self.sum += sample as u32;
+    self.count += 1;
+
+    if self.count as u16 >= 1 << (2 * OVERSAMPLE_BITS) {
+      let reading = (self.sum >> OVERSAMPLE_BITS) as u16;
+      *self = Oversampler::new();
+      Some(reading)
+    } else {
+      None
+    }
+  }
+}
+
+/// Raw readings at either end of travel and at rest.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AxisCalibration {
+  pub min: u16,
+  pub center: u16,
+  pub max: u16,
+}
+
+impl AxisCalibration {
+  pub const DEFAULT: AxisCalibration = AxisCalibration {
+    min: 0,
+    center: RAW_MAX / 2,
+    max: RAW_MAX,
+  };
+
+  /// Scales a reading to `-AXIS_MAX..=AXIS_MAX`, each side of the centre
+  /// against its own calibrated range.
+  pub fn normalise(&self, raw: u16) -> i16 {
+    let (offset, range, sign) = if raw >= self.center {
+      (raw - self.center, self.max.saturating_sub(self.center), 1)
+    } else {
+      (self.center - raw, self.center.saturating_sub(self.min), -1)
+    };
+
+    if range == 0 {
+      return 0;
+    }
+
+    let scaled = (offset as i32 * AXIS_MAX as i32 / range as i32).min(AXIS_MAX as i32);
+    (scaled * sign) as i16
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Deadzone {
+  /// Each axis ignores deflection below the percentage on its own.
+  Axial(u8),
+  /// The stick ignores deflection below the percentage in any direction,
+  /// keeping diagonals true to angle.
+  Radial(u8),
+}
+
+impl Deadzone {
+  /// The same kind of deadzone with a different size.
+  pub fn with_percent(self, percent: u8) -> Deadzone {
+    match self {
+      Deadzone::Axial(_) => Deadzone::Axial(percent),
+      Deadzone::Radial(_) => Deadzone::Radial(percent),
+    }
+  }
+
+  fn threshold(percent: u8) -> i32 {
+    percent.min(100) as i32 * AXIS_MAX as i32 / 100
+  }
+
+  /// Zeroes deflection inside the deadzone and rescales the rest so output
+  /// still reaches full deflection.
+  pub fn apply(&self, x: i16, y: i16) -> (i16, i16) {
+    match *self {
+      Deadzone::Axial(percent) => {
+        let threshold = Deadzone::threshold(percent);
+        (rescale_axis(x, threshold), rescale_axis(y, threshold))
+      },
+      Deadzone::Radial(percent) => {
+        let threshold = Deadzone::threshold(percent);
+        let square = |v: i16| (v as i32 * v as i32) as u32;
+        let magnitude = isqrt(square(x) + square(y)) as i32;
+        if magnitude <= threshold || threshold >= AXIS_MAX as i32 {
+          return (0, 0);
+        }
+
+        let outer = magnitude.min(AXIS_MAX as i32);
+        let scaled = (outer - threshold) * AXIS_MAX as i32 / (AXIS_MAX as i32 - threshold);
+        let scale = |v: i16| clamp_axis(v as i32 * scaled / magnitude);
+        (scale(x), scale(y))
+      },
+    }
+  }
+}
+
+fn rescale_axis(value: i16, threshold: i32) -> i16 {
+  let magnitude = (value as i32).abs();
+  if magnitude <= threshold || threshold >= AXIS_MAX as i32 {
+    return 0;
+  }
+
+  let scaled = (magnitude - threshold) * AXIS_MAX as i32 / (AXIS_MAX as i32 - threshold);
+  clamp_axis(scaled * (value as i32).signum())
+}
+
+fn clamp_axis(value: i32) -> i16 {
+  value.max(-(AXIS_MAX as i32)).min(AXIS_MAX as i32) as i16
+}
+
+fn isqrt(value: u32) -> u32 {
+  let mut result = 0;
+  let mut bit = 1 << 30;
+  let mut remainder = value;
+
+  while bit > value {
+    bit >>= 2;
+  }
+
+  while bit != 0 {
+    if remainder >= result + bit {
+      remainder -= result + bit;
+      result = (result >> 1) + bit;
+    } else {
+      result >>= 1;
+    }
+    bit >>= 2;
+  }
+
+  result
+}
+
+/// Shapes deflection after the deadzone, trading precision near the centre
+/// against speed at the edge.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Curve {
+  Linear,
+  Quadratic,
+  Cubic,
+}
+
+impl Curve {
+  pub fn apply(&self, value: i16) -> i16 {
+    let value = value as i32;
+    let max = AXIS_MAX as i32;
+    match self {
+      Curve::Linear => value as i16,
+      Curve::Quadratic => (value * value.abs() / max) as i16,
+      Curve::Cubic => (value * value / max * value / max) as i16,
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StickConfig {
+  pub deadzone: Deadzone,
+  pub curve: Curve,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StickCalibration {
+  pub x: AxisCalibration,
+  pub y: AxisCalibration,
+  /// Deadzone size measured for this stick, replacing the one configured.
+  pub deadzone: Option<u8>,
+}
+
+impl StickCalibration {
+  pub const DEFAULT: StickCalibration = StickCalibration {
+    x: AxisCalibration::DEFAULT,
+    y: AxisCalibration::DEFAULT,
+    deadzone: None,
+  };
+}
+
+/// Turns a pair of oversampled readings into report axes.
+pub fn process_stick(raw: [u16; 2], calibration: &StickCalibration, config: &StickConfig) -> (i8, i8) {
+  let x = calibration.x.normalise(raw[0]);
+  let y = calibration.y.normalise(raw[1]);
+  let deadzone = calibration
+    .deadzone
+    .map_or(config.deadzone, |percent| config.deadzone.with_percent(percent));
+  let (x, y) = deadzone.apply(x, y);
+
+  (to_report(config.curve.apply(x)), to_report(config.curve.apply(y)))
+}
+
+fn to_report(value: i16) -> i8 {
+  (value >> 8) as i8
+}
@@ -0,0 +1,254 @@
+//! Guided analog calibration.
+//!
+//! Holding the combo for [`CalibrationConfig::hold_ms`] with the sticks at
+//! rest samples their centres and noise. Keeping the combo held, each stick is
+//! rotated fully around its gate, and releasing the combo computes the
+//! calibration. Results where an axis never moved, or moved too little to be a
+//! full rotation, are rejected so a bad attempt can not replace a good
+//! calibration.
+
+use crate::analog::{AxisCalibration, StickCalibration, RAW_MAX};
+use crate::input::PhysicalButtons;
+
+/// Two sticks of two axes each, in `[left x, left y, right x, right y]` order.
+pub const CALIBRATED_AXES: usize = 4;
+
+#[derive(Clone, Copy)]
+pub struct CalibrationConfig {
+  /// Buttons that must be held, and nothing else, for calibration to run.
+  pub combo: PhysicalButtons,
+  pub hold_ms: u16,
+  /// Smallest total travel an axis must show, as a fraction of `RAW_MAX` in
+  /// percent.
+  pub min_travel_percent: u8,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CalibrationError {
+  /// The axis stayed within its resting noise.
+  NotMoved { axis: u8 },
+  /// The axis moved, but not far enough to have been rotated fully.
+  RangeTooNarrow { axis: u8 },
+  /// The resting centre sits too close to one end of the travel.
+  CenterOutOfRange { axis: u8 },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CalibrationEvent {
+  Started,
+  /// New calibration for each stick, `None` for a stick that was left alone
+  /// and should keep its previous calibration.
+  Calibrated([Option<StickCalibration>; 2]),
+  Rejected(CalibrationError),
+}
+
+/// Readings of one axis over a calibration attempt.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AxisTrace {
+  rest_sum: u32,
+  rest_count: u16,
+  rest_min: u16,
+  rest_max: u16,
+  min: u16,
+  max: u16,
+}
+
+impl AxisTrace {
+  pub const fn new() -> AxisTrace {
+    AxisTrace {
+      rest_sum: 0,
+      rest_count: 0,
+      rest_min: u16::MAX,
+      rest_max: 0,
+      min: u16::MAX,
+      max: 0,
+    }
+  }
+
+  /// Records a reading taken with the stick left alone.
+  pub fn rest(&mut self, reading: u16) {
+    self.rest_sum += reading as u32;
+    self.rest_count += 1;
+    self.rest_min = self.rest_min.min(reading);
+    self.rest_max = self.rest_max.max(reading);
+    self.sweep(reading);
+  }
+
+  /// Records a reading taken while the stick is being rotated.
+  pub fn sweep(&mut self, reading: u16) {
+    self.min = self.min.min(reading);
+    self.max = self.max.max(reading);
+  }
+
+  /// Computes the calibration of the axis and the deadzone, in percent of
+  /// the shorter side of travel, needed to hide its resting noise.
+  pub fn finish(&self, axis: u8, min_travel_percent: u8) -> Result<(AxisCalibration, u8), CalibrationError> {
+    if self.rest_count == 0 {
+      return Err(CalibrationError::NotMoved { axis });
+    }
+
+    let center = (self.rest_sum / self.rest_count as u32) as u16;
+    let noise = self.rest_max - self.rest_min;
+    let travel = self.max - self.min;
+
+    if travel <= noise.saturating_mul(2) {
+      return Err(CalibrationError::NotMoved { axis });
+    }
+
+    if (travel as u32) * 100 < RAW_MAX as u32 * min_travel_percent as u32 {
+      return Err(CalibrationError::RangeTooNarrow { axis });
+    }
+
+    // Each side needs at least a quarter of the travel to be usable, and
+    // never nothing, however small the travel allowed
+    let margin = (travel / 4).max(1);
+    if center < self.min + margin || center > self.max - margin {
+      return Err(CalibrationError::CenterOutOfRange { axis });
+    }
+
+    let half_travel = (center - self.min).min(self.max - center) as u32;
+    // The whole resting noise band against the shorter side, with a percent
+    // to spare
+    let deadzone = (noise as u32 * 100 / half_travel + 1).min(100) as u8;
+
+    Ok((
+      AxisCalibration {
+        min: self.min,
+        center,
+        max: self.max,
+      },
+      deadzone,
+    ))
+  }
+}
+
+impl Default for AxisTrace {
+  fn default() -> Self {
+    AxisTrace::new()
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CalibrationState {
+  Idle,
+  /// Combo held, sampling the sticks at rest.
+  Arming,
+  /// Combo still held, sampling the sticks being rotated.
+  Sweeping,
+}
+
+pub struct CalibrationMachine {
+  config: CalibrationConfig,
+  state: CalibrationState,
+  held_ms: u16,
+  traces: [AxisTrace; CALIBRATED_AXES],
+}
+
+impl CalibrationMachine {
+  pub const fn new(config: CalibrationConfig) -> CalibrationMachine {
+    CalibrationMachine {
+      config,
+      state: CalibrationState::Idle,
+      held_ms: 0,
+      traces: [AxisTrace::new(); CALIBRATED_AXES],
+    }
+  }
+
+  pub fn state(&self) -> CalibrationState {
+    self.state
+  }
+
+  /// Whether the combo is held for calibration, in which case buttons should
+  /// not be reported to the host.
+  pub fn is_active(&self) -> bool {
+    self.state != CalibrationState::Idle
+  }
+
+  /// Advances the machine with the buttons held and the latest stick readings.
+  pub fn update(
+    &mut self,
+    pressed: PhysicalButtons,
+    readings: [u16; CALIBRATED_AXES],
+    elapsed_ms: u16,
+  ) -> Option<CalibrationEvent> {
+    let held = pressed == self.config.combo;
+
+    match self.state {
+      CalibrationState::Idle => {
+        if held {
+          self.state = CalibrationState::Arming;
+          self.held_ms = 0;
+          self.traces = [AxisTrace::new(); CALIBRATED_AXES];
+          self.record(readings, true);
+        }
+        None
+      },
+      CalibrationState::Arming => {
+        if !held {
+          self.state = CalibrationState::Idle;
+          return None;
+        }
+
+        self.record(readings, true);
+        self.held_ms = self.held_ms.saturating_add(elapsed_ms);
+        if self.held_ms >= self.config.hold_ms {
+          self.state = CalibrationState::Sweeping;
+          Some(CalibrationEvent::Started)
+        } else {
+          None
+        }
+      },
+      CalibrationState::Sweeping => {
+        if held {
+          self.record(readings, false);
+          None
+        } else {
+          self.state = CalibrationState::Idle;
+          Some(self.finish())
+        }
+      },
+    }
+  }
+
+  fn record(&mut self, readings: [u16; CALIBRATED_AXES], at_rest: bool) {
+    for (trace, &reading) in self.traces.iter_mut().zip(readings.iter()) {
+      if at_rest {
+        trace.rest(reading);
+      } else {
+        trace.sweep(reading);
+      }
+    }
+  }
+
+  fn finish(&self) -> CalibrationEvent {
+    let mut sticks = [None; 2];
+    let mut untouched = None;
+
+    for (index, stick) in sticks.iter_mut().enumerate() {
+      let x_axis = (index * 2) as u8;
+      let y_axis = x_axis + 1;
+
+      let x = self.traces[x_axis as usize].finish(x_axis, self.config.min_travel_percent);
+      let y = self.traces[y_axis as usize].finish(y_axis, self.config.min_travel_percent);
+
+      match (x, y) {
+        (Ok((x, x_deadzone)), Ok((y, y_deadzone))) => {
+          *stick = Some(StickCalibration {
+            x,
+            y,
+            deadzone: Some(x_deadzone.max(y_deadzone)),
+          });
+        },
+        (Err(error @ CalibrationError::NotMoved { .. }), Err(CalibrationError::NotMoved { .. })) => {
+          untouched = Some(error);
+        },
+        (Err(error), _) | (_, Err(error)) => return CalibrationEvent::Rejected(error),
+      }
+    }
+
+    match (sticks, untouched) {
+      ([None, None], Some(error)) => CalibrationEvent::Rejected(error),
+      _ => CalibrationEvent::Calibrated(sticks),
+    }
+  }
+}
@@ -0,0 +1,86 @@
+//! The millisecond clock both firmwares keep on TIMER0.
+//!
+//! Each firmware owns the timer registers and calls in here from its compare
+//! interrupt and around its critical sections. The time and the critical
+//! section measurements are kept by [`TickClock`], so the two chips count time
+//! the same way.
+
+use crate::scheduler::{ticks_between, CriticalSectionStats};
+use crate::time::{Clock, Duration, Instant};
+use crate::timing::{TimerConfig, CPU_HZ, TIMER8_TOP};
+
+/// Time between compare interrupts.
+pub const TICK: Duration = Duration::from_millis(1);
+/// TIMER0 in CTC mode, interrupting every [`TICK`].
+pub const TICK_TIMER: TimerConfig = TimerConfig::from_period_us(CPU_HZ, 1000, TIMER8_TOP);
+/// Longest either firmware should hold interrupts off. Half a tick leaves the
+/// compare interrupt time to run before the next match, and is well inside
+/// the two bytes the UART buffers at 9600 baud.
+pub const CRITICAL_SECTION_BUDGET_US: u32 = 500;
+
+/// A reading of TIMER0: its counter, and whether a compare match is pending.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TickPosition {
+  pub count: u8,
+  pub pending: bool,
+}
+
+pub struct TickClock {
+  now: Instant,
+  critical_sections: CriticalSectionStats,
+}
+
+impl TickClock {
+  pub const fn new() -> TickClock {
+    TickClock {
+      now: Instant::ZERO,
+      critical_sections: CriticalSectionStats::new(),
+    }
+  }
+
+  pub fn now(&self) -> Instant {
+    self.now
+  }
+
+  /// Advances the clock by one tick, from the compare interrupt, returning
+  /// the new time.
+  pub fn tick(&mut self) -> Instant {
+    self.now = self.now + TICK;
+    self.now
+  }
+
+  /// Records a critical section from readings of the timer taken as it
+  /// started and ended.
+  pub fn record_section(&mut self, start: TickPosition, end: TickPosition) {
+    let wrapped = end.pending && !start.pending;
+    let ticks = ticks_between(start.count, end.count, wrapped, TICK_TIMER.compare as u8);
+    self.critical_sections.record(ticks);
+  }
+
+  pub fn critical_sections(&self) -> CriticalSectionStats {
+    self.critical_sections
+  }
+
+  /// Longest time interrupts have been held off, in microseconds.
+  pub fn worst_critical_section_us(&self) -> u32 {
+    self.critical_sections.worst_ticks as u32 * TICK_TIMER.tick_ns(CPU_HZ) / 1000
+  }
+
+  /// Whether interrupts have ever been held off past
+  /// [`CRITICAL_SECTION_BUDGET_US`].
+  pub fn is_over_budget(&self) -> bool {
+    self.worst_critical_section_us() > CRITICAL_SECTION_BUDGET_US
+  }
+}
+
+impl Clock for TickClock {
+  fn now(&self) -> Instant {
+    TickClock::now(self)
+  }
+}
+
+impl Default for TickClock {
+  fn default() -> Self {
+    TickClock::new()
+  }
+}
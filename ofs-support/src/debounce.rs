@@ -0,0 +1,64 @@
+//! Edge-triggered debouncing for directly wired inputs.
+//!
+//! The first edge on a settled line is accepted straight away, so a press
+//! reaches the report without waiting for the contact to stop bouncing. The
+//! line is then locked for [`DebounceConfig::lockout`], during which the
+//! bounces that follow are ignored. Sampling again after the lockout picks up
+//! any change that was missed while locked.
+
+use crate::time::{Duration, Instant};
+
+/// Maximum number of input lines a debouncer tracks.
+pub const MAX_LINES: usize = 16;
+
+#[derive(Clone, Copy)]
+pub struct DebounceConfig {
+  pub lockout: Duration,
+}
+
+pub struct Debouncer {
+  config: DebounceConfig,
+  /// Debounced level of each line, set while active.
+  state: u16,
+  /// Lines whose lockout has expired.
+  settled: u16,
+  /// Time of the last accepted edge on each line.
+  changed_at: [Instant; MAX_LINES],
+}
+
+impl Debouncer {
+  pub const fn new(config: DebounceConfig) -> Debouncer {
+    Debouncer {
+      config,
+      state: 0,
+      settled: !0,
+      changed_at: [Instant::ZERO; MAX_LINES],
+    }
+  }
+
+  /// Debounced lines, one bit per line.
+  pub fn state(&self) -> u16 {
+    self.state
+  }
+
+  /// Feeds the raw level of every line as sampled at `now`, returning whether
+  /// the debounced state changed.
+  pub fn sample(&mut self, raw: u16, now: Instant) -> bool {
+    for line in 0..MAX_LINES {
+      if self.changed_at[line].has_elapsed(now, self.config.lockout) {
+        self.settled |= 1 << line;
+      }
+    }
+
+    let accepted = (raw ^ self.state) & self.settled;
+    for line in 0..MAX_LINES {
+      if (accepted >> line) & 1 == 1 {
+        self.changed_at[line] = now;
+      }
+    }
+
+    self.state ^= accepted;
+    self.settled &= !accepted;
+    accepted != 0
+  }
+}
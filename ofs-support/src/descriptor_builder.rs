@@ -0,0 +1,278 @@
+//! Builds USB descriptors at compile time from typed fields.
+//!
+//! A [`DescriptorBuilder`] writes descriptors one after another into a buffer
+//! of `N` bytes. Lengths come from the descriptor types, the configuration's
+//! total length is the buffer size, and the interface and endpoint counts
+//! are patched in as interfaces and endpoints are added. Overrunning the
+//! buffer, or [`DescriptorBuilder::build`] with bytes left over, is an index
+//! out of bounds, which fails the build when evaluated in a `const`.
+
+use crate::endpoint::{Direction, EndpointConfig};
+
+/// Descriptor types, USB 2.0 table 9-5 and HID 1.11 section 7.1.
+pub const DEVICE_TYPE: u8 = 1;
+pub const CONFIGURATION_TYPE: u8 = 2;
+pub const STRING_TYPE: u8 = 3;
+pub const INTERFACE_TYPE: u8 = 4;
+pub const ENDPOINT_TYPE: u8 = 5;
+pub const HID_TYPE: u8 = 0x21;
+pub const HID_REPORT_TYPE: u8 = 0x22;
+
+pub const DEVICE_LENGTH: usize = 18;
+pub const CONFIGURATION_LENGTH: usize = 9;
+pub const INTERFACE_LENGTH: usize = 9;
+/// A HID descriptor listing only the report descriptor.
+pub const HID_LENGTH: usize = 9;
+pub const ENDPOINT_LENGTH: usize = 7;
+
+/// USB 2.0 table 9-8.
+#[derive(Clone, Copy, Debug)]
+pub struct Device {
+  /// BCD, 0x0110 for USB 1.1.
+  pub usb_version: u16,
+  pub class: u8,
+  pub subclass: u8,
+  pub protocol: u8,
+  pub max_packet_size0: u8,
+  pub vendor_id: u16,
+  pub product_id: u16,
+  /// BCD.
+  pub device_version: u16,
+  pub manufacturer: u8,
+  pub product: u8,
+  pub serial_number: u8,
+  pub configurations: u8,
+}
+
+/// USB 2.0 table 9-10. The total length and interface count are filled in
+/// by the builder.
+#[derive(Clone, Copy, Debug)]
+pub struct Configuration {
+  pub value: u8,
+  pub string: u8,
+  pub attributes: u8,
+  pub max_power_ma: u16,
+}
+
+/// USB 2.0 table 9-12. The endpoint count is filled in by the builder.
+#[derive(Clone, Copy, Debug)]
+pub struct Interface {
+  pub number: u8,
+  pub alternate_setting: u8,
+  pub class: u8,
+  pub subclass: u8,
+  pub protocol: u8,
+  pub string: u8,
+}
+
+/// HID 1.11 section 6.2.1, with the one report descriptor.
+#[derive(Clone, Copy, Debug)]
+pub struct Hid {
+  /// BCD, 0x0111 for HID 1.11.
+  pub hid_version: u16,
+  pub country_code: u8,
+  pub report_length: usize,
+}
+
+/// `bmAttributes` of a configuration, bit 7 is always set.
+pub const BUS_POWERED: u8 = 0x80;
+pub const SELF_POWERED: u8 = 0x40;
+pub const REMOTE_WAKEUP: u8 = 0x20;
+
+pub struct DescriptorBuilder<const N: usize> {
+  bytes: [u8; N],
+  length: usize,
+  /// Where the current configuration and interface start, for their counts.
+  configuration: Option<usize>,
+  interface: Option<usize>,
+}
+
+impl<const N: usize> DescriptorBuilder<N> {
+  pub const fn new() -> DescriptorBuilder<N> {
+    DescriptorBuilder {
+      bytes: [0; N],
+      length: 0,
+      configuration: None,
+      interface: None,
+    }
+  }
+
+  const fn byte(mut self, byte: u8) -> DescriptorBuilder<N> {
+    self.bytes[self.length] = byte;
+    self.length += 1;
+    self
+  }
+
+  const fn word(self, word: u16) -> DescriptorBuilder<N> {
+    self.byte((word & 0xFF) as u8).byte((word >> 8) as u8)
+  }
+
+  /// Counts one more item in the byte at `at`.
+  const fn count(mut self, at: Option<usize>) -> DescriptorBuilder<N> {
+    if let Some(at) = at {
+      self.bytes[at] += 1;
+    }
+    self
+  }
+
+  pub const fn device(self, device: &Device) -> DescriptorBuilder<N> {
+    self
+      .byte(DEVICE_LENGTH as u8)
+      .byte(DEVICE_TYPE)
+      .word(device.usb_version)
+      .byte(device.class)
+      .byte(device.subclass)
+      .byte(device.protocol)
+      .byte(device.max_packet_size0)
+      .word(device.vendor_id)
+      .word(device.product_id)
+      .word(device.device_version)
+      .byte(device.manufacturer)
+      .byte(device.product)
+      .byte(device.serial_number)
+      .byte(device.configurations)
+  }
+
+  /// Starts a configuration taking up the rest of the buffer.
+  pub const fn configuration(mut self, configuration: &Configuration) -> DescriptorBuilder<N> {
+    let total = N - self.length;
+    self.configuration = Some(self.length + 4);
+    self
+      .byte(CONFIGURATION_LENGTH as u8)
+      .byte(CONFIGURATION_TYPE)
+      .word(total as u16)
+      .byte(0)
+      .byte(configuration.value)
+      .byte(configuration.string)
+      .byte(configuration.attributes | BUS_POWERED)
+      .byte((configuration.max_power_ma / 2) as u8)
+  }
+
+  pub const fn interface(mut self, interface: &Interface) -> DescriptorBuilder<N> {
+    let configuration = self.configuration;
+    self.interface = Some(self.length + 4);
+    self
+      .count(configuration)
+      .byte(INTERFACE_LENGTH as u8)
+      .byte(INTERFACE_TYPE)
+      .byte(interface.number)
+      .byte(interface.alternate_setting)
+      .byte(0)
+      .byte(interface.class)
+      .byte(interface.subclass)
+      .byte(interface.protocol)
+      .byte(interface.string)
+  }
+
+  pub const fn hid(self, hid: &Hid) -> DescriptorBuilder<N> {
+    self
+      .byte(HID_LENGTH as u8)
+      .byte(HID_TYPE)
+      .word(hid.hid_version)
+      .byte(hid.country_code)
+      .byte(1)
+      .byte(HID_REPORT_TYPE)
+      .word(hid.report_length as u16)
+  }
+
+  /// An endpoint of the current interface, as configured in `config`, polled
+  /// every `interval` frames.
+  pub const fn endpoint(self, config: &EndpointConfig, interval: u8) -> DescriptorBuilder<N> {
+    let direction = match config.direction {
+      Direction::In => 0x80,
+      Direction::Out => 0,
+    };
+    let interface = self.interface;
+    self
+      .count(interface)
+      .byte(ENDPOINT_LENGTH as u8)
+      .byte(ENDPOINT_TYPE)
+      .byte(config.number | direction)
+      .byte(config.kind as u8)
+      .word(config.size as u16)
+      .byte(interval)
+  }
+
+  /// The finished descriptor, which must fill the buffer exactly.
+  pub const fn build(self) -> [u8; N] {
+    // Out of bounds, failing the build, if bytes are left over
+    [self.bytes][(self.length != N) as usize]
+  }
+}
+
+/// Decodes the UTF-8 character starting at `at`, returning it and the index
+/// of the next one. `&str` is valid UTF-8, so only the lead byte is checked.
+const fn decode_utf8(bytes: &[u8], at: usize) -> (u32, usize) {
+  let lead = bytes[at] as u32;
+  let (mut code, length) = if lead < 0x80 {
+    (lead, 1)
+  } else if lead < 0xE0 {
+    (lead & 0x1F, 2)
+  } else if lead < 0xF0 {
+    (lead & 0x0F, 3)
+  } else {
+    (lead & 0x07, 4)
+  };
+
+  let mut i = 1;
+  while i < length {
+    code = (code << 6) | (bytes[at + i] as u32 & 0x3F);
+    i += 1;
+  }
+  (code, at + length)
+}
+
+/// Length of the string descriptor for `string`, two bytes of header and
+/// then two bytes per UTF-16 code unit.
+pub const fn string_descriptor_length(string: &str) -> usize {
+  let bytes = string.as_bytes();
+  let mut length = 2;
+  let mut at = 0;
+  while at < bytes.len() {
+    let (code, next) = decode_utf8(bytes, at);
+    // Beyond the basic plane takes a surrogate pair
+    length += if code > 0xFFFF { 4 } else { 2 };
+    at = next;
+  }
+  length
+}
+
+/// The string descriptor for `string`, UTF-16LE, USB 2.0 section 9.6.7.
+/// `N` must be [`string_descriptor_length`] of `string`, and at most 255 for
+/// `bLength`, which fails the build otherwise when evaluated in a `const`.
+pub const fn string_descriptor<const N: usize>(string: &str) -> [u8; N] {
+  let bytes = string.as_bytes();
+  let mut descriptor = [0; N];
+  descriptor[0] = N as u8;
+  descriptor[1] = STRING_TYPE;
+
+  let mut length = 2;
+  let mut at = 0;
+  while at < bytes.len() {
+    let (code, next) = decode_utf8(bytes, at);
+    if code > 0xFFFF {
+      let code = code - 0x10000;
+      let high = 0xD800 | (code >> 10);
+      let low = 0xDC00 | (code & 0x3FF);
+      descriptor[length] = high as u8;
+      descriptor[length + 1] = (high >> 8) as u8;
+      descriptor[length + 2] = low as u8;
+      descriptor[length + 3] = (low >> 8) as u8;
+      length += 4;
+    } else {
+      descriptor[length] = code as u8;
+      descriptor[length + 1] = (code >> 8) as u8;
+      length += 2;
+    }
+    at = next;
+  }
+
+  // Out of bounds, failing the build, if `N` is too long
+  [descriptor][(length != N || N > 0xFF) as usize]
+}
+
+impl<const N: usize> Default for DescriptorBuilder<N> {
+  fn default() -> Self {
+    DescriptorBuilder::new()
+  }
+}
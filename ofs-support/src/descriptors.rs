@@ -0,0 +1,145 @@
+use crate::descriptor_builder::{string_descriptor, string_descriptor_length};
+use crate::descriptor_builder::{
+  Configuration, DescriptorBuilder, Device, Hid, Interface, CONFIGURATION_LENGTH, DEVICE_LENGTH, ENDPOINT_LENGTH,
+  HID_LENGTH, INTERFACE_LENGTH, REMOTE_WAKEUP,
+};
+use crate::endpoint::{dpram_used, Banks, Direction, EndpointConfig, EndpointType, DPRAM_SIZE};
+use crate::fightstick::{FIGHTSTICK_DESCRIPTOR_SIZE, REPORT_LAYOUT};
+use crate::report_descriptor::parse;
+use crate::report_layout::report_descriptor;
+
+/// Strings can be set per build through these environment variables, for
+/// example `OFS_PRODUCT="Cabinet Stick" cargo build`.
+const MANUFACTURER_NAME: &str = match option_env!("OFS_MANUFACTURER") {
+  Some(name) => name,
+  None => "OFS",
+};
+const PRODUCT_NAME: &str = match option_env!("OFS_PRODUCT") {
+  Some(name) => name,
+  None => "Open Fight Stick v2a",
+};
+
+pub const MANUFACTURER_STRING: u8 = 1;
+pub const PRODUCT_STRING: u8 = 2;
+/// Unique to each stick, answered from [`crate::serial::SerialNumber`].
+pub const SERIAL_NUMBER_STRING: u8 = 3;
+
+pub const LANGUAGES: [u8; 4] = [4, 3, 0x09, 0x04]; // English (United States)
+pub const MANUFACTURER: [u8; string_descriptor_length(MANUFACTURER_NAME)] = string_descriptor(MANUFACTURER_NAME);
+pub const PRODUCT: [u8; string_descriptor_length(PRODUCT_NAME)] = string_descriptor(PRODUCT_NAME);
+
+pub const VENDOR_ID: u16 = 0x10C4;
+pub const PRODUCT_ID: u16 = 0x82C0;
+
+pub const ENDPOINT0_SIZE: u8 = 64;
+
+pub const GAMEPAD_INTERFACE: u8 = 0;
+pub const GAMEPAD_ENDPOINT: u8 = 1;
+/// Room for the 8 byte report and then some, small enough for two banks to
+/// fit in DPRAM next to endpoint 0.
+pub const GAMEPAD_SIZE: u8 = 32;
+
+pub const ENDPOINT0_CONFIG: EndpointConfig =
+  EndpointConfig::new(0, EndpointType::Control, Direction::Out, ENDPOINT0_SIZE, Banks::Single);
+
+/// Double banked, so a report can be written while the last is in flight.
+pub const GAMEPAD_CONFIG: EndpointConfig = EndpointConfig::new(
+  GAMEPAD_ENDPOINT,
+  EndpointType::Interrupt,
+  Direction::In,
+  GAMEPAD_SIZE,
+  Banks::Double,
+);
+
+pub const DEVICE_DESCRIPTOR: [u8; DEVICE_LENGTH] = DescriptorBuilder::new()
+  .device(&Device {
+    usb_version: 0x0110,
+    class: 0,
+    subclass: 0,
+    protocol: 0,
+    max_packet_size0: ENDPOINT0_SIZE,
+    vendor_id: VENDOR_ID,
+    product_id: PRODUCT_ID,
+    device_version: 0x0100,
+    manufacturer: MANUFACTURER_STRING,
+    product: PRODUCT_STRING,
+    serial_number: SERIAL_NUMBER_STRING,
+    configurations: 1,
+  })
+  .build();
+
+pub const HID_REPORT_DESC_SIZE: usize = report_descriptor::<0>(&REPORT_LAYOUT).len();
+pub const HID_REPORT_DESC: [u8; HID_REPORT_DESC_SIZE] = report_descriptor(&REPORT_LAYOUT).build();
+
+// Fails to build, with a length mismatch, if the report descriptor declares a
+// different input report than the one sent
+const _: [(); 0] = [(); (input_report_size(&HID_REPORT_DESC) != FIGHTSTICK_DESCRIPTOR_SIZE) as usize];
+
+/// Bytes in the input report `descriptor` declares, 0 if it does not parse.
+const fn input_report_size(descriptor: &[u8]) -> usize {
+  match parse(descriptor) {
+    Ok(reports) => reports.input_bytes(),
+    Err(_) => 0,
+  }
+}
+
+const GAMEPAD_HID: Hid = Hid {
+  hid_version: 0x0111,
+  country_code: 0,
+  report_length: HID_REPORT_DESC_SIZE,
+};
+
+pub const CONFIG1_DESC_SIZE: usize = CONFIGURATION_LENGTH + INTERFACE_LENGTH + HID_LENGTH + ENDPOINT_LENGTH;
+pub const CONFIG1_DESC: [u8; CONFIG1_DESC_SIZE] = DescriptorBuilder::new()
+  .configuration(&Configuration {
+    value: 1,
+    string: 0,
+    attributes: REMOTE_WAKEUP,
+    max_power_ma: 100,
+  })
+  .interface(&Interface {
+    number: GAMEPAD_INTERFACE,
+    alternate_setting: 0,
+    class: 0x03, // HID
+    subclass: 0, // No boot
+    protocol: 0,
+    string: 0,
+  })
+  .hid(&GAMEPAD_HID)
+  .endpoint(&GAMEPAD_CONFIG, 10)
+  .build();
+
+/// The HID descriptor on its own, for GET_DESCRIPTOR on the interface.
+pub const HID: [u8; HID_LENGTH] = DescriptorBuilder::new().hid(&GAMEPAD_HID).build();
+
+// Note: this is for possible compatibility with PS3s (untested)
+// https://github.com/AlanChatham/UnoJoy/blob/496f0083a295b85fb4e3598378461c8e1d825e40/UnoJoy/ATmega8u2Code/usb_gamepad.c#L297
+pub static INIT_BYTES: [u8; 8] = [0x21, 0x26, 0x01, 0x07, 0x00, 0x00, 0x00, 0x00];
+
+pub struct Descriptor {
+  pub value: u16,
+  pub index: u16,
+  pub data: &'static [u8],
+}
+
+impl Descriptor {
+  pub const fn new(value: u16, index: u16, data: &'static [u8]) -> Descriptor {
+    Descriptor { value, index, data }
+  }
+}
+
+/// Endpoints set up by SET_CONFIGURATION.
+pub const ENDPOINT_TABLE: [EndpointConfig; 1] = [GAMEPAD_CONFIG];
+
+// Fails to build, with a length mismatch, if the endpoints overflow DPRAM
+const _: [(); 0] = [(); (dpram_used(&ENDPOINT0_CONFIG, &ENDPOINT_TABLE) > DPRAM_SIZE) as usize];
+
+pub static DESCRIPTOR_LIST: [Descriptor; 7] = [
+  Descriptor::new(0x0100, 0x0000, &DEVICE_DESCRIPTOR),
+  Descriptor::new(0x0200, 0x0000, &CONFIG1_DESC),
+  Descriptor::new(0x2100, GAMEPAD_INTERFACE as u16, &HID),
+  Descriptor::new(0x2200, GAMEPAD_INTERFACE as u16, &HID_REPORT_DESC),
+  Descriptor::new(0x0300, 0x0000, &LANGUAGES),
+  Descriptor::new(0x0300 | MANUFACTURER_STRING as u16, 0x0409, &MANUFACTURER),
+  Descriptor::new(0x0300 | PRODUCT_STRING as u16, 0x0409, &PRODUCT),
+];
@@ -0,0 +1,112 @@
+//! Endpoint configurations, as written to `UECFG0X` and `UECFG1X`.
+//!
+//! Every endpoint's banks come out of the controller's 176 bytes of DPRAM,
+//! so [`dpram_used`] lets a table of configurations be checked against
+//! [`DPRAM_SIZE`] at compile time.
+
+/// Bytes of endpoint memory on the atmega8u2.
+pub const DPRAM_SIZE: u16 = 176;
+
+/// `UECFG1X`, allocates the endpoint's memory.
+const ALLOC: u8 = 1 << 1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum EndpointType {
+  Control = 0,
+  Isochronous = 1,
+  Bulk = 2,
+  Interrupt = 3,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum Direction {
+  Out = 0,
+  In = 1,
+}
+
+/// Banks of the endpoint's FIFO. With two, the firmware fills one bank
+/// while the host reads the other.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum Banks {
+  Single = 0,
+  Double = 1,
+}
+
+impl Banks {
+  pub const fn count(self) -> u8 {
+    self as u8 + 1
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EndpointConfig {
+  pub number: u8,
+  pub kind: EndpointType,
+  pub direction: Direction,
+  /// Bank size in bytes, a power of two from 8 to 64.
+  pub size: u8,
+  pub banks: Banks,
+}
+
+impl EndpointConfig {
+  pub const fn new(number: u8, kind: EndpointType, direction: Direction, size: u8, banks: Banks) -> EndpointConfig {
+    EndpointConfig {
+      number,
+      kind,
+      direction,
+      size,
+      banks,
+    }
+  }
+
+  /// Type and direction, `UECFG0X`.
+  pub const fn config0(&self) -> u8 {
+    ((self.kind as u8) << 6) | self.direction as u8
+  }
+
+  /// Size, banks and allocation, `UECFG1X`.
+  pub const fn config1(&self) -> u8 {
+    let size = match self.size {
+      8 => 0,
+      16 => 1,
+      32 => 2,
+      _ => 3,
+    };
+    (size << 4) | ((self.banks as u8) << 2) | ALLOC
+  }
+
+  /// Bytes of DPRAM the endpoint takes.
+  pub const fn memory(&self) -> u16 {
+    self.size as u16 * self.banks.count() as u16
+  }
+
+  /// The endpoint's bit in masks such as `UERST`.
+  pub const fn mask(&self) -> u8 {
+    1 << self.number
+  }
+}
+
+/// DPRAM taken by `endpoint0` and `endpoints` together.
+pub const fn dpram_used(endpoint0: &EndpointConfig, endpoints: &[EndpointConfig]) -> u16 {
+  let mut used = endpoint0.memory();
+  let mut i = 0;
+  while i < endpoints.len() {
+    used += endpoints[i].memory();
+    i += 1;
+  }
+  used
+}
+
+/// Endpoints in `endpoints`, one bit each.
+pub const fn endpoint_mask(endpoints: &[EndpointConfig]) -> u8 {
+  let mut mask = 0;
+  let mut i = 0;
+  while i < endpoints.len() {
+    mask |= endpoints[i].mask();
+    i += 1;
+  }
+  mask
+}
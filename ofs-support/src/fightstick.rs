@@ -1,105 +1,332 @@
+use crate::report_descriptor::{DIAL, RZ, X, Y, Z};
+use crate::report_layout::{self, field_offset, pack, report_size, Field, FieldKind};
 use crate::usart::UsartCommand;
 
-pub struct FightstickDescriptor(pub [u8; 4]);
+/// The fields of a report, in order. Each reads its value from a
+/// [`Fightstick`], and the HID report descriptor is generated from them.
+pub const REPORT_LAYOUT: [Field; 7] = [
+  Field {
+    kind: FieldKind::Axis(X),
+    value: |fightstick, _| fightstick.x as u8 as u32,
+  },
+  Field {
+    kind: FieldKind::Axis(Y),
+    value: |fightstick, _| fightstick.y as u8 as u32,
+  },
+  Field {
+    kind: FieldKind::Axis(Z),
+    value: |fightstick, _| fightstick.rx as u8 as u32,
+  },
+  Field {
+    kind: FieldKind::Axis(RZ),
+    value: |fightstick, _| fightstick.ry as u8 as u32,
+  },
+  Field {
+    kind: FieldKind::Relative(DIAL),
+    value: |fightstick, _| fightstick.dial as u8 as u32,
+  },
+  Field {
+    kind: FieldKind::Buttons(BUTTON_COUNT),
+    value: |fightstick, mode| fightstick.report_buttons(mode) as u32,
+  },
+  Field {
+    kind: FieldKind::Vendor(1),
+    value: |fightstick, _| fightstick.status as u32,
+  },
+];
+
+/// Number of bytes in a fightstick report, both over UART and USB.
+pub const FIGHTSTICK_DESCRIPTOR_SIZE: usize = report_size(&REPORT_LAYOUT);
+
+/// Status bit set while tournament lock is engaged.
+pub const STATUS_LOCKED: u8 = 1 << 0;
+/// Status bit set while buttons are being remapped.
+pub const STATUS_REMAPPING: u8 = 1 << 1;
+/// Status bit set while analog sticks are being calibrated.
+pub const STATUS_CALIBRATING: u8 = 1 << 2;
+/// Status bit set once either chip has held interrupts off for longer than
+/// [`crate::clock::CRITICAL_SECTION_BUDGET_US`].
+pub const STATUS_OVER_BUDGET: u8 = 1 << 3;
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct FightstickDescriptor(pub [u8; FIGHTSTICK_DESCRIPTOR_SIZE]);
 
 impl FightstickDescriptor {
-  pub fn build_send_data_message(&self) -> [u8; 5] {
-    [
-      UsartCommand::SendData.into(),
-      self.0[0],
-      self.0[1],
-      self.0[2],
-      self.0[3],
-    ]
+  pub fn build_send_data_message(&self) -> [u8; FIGHTSTICK_DESCRIPTOR_SIZE + 1] {
+    let mut message = [0; FIGHTSTICK_DESCRIPTOR_SIZE + 1];
+    message[0] = UsartCommand::SendData.into();
+    message[1..].copy_from_slice(&self.0);
+    message
+  }
+
+  /// Replaces the relative dial motion carried by the report.
+  pub fn set_dial(&mut self, dial: i8) {
+    self.0[DIAL_INDEX] = dial as u8;
+  }
+
+  /// Sets `STATUS_*` bits in the report, on top of those already set.
+  pub fn add_status(&mut self, status: u8) {
+    self.0[STATUS_INDEX] |= status;
+  }
+
+  /// Zeroes relative fields once the report has been sent, so the same motion
+  /// is not reported twice.
+  pub fn clear_relative(&mut self) {
+    report_layout::clear_relative(&REPORT_LAYOUT, &mut self.0);
+  }
+
+  /// Whether the report carries relative motion, which is lost unless it is
+  /// sent.
+  pub fn has_relative(&self) -> bool {
+    report_layout::has_relative(&REPORT_LAYOUT, &self.0)
   }
 }
 
-impl Default for FightstickDescriptor {
-  fn default() -> Self {
-    FightstickDescriptor([0, 0, 0, 0])
+/// Position of the relative dial in a report.
+const DIAL_INDEX: usize = field_offset(&REPORT_LAYOUT, FieldKind::Relative(DIAL));
+/// Position of the vendor status byte in a report.
+const STATUS_INDEX: usize = field_offset(&REPORT_LAYOUT, FieldKind::Vendor(1));
+
+/// Number of buttons carried in every report.
+pub const BUTTON_COUNT: u8 = 16;
+
+/// What a button means to the player, independent of where it sits in the
+/// report of any one output mode.
+///
+/// Punches and kicks follow the usual arcade stick layout: the light and
+/// medium attacks are the four face buttons, heavy punch and heavy kick sit on
+/// R1 and R2, leaving L1 and L2 as the extra two buttons.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum Button {
+  LightPunch,
+  MediumPunch,
+  HeavyPunch,
+  LightKick,
+  MediumKick,
+  HeavyKick,
+  L1,
+  L2,
+  L3,
+  R3,
+  Select,
+  Start,
+  Home,
+  Capture,
+}
+
+impl Button {
+  pub const ALL: [Button; 14] = [
+    Button::LightPunch,
+    Button::MediumPunch,
+    Button::HeavyPunch,
+    Button::LightKick,
+    Button::MediumKick,
+    Button::HeavyKick,
+    Button::L1,
+    Button::L2,
+    Button::L3,
+    Button::R3,
+    Button::Select,
+    Button::Start,
+    Button::Home,
+    Button::Capture,
+  ];
+
+  pub fn from_u8(value: u8) -> Option<Button> {
+    Button::ALL.get(value as usize).copied()
+  }
+
+  /// Bit of this button in [`Fightstick::buttons`].
+  pub const fn bit(self) -> u16 {
+    1 << self as u8
+  }
+
+  /// Combines buttons into a mask in the layout of [`Fightstick::buttons`].
+  pub const fn mask(buttons: &[Button]) -> u16 {
+    let mut mask = 0;
+    let mut i = 0;
+    while i < buttons.len() {
+      mask |= buttons[i].bit();
+      i += 1;
+    }
+    mask
   }
 }
 
-impl Clone for FightstickDescriptor {
-  fn clone(&self) -> FightstickDescriptor {
-    FightstickDescriptor(self.0.clone())
+/// How buttons are presented to the host.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OutputMode {
+  /// Generic PC gamepad, numbered as sticks have always reported their
+  /// buttons so existing bindings keep working.
+  Pc,
+  /// PS3 pads (Square, Cross, Circle, Triangle, L1, R1, ...).
+  Ps3,
+  /// Switch HORIPAD layout (Y, B, A, X, L, R, ...).
+  Switch,
+  /// Keyboard encoder, `usage` gives Keyboard/Keypad page usage IDs rather
+  /// than button indices.
+  Keyboard,
+}
+
+const NONE: u8 = 0xFF;
+
+// Indexed by `Button as u8`.
+const PC_TABLE: [u8; 14] = [
+  0,  // LightPunch
+  1,  // MediumPunch
+  4,  // HeavyPunch
+  9,  // LightKick
+  3,  // MediumKick
+  6,  // HeavyKick
+  2,  // L1
+  7,  // L2
+  10, // L3
+  11, // R3
+  5,  // Select
+  8,  // Start
+  12, // Home
+  13, // Capture
+];
+
+const PS3_TABLE: [u8; 14] = [
+  0,  // LightPunch: Square
+  3,  // MediumPunch: Triangle
+  5,  // HeavyPunch: R1
+  1,  // LightKick: Cross
+  2,  // MediumKick: Circle
+  7,  // HeavyKick: R2
+  4,  // L1
+  6,  // L2
+  10, // L3
+  11, // R3
+  8,  // Select
+  9,  // Start
+  12, // Home: PS
+  13, // Capture
+];
+
+const SWITCH_TABLE: [u8; 14] = [
+  0,  // LightPunch: Y
+  3,  // MediumPunch: X
+  5,  // HeavyPunch: R
+  1,  // LightKick: B
+  2,  // MediumKick: A
+  7,  // HeavyKick: ZR
+  4,  // L1: L
+  6,  // L2: ZL
+  10, // L3: Left Stick
+  11, // R3: Right Stick
+  8,  // Select: Minus
+  9,  // Start: Plus
+  12, // Home
+  13, // Capture
+];
+
+// MAME default player one keys
+const KEYBOARD_TABLE: [u8; 14] = [
+  0xE0, // LightPunch: Left Control
+  0xE2, // MediumPunch: Left Alt
+  0x2C, // HeavyPunch: Space
+  0xE1, // LightKick: Left Shift
+  0x1D, // MediumKick: Z
+  0x1B, // HeavyKick: X
+  0x06, // L1: C
+  0x19, // L2: V
+  NONE, // L3
+  NONE, // R3
+  0x22, // Select: 5 (Coin)
+  0x1E, // Start: 1
+  0x29, // Home: Escape
+  NONE, // Capture
+];
+
+impl OutputMode {
+  /// The HID usage `button` is reported as, a zero based button index for
+  /// gamepad modes or a keyboard usage ID for `Keyboard`.
+  pub fn usage(self, button: Button) -> Option<u8> {
+    let table = match self {
+      OutputMode::Pc => &PC_TABLE,
+      OutputMode::Ps3 => &PS3_TABLE,
+      OutputMode::Switch => &SWITCH_TABLE,
+      OutputMode::Keyboard => &KEYBOARD_TABLE,
+    };
+
+    match table[button as usize] {
+      NONE => None,
+      usage => Some(usage),
+    }
   }
 }
 
+#[derive(Clone, Copy, Default)]
 pub struct Fightstick {
   pub x: i8,
   pub y: i8,
+  /// Second stick, reported as Z and Rz.
+  pub rx: i8,
+  pub ry: i8,
+  /// Spinner motion since the previous report, relative.
+  pub dial: i8,
 
-  pub button_0: bool,
-  pub button_1: bool,
-  pub button_2: bool,
-  pub button_3: bool,
-  pub button_4: bool,
-  pub button_5: bool,
-  pub button_6: bool,
-  pub button_7: bool,
-  pub button_8: bool,
-  pub button_9: bool,
-  pub button_10: bool,
-}
+  /// Held buttons, one bit per [`Button`].
+  pub buttons: u16,
 
-pub const IDLE_FIGHTSTICK: FightstickDescriptor = FightstickDescriptor([127, 127, 0, 0]);
-
-impl Default for Fightstick {
-  fn default() -> Self {
-    Self {
-      x: 0,
-      y: 0,
-      button_0: false,
-      button_1: false,
-      button_2: false,
-      button_3: false,
-      button_4: false,
-      button_5: false,
-      button_6: false,
-      button_7: false,
-      button_8: false,
-      button_9: false,
-      button_10: false,
-    }
-  }
+  /// Vendor status flags, `STATUS_*`.
+  pub status: u8,
 }
 
-#[inline(always)]
-fn left_shift_bit(val: bool, index: u8) -> u8 {
-  (val as u8) << index
-}
+/// Reported when the controller can not read its inputs. It holds PC button 1,
+/// as sticks always have, so the fault shows up on the host.
+pub const UNREADABLE_FIGHTSTICK: Fightstick = Fightstick {
+  x: 0,
+  y: 0,
+  rx: 0,
+  ry: 0,
+  dial: 0,
+  buttons: Button::MediumPunch.bit(),
+  status: 0,
+};
+
+pub const IDLE_FIGHTSTICK: FightstickDescriptor = FightstickDescriptor([0; FIGHTSTICK_DESCRIPTOR_SIZE]);
 
-impl Into<FightstickDescriptor> for Fightstick {
-  fn into(self) -> FightstickDescriptor {
-    FightstickDescriptor([
-      self.get_descriptor_index(0).unwrap(),
-      self.get_descriptor_index(1).unwrap(),
-      self.get_descriptor_index(2).unwrap(),
-      self.get_descriptor_index(3).unwrap(),
-    ])
+impl From<Fightstick> for FightstickDescriptor {
+  fn from(fightstick: Fightstick) -> Self {
+    fightstick.to_descriptor(OutputMode::Pc)
   }
 }
 
 impl Fightstick {
-  pub fn get_descriptor_index(&self, index: u8) -> Option<u8> {
-    match index {
-      0 => Some((self.x + 127) as u8),
-      1 => Some((self.y + 127) as u8),
-      2 => Some(
-        left_shift_bit(self.button_0, 0)
-          | left_shift_bit(self.button_1, 1)
-          | left_shift_bit(self.button_2, 2)
-          | left_shift_bit(self.button_3, 3)
-          | left_shift_bit(self.button_4, 4)
-          | left_shift_bit(self.button_5, 5)
-          | left_shift_bit(self.button_6, 6)
-          | left_shift_bit(self.button_7, 7),
-      ),
-      3 => {
-        Some(left_shift_bit(self.button_8, 0) | left_shift_bit(self.button_9, 1) | left_shift_bit(self.button_10, 2))
-      },
-      _ => None,
+  pub fn is_pressed(&self, button: Button) -> bool {
+    self.buttons & button.bit() != 0
+  }
+
+  pub fn set_button(&mut self, button: Button, pressed: bool) {
+    if pressed {
+      self.buttons |= button.bit();
+    } else {
+      self.buttons &= !button.bit();
+    }
+  }
+
+  /// Lays the held buttons out as the report button field of `mode`. Keyboard
+  /// mode has no gamepad buttons, its keys come from [`OutputMode::usage`].
+  pub fn report_buttons(&self, mode: OutputMode) -> u16 {
+    if mode == OutputMode::Keyboard {
+      return 0;
     }
+
+    Button::ALL
+      .iter()
+      .filter(|&&button| self.is_pressed(button))
+      .filter_map(|&button| mode.usage(button))
+      .filter(|&index| index < BUTTON_COUNT)
+      .fold(0, |buttons, index| buttons | (1 << index))
+  }
+
+  pub fn to_descriptor(&self, mode: OutputMode) -> FightstickDescriptor {
+    let mut descriptor = IDLE_FIGHTSTICK;
+    pack(&REPORT_LAYOUT, self, mode, &mut descriptor.0);
+    descriptor
   }
 }
@@ -0,0 +1,123 @@
+//! Traits over the peripherals the usb firmware drives.
+//!
+//! The firmware implements them by wrapping `avr_device` registers, and
+//! `mock` (behind the `mock` feature) implements them on the host, recording
+//! every access, so the protocol logic can be exercised off-target. The
+//! methods map one to one onto register accesses, so they take `&mut self`
+//! even to read, as some reads (the endpoint FIFO) have side effects.
+
+/// Endpoint interrupt flags, `UEINTX`. Writing a zero clears a flag, writing
+/// a one leaves it as it is.
+pub const TXINI: u8 = 1 << 0;
+pub const STALLEDI: u8 = 1 << 1;
+pub const RXOUTI: u8 = 1 << 2;
+pub const RXSTPI: u8 = 1 << 3;
+pub const NAKOUTI: u8 = 1 << 4;
+pub const RWAL: u8 = 1 << 5;
+pub const NAKINI: u8 = 1 << 6;
+pub const FIFOCON: u8 = 1 << 7;
+
+/// Endpoint interrupt enables, `UEIENX`.
+pub const RXSTPE: u8 = 1 << 3;
+
+/// Endpoint control, `UECONX`.
+pub const EPEN: u8 = 1 << 0;
+pub const RSTDT: u8 = 1 << 3;
+pub const STALLRQC: u8 = 1 << 4;
+pub const STALLRQ: u8 = 1 << 5;
+
+/// Device interrupt flags, `UDINT`.
+pub const SUSPI: u8 = 1 << 0;
+pub const SOFI: u8 = 1 << 2;
+pub const EORSTI: u8 = 1 << 3;
+pub const WAKEUPI: u8 = 1 << 4;
+
+/// Device interrupt enables, `UDIEN`, one per flag in `UDINT`.
+pub const SUSPE: u8 = 1 << 0;
+pub const SOFE: u8 = 1 << 2;
+pub const EORSTE: u8 = 1 << 3;
+pub const WAKEUPE: u8 = 1 << 4;
+
+/// The usb device controller. Endpoint registers and the FIFO refer to the
+/// endpoint last passed to `select_endpoint`.
+pub trait UsbController {
+  fn selected_endpoint(&mut self) -> u8;
+  fn select_endpoint(&mut self, endpoint: u8);
+
+  fn read_fifo(&mut self) -> u8;
+  fn write_fifo(&mut self, data: u8);
+
+  fn endpoint_flags(&mut self) -> u8;
+  fn write_endpoint_flags(&mut self, flags: u8);
+
+  fn endpoint_interrupts(&mut self) -> u8;
+  fn set_endpoint_interrupts(&mut self, enables: u8);
+
+  /// Writes `UECONX`, enabling or stalling the selected endpoint.
+  fn write_endpoint_control(&mut self, control: u8);
+  /// Writes the type, direction, size and banks of the selected endpoint,
+  /// `UECFG0X` and `UECFG1X`.
+  fn configure_endpoint(&mut self, config0: u8, config1: u8);
+  /// Resets the FIFOs of the endpoints set in `endpoints`, a mask with bit
+  /// `n` for endpoint `n`.
+  fn reset_endpoints(&mut self, endpoints: u8);
+
+  fn set_address(&mut self, address: u8);
+  fn frame_number(&mut self) -> u16;
+
+  fn device_interrupts(&mut self) -> u8;
+  /// Clears `flags` in `UDINT`, leaving any other raised flag for later.
+  fn clear_device_interrupts(&mut self, flags: u8);
+  fn device_interrupt_enables(&mut self) -> u8;
+  fn set_device_interrupt_enables(&mut self, enables: u8);
+
+  /// Whether the usb clock is frozen, `FRZCLK`.
+  fn is_clock_frozen(&mut self) -> bool;
+  /// Freezes the usb clock and stops the PLL behind it, or starts the PLL
+  /// and waits for it to lock before unfreezing the clock.
+  fn freeze_clock(&mut self, frozen: bool);
+  /// Starts resume signalling on the bus, `RMWKUP`. The clock must be running.
+  fn send_remote_wakeup(&mut self);
+
+  /// Clears `flags` on the selected endpoint, leaving the others as read.
+  fn clear_endpoint_flags(&mut self, flags: u8) {
+    let current = self.endpoint_flags();
+    self.write_endpoint_flags(current & !flags);
+  }
+
+  fn is_endpoint_flag_set(&mut self, flag: u8) -> bool {
+    self.endpoint_flags() & flag != 0
+  }
+}
+
+/// A UART.
+pub trait Uart {
+  fn write(&mut self, data: u8);
+  fn read(&mut self) -> u8;
+  /// Whether the transmit buffer can take another byte.
+  fn ready_to_send(&mut self) -> bool;
+}
+
+/// An eight pin GPIO port, pins numbered by their bit.
+pub trait Gpio {
+  fn set_output(&mut self, pin: u8, output: bool);
+  fn write(&mut self, pin: u8, high: bool);
+  /// Level an output pin is driven to.
+  fn is_set_high(&mut self, pin: u8) -> bool;
+  /// Level read back from the pin.
+  fn read(&mut self, pin: u8) -> bool;
+
+  fn toggle(&mut self, pin: u8) {
+    let high = self.is_set_high(pin);
+    self.write(pin, !high);
+  }
+}
+
+/// `bits` with bit `pin` set to `high`.
+pub fn with_bit(bits: u8, pin: u8, high: bool) -> u8 {
+  if high {
+    bits | (1 << pin)
+  } else {
+    bits & !(1 << pin)
+  }
+}
@@ -0,0 +1,66 @@
+/// Maximum number of physical buttons a layout can wire up.
+pub const MAX_PHYSICAL_BUTTONS: usize = 16;
+
+/// The set of physical buttons held down during a scan, one bit per button
+/// index as assigned by the controller layout.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct PhysicalButtons(pub u16);
+
+impl PhysicalButtons {
+  pub const NONE: PhysicalButtons = PhysicalButtons(0);
+
+  pub const fn from_indices(indices: &[u8]) -> PhysicalButtons {
+    let mut bits = 0;
+    let mut i = 0;
+    while i < indices.len() {
+      bits |= 1 << indices[i];
+      i += 1;
+    }
+    PhysicalButtons(bits)
+  }
+
+  pub fn with(self, index: u8, pressed: bool) -> PhysicalButtons {
+    if pressed {
+      PhysicalButtons(self.0 | (1 << index))
+    } else {
+      PhysicalButtons(self.0 & !(1 << index))
+    }
+  }
+
+  pub fn is_pressed(&self, index: u8) -> bool {
+    (self.0 >> index) & 1 == 1
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0 == 0
+  }
+
+  /// Buttons held in `self` that were not held in `previous`.
+  pub fn newly_pressed(&self, previous: PhysicalButtons) -> PhysicalButtons {
+    PhysicalButtons(self.0 & !previous.0)
+  }
+
+  /// Returns the index of the only button in the set, if exactly one is held.
+  pub fn single(&self) -> Option<u8> {
+    if self.0.count_ones() == 1 {
+      Some(self.0.trailing_zeros() as u8)
+    } else {
+      None
+    }
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = u8> {
+    let bits = self.0;
+    (0..MAX_PHYSICAL_BUTTONS as u8).filter(move |i| (bits >> i) & 1 == 1)
+  }
+}
+
+/// A digital axis from whether each of its directions is held. Holding both,
+/// or neither, centres it.
+pub fn determine_axis(positive: bool, negative: bool) -> i8 {
+  match (positive, negative) {
+    (true, false) => 127,
+    (false, true) => -127,
+    _ => 0,
+  }
+}
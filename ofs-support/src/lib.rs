@@ -1,4 +1,32 @@
 #![no_std]
 
+#[cfg(feature = "mock")]
+extern crate std;
+
+pub mod analog;
+pub mod calibration;
+pub mod clock;
+pub mod debounce;
+pub mod descriptor_builder;
+pub mod descriptors;
+pub mod endpoint;
 pub mod fightstick;
+pub mod hal;
+pub mod input;
+pub mod lock;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod power;
+pub mod quadrature;
+pub mod queue;
+pub mod remap;
+pub mod report_descriptor;
+pub mod report_layout;
+pub mod resource;
+pub mod scheduler;
+pub mod serial;
+pub mod settings;
+pub mod time;
+pub mod timing;
 pub mod usart;
+pub mod usb;
@@ -0,0 +1,84 @@
+//! Tournament lock, a policy applied after the active profile.
+//!
+//! While locked, the configured system buttons (Start, Home, Select and so
+//! on) never reach the host, and on-device configuration such as remapping is
+//! refused. The lock is toggled by holding its combo for
+//! [`LockConfig::hold_ms`], and the combo has to be released before it can
+//! toggle again.
+
+use crate::fightstick::{Fightstick, STATUS_LOCKED};
+use crate::input::PhysicalButtons;
+
+#[derive(Clone, Copy)]
+pub struct LockConfig {
+  /// Buttons that must be held, and nothing else, to toggle the lock.
+  pub combo: PhysicalButtons,
+  pub hold_ms: u16,
+  /// Logical buttons, in the layout of [`Fightstick::buttons`], that are
+  /// masked while locked.
+  pub blocked_buttons: u16,
+}
+
+pub struct TournamentLock {
+  config: LockConfig,
+  locked: bool,
+  held_ms: u16,
+  /// Set once the combo toggled the lock, until it is released.
+  toggled: bool,
+}
+
+impl TournamentLock {
+  pub const fn new(config: LockConfig) -> TournamentLock {
+    TournamentLock {
+      config,
+      locked: false,
+      held_ms: 0,
+      toggled: false,
+    }
+  }
+
+  pub fn is_locked(&self) -> bool {
+    self.locked
+  }
+
+  pub fn set_locked(&mut self, locked: bool) {
+    self.locked = locked;
+  }
+
+  /// Whether remapping and other on-device configuration may run.
+  pub fn allows_configuration(&self) -> bool {
+    !self.locked
+  }
+
+  /// Advances the combo timer, returning the new lock state if it toggled.
+  pub fn update(&mut self, pressed: PhysicalButtons, elapsed_ms: u16) -> Option<bool> {
+    if pressed != self.config.combo {
+      self.held_ms = 0;
+      self.toggled = false;
+      return None;
+    }
+
+    if self.toggled {
+      return None;
+    }
+
+    self.held_ms = self.held_ms.saturating_add(elapsed_ms);
+    if self.held_ms >= self.config.hold_ms {
+      self.toggled = true;
+      self.locked = !self.locked;
+      Some(self.locked)
+    } else {
+      None
+    }
+  }
+
+  /// Masks blocked buttons and reports the lock in the vendor status field.
+  pub fn apply(&self, fightstick: &mut Fightstick) {
+    if self.locked {
+      fightstick.buttons &= !self.config.blocked_buttons;
+      fightstick.status |= STATUS_LOCKED;
+    } else {
+      fightstick.status &= !STATUS_LOCKED;
+    }
+  }
+}
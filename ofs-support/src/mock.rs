@@ -0,0 +1,366 @@
+//! Host-side implementations of the `hal` traits for tests.
+//!
+//! Each mock keeps the register state a test can set up or inspect, and logs
+//! every access in order. Endpoint flags follow the hardware, writes can only
+//! clear them, so tests raise flags by setting the fields directly.
+//!
+//! On endpoint 0, clearing TXINI sends the bytes written to the bank as one
+//! IN packet, except when it is cleared together with RXSTPI to acknowledge a
+//! setup packet. OUT packets queued on an endpoint arrive one at a time,
+//! each once the setup packet and the OUT packet before it have been
+//! acknowledged.
+//!
+//! On the other endpoints, clearing FIFOCON sends the bank and switches to
+//! the next one, as many as the endpoint is configured with. Once every bank
+//! is full RWAL stays clear until the host takes a packet with
+//! `MockUsb::take_in`, unless `host_takes_in` is set.
+
+use std::collections::VecDeque;
+use std::mem;
+use std::vec::Vec;
+
+use crate::hal::{with_bit, Gpio, Uart, UsbController, FIFOCON, RWAL, RXOUTI, RXSTPI, TXINI};
+
+/// Endpoints on the usb chip, endpoint 0 and four more.
+pub const MOCK_ENDPOINTS: usize = 5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UsbAccess {
+  ReadSelectedEndpoint(u8),
+  SelectEndpoint(u8),
+  ReadFifo(u8),
+  WriteFifo(u8),
+  ReadEndpointFlags(u8),
+  WriteEndpointFlags(u8),
+  ReadEndpointInterrupts(u8),
+  SetEndpointInterrupts(u8),
+  WriteEndpointControl(u8),
+  ConfigureEndpoint(u8, u8),
+  ResetEndpoints(u8),
+  SetAddress(u8),
+  ReadFrameNumber(u16),
+  ReadDeviceInterrupts(u8),
+  ClearDeviceInterrupts(u8),
+  ReadDeviceInterruptEnables(u8),
+  SetDeviceInterruptEnables(u8),
+  ReadClockFrozen(bool),
+  FreezeClock(bool),
+  SendRemoteWakeup,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct MockEndpoint {
+  pub flags: u8,
+  pub interrupts: u8,
+  pub control: u8,
+  pub config: (u8, u8),
+  /// Bytes from the host, waiting to be read out of the FIFO.
+  pub received: VecDeque<u8>,
+  /// Bytes written into the FIFO that have not been sent yet.
+  pub bank: Vec<u8>,
+  /// IN packets sent to the host, in order.
+  pub sent: Vec<Vec<u8>>,
+  /// OUT packets the host has yet to send.
+  pub out_packets: VecDeque<Vec<u8>>,
+  /// IN packets in banks the host has not read yet.
+  pub in_flight: u8,
+}
+
+impl MockEndpoint {
+  /// Banks set in the configuration, `EPBK` of `UECFG1X`.
+  pub fn banks(&self) -> u8 {
+    ((self.config.1 >> 2) & 0b11) + 1
+  }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct MockUsb {
+  pub log: Vec<UsbAccess>,
+  pub endpoint: u8,
+  pub endpoints: [MockEndpoint; MOCK_ENDPOINTS],
+  pub address: Option<u8>,
+  pub frame: u16,
+  pub device_interrupts: u8,
+  pub device_interrupt_enables: u8,
+  pub clock_frozen: bool,
+  /// Resume signals sent to the host.
+  pub remote_wakeups: u32,
+  /// Whether the host takes IN packets as soon as they are sent, freeing the
+  /// bank and raising TXINI again.
+  pub host_takes_in: bool,
+}
+
+impl MockUsb {
+  pub fn new() -> MockUsb {
+    MockUsb::default()
+  }
+
+  pub fn endpoint(&mut self, endpoint: u8) -> &mut MockEndpoint {
+    &mut self.endpoints[endpoint as usize]
+  }
+
+  /// The host reads the IN packet waiting on `endpoint`, freeing its bank.
+  /// Returns whether there was one.
+  pub fn take_in(&mut self, endpoint: u8) -> bool {
+    let endpoint = self.endpoint(endpoint);
+    let waiting = endpoint.in_flight > 0;
+    endpoint.in_flight = endpoint.in_flight.saturating_sub(1);
+    endpoint.flags |= RWAL | TXINI | FIFOCON;
+    waiting
+  }
+
+  fn selected(&mut self) -> &mut MockEndpoint {
+    let endpoint = self.endpoint;
+    self.endpoint(endpoint)
+  }
+}
+
+impl UsbController for MockUsb {
+  fn selected_endpoint(&mut self) -> u8 {
+    self.log.push(UsbAccess::ReadSelectedEndpoint(self.endpoint));
+    self.endpoint
+  }
+
+  fn select_endpoint(&mut self, endpoint: u8) {
+    self.log.push(UsbAccess::SelectEndpoint(endpoint));
+    self.endpoint = endpoint;
+  }
+
+  fn read_fifo(&mut self) -> u8 {
+    let data = self.selected().received.pop_front().unwrap_or(0);
+    self.log.push(UsbAccess::ReadFifo(data));
+    data
+  }
+
+  fn write_fifo(&mut self, data: u8) {
+    self.log.push(UsbAccess::WriteFifo(data));
+    self.selected().bank.push(data);
+  }
+
+  fn endpoint_flags(&mut self) -> u8 {
+    let endpoint = self.selected();
+    if endpoint.flags & (RXSTPI | RXOUTI) == 0 {
+      if let Some(packet) = endpoint.out_packets.pop_front() {
+        endpoint.received.extend(packet);
+        endpoint.flags |= RXOUTI;
+      }
+    }
+
+    let flags = endpoint.flags;
+    self.log.push(UsbAccess::ReadEndpointFlags(flags));
+    flags
+  }
+
+  fn write_endpoint_flags(&mut self, flags: u8) {
+    self.log.push(UsbAccess::WriteEndpointFlags(flags));
+    let host_takes_in = self.host_takes_in;
+    let control = self.endpoint == 0;
+    let endpoint = self.selected();
+    let cleared = endpoint.flags & !flags;
+    endpoint.flags &= flags;
+
+    if control && cleared & TXINI != 0 {
+      if cleared & RXSTPI == 0 {
+        let packet = mem::take(&mut endpoint.bank);
+        endpoint.sent.push(packet);
+      }
+
+      if host_takes_in {
+        endpoint.flags |= TXINI;
+      }
+    }
+
+    if !control && cleared & FIFOCON != 0 {
+      let packet = mem::take(&mut endpoint.bank);
+      endpoint.sent.push(packet);
+
+      if host_takes_in {
+        endpoint.flags |= TXINI | FIFOCON;
+      } else {
+        endpoint.in_flight += 1;
+        if endpoint.in_flight < endpoint.banks() {
+          endpoint.flags |= FIFOCON;
+        } else {
+          endpoint.flags &= !RWAL;
+        }
+      }
+    }
+  }
+
+  fn endpoint_interrupts(&mut self) -> u8 {
+    let interrupts = self.selected().interrupts;
+    self.log.push(UsbAccess::ReadEndpointInterrupts(interrupts));
+    interrupts
+  }
+
+  fn set_endpoint_interrupts(&mut self, enables: u8) {
+    self.log.push(UsbAccess::SetEndpointInterrupts(enables));
+    self.selected().interrupts = enables;
+  }
+
+  fn write_endpoint_control(&mut self, control: u8) {
+    self.log.push(UsbAccess::WriteEndpointControl(control));
+    self.selected().control = control;
+  }
+
+  fn configure_endpoint(&mut self, config0: u8, config1: u8) {
+    self.log.push(UsbAccess::ConfigureEndpoint(config0, config1));
+    self.selected().config = (config0, config1);
+  }
+
+  fn reset_endpoints(&mut self, endpoints: u8) {
+    self.log.push(UsbAccess::ResetEndpoints(endpoints));
+    for (n, endpoint) in self.endpoints.iter_mut().enumerate() {
+      if endpoints & (1 << n) != 0 {
+        endpoint.received.clear();
+        endpoint.bank.clear();
+      }
+    }
+  }
+
+  fn set_address(&mut self, address: u8) {
+    self.log.push(UsbAccess::SetAddress(address));
+    self.address = Some(address);
+  }
+
+  fn frame_number(&mut self) -> u16 {
+    self.log.push(UsbAccess::ReadFrameNumber(self.frame));
+    self.frame
+  }
+
+  fn device_interrupts(&mut self) -> u8 {
+    self.log.push(UsbAccess::ReadDeviceInterrupts(self.device_interrupts));
+    self.device_interrupts
+  }
+
+  fn clear_device_interrupts(&mut self, flags: u8) {
+    self.log.push(UsbAccess::ClearDeviceInterrupts(flags));
+    self.device_interrupts &= !flags;
+  }
+
+  fn device_interrupt_enables(&mut self) -> u8 {
+    let enables = self.device_interrupt_enables;
+    self.log.push(UsbAccess::ReadDeviceInterruptEnables(enables));
+    enables
+  }
+
+  fn set_device_interrupt_enables(&mut self, enables: u8) {
+    self.log.push(UsbAccess::SetDeviceInterruptEnables(enables));
+    self.device_interrupt_enables = enables;
+  }
+
+  fn is_clock_frozen(&mut self) -> bool {
+    self.log.push(UsbAccess::ReadClockFrozen(self.clock_frozen));
+    self.clock_frozen
+  }
+
+  fn freeze_clock(&mut self, frozen: bool) {
+    self.log.push(UsbAccess::FreezeClock(frozen));
+    self.clock_frozen = frozen;
+  }
+
+  fn send_remote_wakeup(&mut self) {
+    self.log.push(UsbAccess::SendRemoteWakeup);
+    assert!(!self.clock_frozen, "remote wakeup sent with the usb clock frozen");
+    self.remote_wakeups += 1;
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UartAccess {
+  Write(u8),
+  Read(u8),
+  ReadyToSend(bool),
+}
+
+#[derive(Clone, Debug)]
+pub struct MockUart {
+  pub log: Vec<UartAccess>,
+  pub received: VecDeque<u8>,
+  pub sent: Vec<u8>,
+  pub ready: bool,
+}
+
+impl MockUart {
+  pub fn new() -> MockUart {
+    MockUart {
+      log: Vec::new(),
+      received: VecDeque::new(),
+      sent: Vec::new(),
+      ready: true,
+    }
+  }
+}
+
+impl Default for MockUart {
+  fn default() -> Self {
+    MockUart::new()
+  }
+}
+
+impl Uart for MockUart {
+  fn write(&mut self, data: u8) {
+    self.log.push(UartAccess::Write(data));
+    self.sent.push(data);
+  }
+
+  fn read(&mut self) -> u8 {
+    let data = self.received.pop_front().unwrap_or(0);
+    self.log.push(UartAccess::Read(data));
+    data
+  }
+
+  fn ready_to_send(&mut self) -> bool {
+    self.log.push(UartAccess::ReadyToSend(self.ready));
+    self.ready
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GpioAccess {
+  SetOutput(u8, bool),
+  Write(u8, bool),
+  IsSetHigh(u8, bool),
+  Read(u8, bool),
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct MockGpio {
+  pub log: Vec<GpioAccess>,
+  /// Data direction bits, set for outputs.
+  pub direction: u8,
+  /// Levels driven on the outputs.
+  pub output: u8,
+  /// Levels read back from the pins.
+  pub input: u8,
+}
+
+impl MockGpio {
+  pub fn new() -> MockGpio {
+    MockGpio::default()
+  }
+}
+
+impl Gpio for MockGpio {
+  fn set_output(&mut self, pin: u8, output: bool) {
+    self.log.push(GpioAccess::SetOutput(pin, output));
+    self.direction = with_bit(self.direction, pin, output);
+  }
+
+  fn write(&mut self, pin: u8, high: bool) {
+    self.log.push(GpioAccess::Write(pin, high));
+    self.output = with_bit(self.output, pin, high);
+  }
+
+  fn is_set_high(&mut self, pin: u8) -> bool {
+    let high = self.output & (1 << pin) != 0;
+    self.log.push(GpioAccess::IsSetHigh(pin, high));
+    high
+  }
+
+  fn read(&mut self, pin: u8) -> bool {
+    let high = self.input & (1 << pin) != 0;
+    self.log.push(GpioAccess::Read(pin, high));
+    high
+  }
+}
@@ -0,0 +1,56 @@
+//! The controller's side of usb suspend.
+//!
+//! While the host has the bus suspended the controller idles, and a button
+//! pressed in that time asks the usb firmware to wake the host. Buttons that
+//! were already held when the bus was suspended only count once released and
+//! pressed again, so a button left held can not keep waking the host.
+
+use crate::input::PhysicalButtons;
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SuspendState {
+  suspended: bool,
+  /// Buttons held at the last scan.
+  held: PhysicalButtons,
+  /// Set once wakeup was asked for, until the host resumes the bus.
+  wakeup_sent: bool,
+}
+
+impl SuspendState {
+  pub const fn new() -> SuspendState {
+    SuspendState {
+      suspended: false,
+      held: PhysicalButtons::NONE,
+      wakeup_sent: false,
+    }
+  }
+
+  pub fn is_suspended(&self) -> bool {
+    self.suspended
+  }
+
+  /// The bus was suspended with `held` down.
+  pub fn suspend(&mut self, held: PhysicalButtons) {
+    self.suspended = true;
+    self.held = held;
+    self.wakeup_sent = false;
+  }
+
+  pub fn resume(&mut self) {
+    self.suspended = false;
+  }
+
+  /// Feeds a scan taken while suspended, returning whether to ask for
+  /// wakeup. Asks once per suspend, the host may take a while to resume.
+  pub fn update(&mut self, buttons: PhysicalButtons) -> bool {
+    let pressed = buttons.newly_pressed(self.held);
+    self.held = buttons;
+
+    if !self.suspended || self.wakeup_sent || pressed.is_empty() {
+      return false;
+    }
+
+    self.wakeup_sent = true;
+    true
+  }
+}
@@ -0,0 +1,75 @@
+//! Quadrature decoding for spinners and other rotary encoders.
+//!
+//! The decoder is fed the A and B lines on every edge and accumulates signed
+//! counts, which are scaled into a relative axis once per report.
+
+/// Count change for each `(previous << 2) | current` pair of AB states.
+/// Transitions that skip a state are treated as noise and ignored.
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+/// Report units per encoder count, as a ratio.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Sensitivity {
+  pub numerator: u8,
+  pub denominator: u8,
+}
+
+impl Sensitivity {
+  pub const UNIT: Sensitivity = Sensitivity {
+    numerator: 1,
+    denominator: 1,
+  };
+}
+
+pub struct QuadratureDecoder {
+  state: u8,
+  count: i16,
+  /// Scaled motion left over from previous reports.
+  remainder: i16,
+}
+
+impl QuadratureDecoder {
+  pub const fn new() -> QuadratureDecoder {
+    QuadratureDecoder {
+      state: 0,
+      count: 0,
+      remainder: 0,
+    }
+  }
+
+  /// Records the current level of the A and B lines, returning the change
+  /// in count.
+  pub fn update(&mut self, a: bool, b: bool) -> i8 {
+    let current = ((a as u8) << 1) | b as u8;
+    let delta = TRANSITIONS[((self.state << 2) | current) as usize];
+
+    self.state = current;
+    self.count = self.count.saturating_add(delta as i16);
+    delta
+  }
+
+  /// Counts accumulated since the last report.
+  pub fn pending(&self) -> i16 {
+    self.count
+  }
+
+  /// Drains accumulated counts into a relative axis value. Motion that does
+  /// not fit in one report, or is finer than one report unit, carries over to
+  /// the next.
+  pub fn take_report(&mut self, sensitivity: Sensitivity) -> i8 {
+    let denominator = sensitivity.denominator.max(1) as i32;
+    let total = self.count as i32 * sensitivity.numerator as i32 + self.remainder as i32;
+    let report = (total / denominator).clamp(-127, 127);
+
+    let limit = denominator * 127;
+    self.remainder = (total - report * denominator).clamp(-limit, limit) as i16;
+    self.count = 0;
+    report as i8
+  }
+}
+
+impl Default for QuadratureDecoder {
+  fn default() -> Self {
+    QuadratureDecoder::new()
+  }
+}
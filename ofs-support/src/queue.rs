@@ -0,0 +1,65 @@
+/// Bytes a [`ByteQueue`] holds before dropping new ones.
+pub const BYTE_QUEUE_CAPACITY: usize = 16;
+
+/// A fixed size FIFO for handing received bytes from an interrupt to a task
+/// without allocating.
+pub struct ByteQueue {
+  data: [u8; BYTE_QUEUE_CAPACITY],
+  head: u8,
+  len: u8,
+  /// Bytes dropped because the queue was full.
+  dropped: u16,
+}
+
+impl ByteQueue {
+  pub const fn new() -> ByteQueue {
+    ByteQueue {
+      data: [0; BYTE_QUEUE_CAPACITY],
+      head: 0,
+      len: 0,
+      dropped: 0,
+    }
+  }
+
+  /// Appends a byte, returning false and dropping it if the queue is full.
+  pub fn push(&mut self, byte: u8) -> bool {
+    if self.len as usize >= BYTE_QUEUE_CAPACITY {
+      self.dropped = self.dropped.saturating_add(1);
+      return false;
+    }
+
+    let tail = (self.head as usize + self.len as usize) % BYTE_QUEUE_CAPACITY;
+    self.data[tail] = byte;
+    self.len += 1;
+    true
+  }
+
+  pub fn pop(&mut self) -> Option<u8> {
+    if self.len == 0 {
+      return None;
+    }
+
+    let byte = self.data[self.head as usize];
+    self.head = ((self.head as usize + 1) % BYTE_QUEUE_CAPACITY) as u8;
+    self.len -= 1;
+    Some(byte)
+  }
+
+  pub fn len(&self) -> usize {
+    self.len as usize
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn dropped(&self) -> u16 {
+    self.dropped
+  }
+}
+
+impl Default for ByteQueue {
+  fn default() -> Self {
+    ByteQueue::new()
+  }
+}
@@ -0,0 +1,147 @@
+//! On-device remapping, driven entirely from the stick's own buttons.
+//!
+//! Holding the entry combo for [`RemapConfig::hold_ms`] enters remap mode.
+//! While in remap mode, pressing a physical button selects it, and pressing a
+//! second button assigns the logical button of the second to the first. The
+//! cancel button drops a pending selection, or leaves remap mode when nothing
+//! is selected. Remap mode is also left after [`RemapConfig::timeout_ms`]
+//! without any presses.
+
+use crate::fightstick::Button;
+use crate::input::PhysicalButtons;
+use crate::settings::Profile;
+
+#[derive(Clone, Copy)]
+pub struct RemapConfig {
+  /// Buttons that must be held, and nothing else, to enter remap mode.
+  pub combo: PhysicalButtons,
+  /// Physical button that cancels a selection or leaves remap mode.
+  pub cancel: u8,
+  pub hold_ms: u16,
+  pub timeout_ms: u16,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RemapState {
+  Idle,
+  Arming,
+  AwaitSource,
+  AwaitTarget { source: u8 },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RemapEvent {
+  Entered,
+  Selected {
+    physical: u8,
+  },
+  /// `physical` should now report as `logical` in the active profile.
+  Remapped {
+    physical: u8,
+    logical: Button,
+  },
+  SelectionCancelled,
+  Exited,
+  TimedOut,
+}
+
+pub struct RemapMachine {
+  config: RemapConfig,
+  state: RemapState,
+  previous: PhysicalButtons,
+  /// Time spent holding the combo while arming, or without presses while
+  /// remapping.
+  timer_ms: u16,
+}
+
+impl RemapMachine {
+  pub const fn new(config: RemapConfig) -> RemapMachine {
+    RemapMachine {
+      config,
+      state: RemapState::Idle,
+      previous: PhysicalButtons::NONE,
+      timer_ms: 0,
+    }
+  }
+
+  pub fn state(&self) -> RemapState {
+    self.state
+  }
+
+  /// Whether remap mode owns the buttons, in which case they should not be
+  /// reported to the host.
+  pub fn is_active(&self) -> bool {
+    matches!(self.state, RemapState::AwaitSource | RemapState::AwaitTarget { .. })
+  }
+
+  /// Abandons remap mode without reporting an event.
+  pub fn reset(&mut self) {
+    self.transition(RemapState::Idle);
+  }
+
+  /// Advances the machine with the buttons held this scan and the time since
+  /// the previous call.
+  pub fn update(&mut self, pressed: PhysicalButtons, elapsed_ms: u16, profile: &Profile) -> Option<RemapEvent> {
+    let newly_pressed = pressed.newly_pressed(self.previous);
+    self.previous = pressed;
+    self.timer_ms = self.timer_ms.saturating_add(elapsed_ms);
+
+    match self.state {
+      RemapState::Idle => {
+        if pressed == self.config.combo {
+          self.transition(RemapState::Arming);
+        }
+        None
+      },
+      RemapState::Arming => {
+        if pressed != self.config.combo {
+          self.transition(RemapState::Idle);
+          None
+        } else if self.timer_ms >= self.config.hold_ms {
+          self.transition(RemapState::AwaitSource);
+          Some(RemapEvent::Entered)
+        } else {
+          None
+        }
+      },
+      RemapState::AwaitSource => match newly_pressed.single() {
+        Some(physical) if physical == self.config.cancel => {
+          self.transition(RemapState::Idle);
+          Some(RemapEvent::Exited)
+        },
+        Some(physical) => {
+          self.transition(RemapState::AwaitTarget { source: physical });
+          Some(RemapEvent::Selected { physical })
+        },
+        None => self.check_timeout(),
+      },
+      RemapState::AwaitTarget { source } => match newly_pressed.single() {
+        Some(target) => {
+          self.transition(RemapState::AwaitSource);
+          match profile.logical(target) {
+            Some(logical) if target != self.config.cancel => Some(RemapEvent::Remapped {
+              physical: source,
+              logical,
+            }),
+            _ => Some(RemapEvent::SelectionCancelled),
+          }
+        },
+        None => self.check_timeout(),
+      },
+    }
+  }
+
+  fn transition(&mut self, state: RemapState) {
+    self.state = state;
+    self.timer_ms = 0;
+  }
+
+  fn check_timeout(&mut self) -> Option<RemapEvent> {
+    if self.timer_ms >= self.config.timeout_ms {
+      self.transition(RemapState::Idle);
+      Some(RemapEvent::TimedOut)
+    } else {
+      None
+    }
+  }
+}
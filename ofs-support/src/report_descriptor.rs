@@ -0,0 +1,364 @@
+//! HID report descriptors, HID 1.11 section 6.2.2.
+//!
+//! A [`ReportDescriptorBuilder`] writes items at compile time, each as the
+//! smallest short item that holds its data. Item sizes vary, so a
+//! descriptor is written by a generic `const fn` and measured first with a
+//! builder of zero bytes, whose [`ReportDescriptorBuilder::len`] sizes the
+//! real one.
+//!
+//! [`parse`] reads a descriptor back into the size of the reports it
+//! declares, so the descriptor can be held against the report that is sent.
+
+/// Item prefixes, with the size bits clear. Main items.
+pub const INPUT: u8 = 0x80;
+pub const OUTPUT: u8 = 0x90;
+pub const COLLECTION: u8 = 0xA0;
+pub const FEATURE: u8 = 0xB0;
+pub const END_COLLECTION: u8 = 0xC0;
+/// Global items.
+pub const USAGE_PAGE: u8 = 0x04;
+pub const LOGICAL_MINIMUM: u8 = 0x14;
+pub const LOGICAL_MAXIMUM: u8 = 0x24;
+pub const REPORT_SIZE: u8 = 0x74;
+pub const REPORT_ID: u8 = 0x84;
+pub const REPORT_COUNT: u8 = 0x94;
+pub const PUSH: u8 = 0xA4;
+pub const POP: u8 = 0xB4;
+/// Local items.
+pub const USAGE: u8 = 0x08;
+pub const USAGE_MINIMUM: u8 = 0x18;
+pub const USAGE_MAXIMUM: u8 = 0x28;
+
+/// Marks a long item, which no descriptor here uses.
+const LONG_ITEM: u8 = 0xFE;
+const SIZE_MASK: u8 = 0b11;
+
+/// Usage pages, HID Usage Tables 1.12 section 3.
+pub const GENERIC_DESKTOP: u16 = 0x01;
+pub const KEYBOARD: u16 = 0x07;
+pub const BUTTON: u16 = 0x09;
+pub const VENDOR_DEFINED: u16 = 0xFF00;
+
+/// Generic Desktop usages, HID Usage Tables 1.12 section 4.
+pub const JOYSTICK: u16 = 0x04;
+pub const GAMEPAD: u16 = 0x05;
+pub const X: u16 = 0x30;
+pub const Y: u16 = 0x31;
+pub const Z: u16 = 0x32;
+pub const RX: u16 = 0x33;
+pub const RY: u16 = 0x34;
+pub const RZ: u16 = 0x35;
+pub const DIAL: u16 = 0x37;
+
+/// Flags of an input, output or feature item, HID 1.11 section 6.2.2.5.
+/// Clear for data, array and absolute.
+pub const CONSTANT: u8 = 1 << 0;
+pub const VARIABLE: u8 = 1 << 1;
+pub const RELATIVE: u8 = 1 << 2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum Collection {
+  Physical = 0,
+  Application = 1,
+  Logical = 2,
+}
+
+pub struct ReportDescriptorBuilder<const N: usize> {
+  bytes: [u8; N],
+  length: usize,
+  /// Collections left open.
+  depth: usize,
+}
+
+impl<const N: usize> ReportDescriptorBuilder<N> {
+  pub const fn new() -> ReportDescriptorBuilder<N> {
+    ReportDescriptorBuilder {
+      bytes: [0; N],
+      length: 0,
+      depth: 0,
+    }
+  }
+
+  /// Bytes written so far, or that would have been past the end of the
+  /// buffer.
+  pub const fn len(&self) -> usize {
+    self.length
+  }
+
+  pub const fn is_empty(&self) -> bool {
+    self.length == 0
+  }
+
+  const fn byte(mut self, byte: u8) -> ReportDescriptorBuilder<N> {
+    // Past the end only counts, for measuring
+    if self.length < N {
+      self.bytes[self.length] = byte;
+    }
+    self.length += 1;
+    self
+  }
+
+  /// A short item with `size` bytes of data, little endian.
+  const fn item(self, prefix: u8, data: u32, size: usize) -> ReportDescriptorBuilder<N> {
+    let code = if size == 4 { 3 } else { size as u8 };
+    let mut builder = self.byte(prefix | code);
+    let mut i = 0;
+    while i < size {
+      builder = builder.byte((data >> (8 * i)) as u8);
+      i += 1;
+    }
+    builder
+  }
+
+  const fn unsigned(self, prefix: u8, data: u32) -> ReportDescriptorBuilder<N> {
+    let size = if data <= 0xFF {
+      1
+    } else if data <= 0xFFFF {
+      2
+    } else {
+      4
+    };
+    self.item(prefix, data, size)
+  }
+
+  const fn signed(self, prefix: u8, data: i32) -> ReportDescriptorBuilder<N> {
+    let size = if data >= i8::MIN as i32 && data <= i8::MAX as i32 {
+      1
+    } else if data >= i16::MIN as i32 && data <= i16::MAX as i32 {
+      2
+    } else {
+      4
+    };
+    self.item(prefix, data as u32, size)
+  }
+
+  pub const fn usage_page(self, page: u16) -> ReportDescriptorBuilder<N> {
+    self.unsigned(USAGE_PAGE, page as u32)
+  }
+
+  pub const fn usage(self, usage: u16) -> ReportDescriptorBuilder<N> {
+    self.unsigned(USAGE, usage as u32)
+  }
+
+  pub const fn usage_minimum(self, usage: u16) -> ReportDescriptorBuilder<N> {
+    self.unsigned(USAGE_MINIMUM, usage as u32)
+  }
+
+  pub const fn usage_maximum(self, usage: u16) -> ReportDescriptorBuilder<N> {
+    self.unsigned(USAGE_MAXIMUM, usage as u32)
+  }
+
+  pub const fn logical_minimum(self, minimum: i32) -> ReportDescriptorBuilder<N> {
+    self.signed(LOGICAL_MINIMUM, minimum)
+  }
+
+  pub const fn logical_maximum(self, maximum: i32) -> ReportDescriptorBuilder<N> {
+    self.signed(LOGICAL_MAXIMUM, maximum)
+  }
+
+  /// Bits in each field.
+  pub const fn report_size(self, bits: u8) -> ReportDescriptorBuilder<N> {
+    self.unsigned(REPORT_SIZE, bits as u32)
+  }
+
+  /// Fields in each following input, output or feature item.
+  pub const fn report_count(self, count: u8) -> ReportDescriptorBuilder<N> {
+    self.unsigned(REPORT_COUNT, count as u32)
+  }
+
+  /// Input fields, with `flags` of [`CONSTANT`], [`VARIABLE`] and [`RELATIVE`].
+  pub const fn input(self, flags: u8) -> ReportDescriptorBuilder<N> {
+    self.unsigned(INPUT, flags as u32)
+  }
+
+  pub const fn output(self, flags: u8) -> ReportDescriptorBuilder<N> {
+    self.unsigned(OUTPUT, flags as u32)
+  }
+
+  pub const fn feature(self, flags: u8) -> ReportDescriptorBuilder<N> {
+    self.unsigned(FEATURE, flags as u32)
+  }
+
+  pub const fn collection(mut self, collection: Collection) -> ReportDescriptorBuilder<N> {
+    self.depth += 1;
+    self.unsigned(COLLECTION, collection as u32)
+  }
+
+  pub const fn end_collection(mut self) -> ReportDescriptorBuilder<N> {
+    // Underflows, failing the build, without a collection to end
+    self.depth -= 1;
+    self.byte(END_COLLECTION)
+  }
+
+  /// The finished descriptor, which must fill the buffer exactly and close
+  /// every collection.
+  pub const fn build(self) -> [u8; N] {
+    // Out of bounds, failing the build, otherwise
+    [self.bytes][(self.length != N || self.depth != 0) as usize]
+  }
+}
+
+impl<const N: usize> Default for ReportDescriptorBuilder<N> {
+  fn default() -> Self {
+    ReportDescriptorBuilder::new()
+  }
+}
+
+/// A short item read from a descriptor.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Item {
+  /// Tag and type, with the size bits clear.
+  pub prefix: u8,
+  pub data: u32,
+  /// Bytes of data, 0, 1, 2 or 4.
+  pub size: u8,
+}
+
+impl Item {
+  /// The data sign extended, for logical and physical extents.
+  pub const fn signed(&self) -> i32 {
+    match self.size {
+      1 => self.data as u8 as i8 as i32,
+      2 => self.data as u16 as i16 as i32,
+      _ => self.data as i32,
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParseError {
+  /// The item at `at` runs past the end of the descriptor.
+  Truncated { at: usize },
+  LongItem { at: usize },
+  /// An END_COLLECTION at `at` with no collection open, or `at` is the end of
+  /// the descriptor with collections still open.
+  UnbalancedCollection { at: usize },
+  /// The device has no report ids, every report is report 0.
+  ReportId { at: usize },
+  /// PUSH and POP are not supported.
+  Unsupported { at: usize },
+}
+
+/// Reads the item at `at`, returning it and where the next one starts.
+pub const fn read_item(descriptor: &[u8], at: usize) -> Result<(Item, usize), ParseError> {
+  let prefix = descriptor[at];
+  if prefix == LONG_ITEM {
+    return Err(ParseError::LongItem { at });
+  }
+
+  let size = match prefix & SIZE_MASK {
+    3 => 4,
+    code => code as usize,
+  };
+  if at + 1 + size > descriptor.len() {
+    return Err(ParseError::Truncated { at });
+  }
+
+  let mut data = 0;
+  let mut i = 0;
+  while i < size {
+    data |= (descriptor[at + 1 + i] as u32) << (8 * i);
+    i += 1;
+  }
+
+  let item = Item {
+    prefix: prefix & !SIZE_MASK,
+    data,
+    size: size as u8,
+  };
+  Ok((item, at + 1 + size))
+}
+
+/// Iterates over the items of a descriptor, stopping after the first error.
+pub struct Items<'a> {
+  descriptor: &'a [u8],
+  at: usize,
+}
+
+pub fn items(descriptor: &[u8]) -> Items<'_> {
+  Items { descriptor, at: 0 }
+}
+
+impl<'a> Iterator for Items<'a> {
+  type Item = Result<Item, ParseError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.at >= self.descriptor.len() {
+      return None;
+    }
+
+    match read_item(self.descriptor, self.at) {
+      Ok((item, next)) => {
+        self.at = next;
+        Some(Ok(item))
+      },
+      Err(error) => {
+        self.at = self.descriptor.len();
+        Some(Err(error))
+      },
+    }
+  }
+}
+
+/// Sizes of the reports a descriptor declares.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Reports {
+  pub input_bits: u32,
+  pub output_bits: u32,
+  pub feature_bits: u32,
+}
+
+impl Reports {
+  /// Bytes in the input report, as sent on the interrupt endpoint.
+  pub const fn input_bytes(&self) -> usize {
+    // Rounded up to a whole byte
+    ((self.input_bits + 7) >> 3) as usize
+  }
+}
+
+/// Walks a descriptor, summing the fields of each input, output and feature
+/// item and checking that collections are balanced.
+pub const fn parse(descriptor: &[u8]) -> Result<Reports, ParseError> {
+  let mut reports = Reports {
+    input_bits: 0,
+    output_bits: 0,
+    feature_bits: 0,
+  };
+  let mut report_size = 0;
+  let mut report_count = 0;
+  let mut depth = 0;
+
+  let mut at = 0;
+  while at < descriptor.len() {
+    let (item, next) = match read_item(descriptor, at) {
+      Ok(read) => read,
+      Err(error) => return Err(error),
+    };
+
+    let bits = report_size * report_count;
+    match item.prefix {
+      INPUT => reports.input_bits += bits,
+      OUTPUT => reports.output_bits += bits,
+      FEATURE => reports.feature_bits += bits,
+      COLLECTION => depth += 1,
+      END_COLLECTION => {
+        if depth == 0 {
+          return Err(ParseError::UnbalancedCollection { at });
+        }
+        depth -= 1;
+      },
+      REPORT_SIZE => report_size = item.data,
+      REPORT_COUNT => report_count = item.data,
+      REPORT_ID => return Err(ParseError::ReportId { at }),
+      PUSH | POP => return Err(ParseError::Unsupported { at }),
+      _ => {},
+    }
+    at = next;
+  }
+
+  if depth != 0 {
+    return Err(ParseError::UnbalancedCollection { at });
+  }
+  Ok(reports)
+}
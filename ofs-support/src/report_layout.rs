@@ -0,0 +1,215 @@
+//! The input report, described once as a table of fields.
+//!
+//! [`REPORT_LAYOUT`](crate::fightstick::REPORT_LAYOUT) lists the fields of the
+//! report in order. The report size, and with it the UART message, the
+//! packing of a [`Fightstick`] and the HID report descriptor all come from
+//! that table, so adding an axis or buttons is a line there and a field on
+//! `Fightstick`.
+
+use crate::fightstick::{Fightstick, OutputMode};
+use crate::report_descriptor::{
+  Collection, ReportDescriptorBuilder, BUTTON, CONSTANT, GENERIC_DESKTOP, JOYSTICK, RELATIVE, VARIABLE,
+  VENDOR_DEFINED,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FieldKind {
+  /// A signed absolute byte, `-128..=127`, with its Generic Desktop usage.
+  Axis(u16),
+  /// A signed relative byte, `-127..=127`, with its Generic Desktop usage.
+  /// Motion is cleared from the report once it is sent.
+  Relative(u16),
+  /// One bit per button, buttons 1 and up, padded to a whole byte.
+  Buttons(u8),
+  /// An unsigned byte with its usage on the vendor-defined page.
+  Vendor(u16),
+}
+
+impl FieldKind {
+  /// Bytes the field takes up in the report.
+  pub const fn size(self) -> usize {
+    match self {
+      FieldKind::Buttons(count) => (count as usize + 7) >> 3,
+      _ => 1,
+    }
+  }
+}
+
+#[derive(Clone, Copy)]
+pub struct Field {
+  pub kind: FieldKind,
+  /// The field's value for a fightstick, written little endian in
+  /// [`FieldKind::size`] bytes.
+  pub value: fn(&Fightstick, OutputMode) -> u32,
+}
+
+/// Bytes in a report of `layout`.
+pub const fn report_size(layout: &[Field]) -> usize {
+  let mut size = 0;
+  let mut i = 0;
+  while i < layout.len() {
+    size += layout[i].kind.size();
+    i += 1;
+  }
+  size
+}
+
+/// Where the first field of `kind` starts in the report, or the report size
+/// if there is none.
+pub const fn field_offset(layout: &[Field], kind: FieldKind) -> usize {
+  let mut offset = 0;
+  let mut i = 0;
+  while i < layout.len() {
+    if same_kind(layout[i].kind, kind) {
+      return offset;
+    }
+    offset += layout[i].kind.size();
+    i += 1;
+  }
+  offset
+}
+
+/// `==` on `FieldKind`, which `PartialEq` can not do in a `const fn`.
+const fn same_kind(a: FieldKind, b: FieldKind) -> bool {
+  match (a, b) {
+    (FieldKind::Axis(a), FieldKind::Axis(b))
+    | (FieldKind::Relative(a), FieldKind::Relative(b))
+    | (FieldKind::Vendor(a), FieldKind::Vendor(b)) => a == b,
+    (FieldKind::Buttons(a), FieldKind::Buttons(b)) => a == b,
+    _ => false,
+  }
+}
+
+/// Packs `fightstick` into `report` as laid out by `layout`, which must be
+/// [`report_size`] bytes.
+pub fn pack(layout: &[Field], fightstick: &Fightstick, mode: OutputMode, report: &mut [u8]) {
+  let mut offset = 0;
+  for field in layout {
+    let value = (field.value)(fightstick, mode);
+    for (i, byte) in report[offset..offset + field.kind.size()].iter_mut().enumerate() {
+      *byte = (value >> (8 * i)) as u8;
+    }
+    offset += field.kind.size();
+  }
+}
+
+/// Zeroes the relative fields of `report`, so the same motion is not reported
+/// twice.
+pub fn clear_relative(layout: &[Field], report: &mut [u8]) {
+  let mut offset = 0;
+  for field in layout {
+    if let FieldKind::Relative(_) = field.kind {
+      report[offset] = 0;
+    }
+    offset += field.kind.size();
+  }
+}
+
+/// Whether `report` carries relative motion.
+pub fn has_relative(layout: &[Field], report: &[u8]) -> bool {
+  let mut offset = 0;
+  for field in layout {
+    if let FieldKind::Relative(_) = field.kind {
+      if report[offset] != 0 {
+        return true;
+      }
+    }
+    offset += field.kind.size();
+  }
+  false
+}
+
+/// The HID report descriptor of `layout`, a joystick application collection
+/// of its fields in order.
+pub const fn report_descriptor<const N: usize>(layout: &[Field]) -> ReportDescriptorBuilder<N> {
+  let mut builder = ReportDescriptorBuilder::new()
+    .usage_page(GENERIC_DESKTOP)
+    .usage(JOYSTICK)
+    .collection(Collection::Application);
+
+  let mut i = 0;
+  while i < layout.len() {
+    let (next, end) = field_items(builder, layout, i);
+    builder = next;
+    i = end;
+  }
+
+  builder.end_collection()
+}
+
+/// Items for the field at `at`, and the index of the field after them. A run
+/// of axes shares one logical collection.
+const fn field_items<const N: usize>(
+  builder: ReportDescriptorBuilder<N>,
+  layout: &[Field],
+  at: usize,
+) -> (ReportDescriptorBuilder<N>, usize) {
+  match layout[at].kind {
+    FieldKind::Axis(_) => {
+      let mut end = at;
+      while end < layout.len() && is_axis(layout[end].kind) {
+        end += 1;
+      }
+
+      let mut builder = builder
+        .collection(Collection::Logical)
+        .logical_minimum(-128)
+        .logical_maximum(127)
+        .usage_page(GENERIC_DESKTOP)
+        .report_size(8)
+        .report_count((end - at) as u8);
+      let mut i = at;
+      while i < end {
+        if let FieldKind::Axis(usage) = layout[i].kind {
+          builder = builder.usage(usage);
+        }
+        i += 1;
+      }
+      (builder.input(VARIABLE).end_collection(), end)
+    },
+    FieldKind::Relative(usage) => {
+      let builder = builder
+        .usage_page(GENERIC_DESKTOP)
+        .usage(usage)
+        .logical_minimum(-127)
+        .logical_maximum(127)
+        .report_size(8)
+        .report_count(1)
+        .input(VARIABLE | RELATIVE);
+      (builder, at + 1)
+    },
+    FieldKind::Buttons(count) => {
+      let mut builder = builder
+        .collection(Collection::Logical)
+        .usage_page(BUTTON)
+        .logical_maximum(1)
+        .logical_minimum(0)
+        .usage_minimum(1)
+        .usage_maximum(count as u16)
+        .report_count(count)
+        .report_size(1)
+        .input(VARIABLE);
+      // Constant bits up to the next byte
+      let padding = (8 - (count & 7)) & 7;
+      if padding != 0 {
+        builder = builder.report_count(padding).input(CONSTANT);
+      }
+      (builder.end_collection(), at + 1)
+    },
+    FieldKind::Vendor(usage) => {
+      let builder = builder
+        .usage_page(VENDOR_DEFINED)
+        .usage(usage)
+        .logical_minimum(0)
+        .logical_maximum(0xFF)
+        .report_size(8)
+        .report_count(1)
+        .input(VARIABLE);
+      (builder, at + 1)
+    },
+  }
+}
+
+const fn is_axis(kind: FieldKind) -> bool {
+  matches!(kind, FieldKind::Axis(_))
+}
@@ -0,0 +1,61 @@
+//! Ownership of main loop state without masking interrupts.
+//!
+//! State that only the main loop touches does not need a critical section,
+//! just protection against a task reentering it. A [`Resource`] lends its
+//! value out for the length of a closure and refuses to lend it again until
+//! the closure returns, so long waits can run with interrupts enabled.
+//!
+//! The busy flag is set with plain atomic stores, as the AVR has no compare
+//! and swap. It guards against reentrancy within the main loop, not against
+//! interrupts, so interrupts must never use a `Resource`. State shared with
+//! interrupts stays behind `interrupt::Mutex` or in an atomic.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+pub struct Resource<T> {
+  busy: AtomicBool,
+  value: UnsafeCell<Option<T>>,
+}
+
+// Only the main loop uses a resource, see the module documentation
+unsafe impl<T: Send> Sync for Resource<T> {}
+
+impl<T> Resource<T> {
+  pub const fn new() -> Resource<T> {
+    Resource {
+      busy: AtomicBool::new(false),
+      value: UnsafeCell::new(None),
+    }
+  }
+
+  /// Hands the value over, returning whether the resource was free to take
+  /// it.
+  pub fn init(&self, value: T) -> bool {
+    self.with_slot(|slot| *slot = Some(value)).is_some()
+  }
+
+  /// Runs `f` on the value, or returns `None` if the resource has not been
+  /// initialised or is already lent out.
+  pub fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Option<R> {
+    self.with_slot(|slot| slot.as_mut().map(f)).flatten()
+  }
+
+  fn with_slot<R, F: FnOnce(&mut Option<T>) -> R>(&self, f: F) -> Option<R> {
+    if self.busy.load(Ordering::Acquire) {
+      return None;
+    }
+
+    self.busy.store(true, Ordering::Release);
+    // The busy flag keeps this the only reference until it is cleared
+    let result = f(unsafe { &mut *self.value.get() });
+    self.busy.store(false, Ordering::Release);
+    Some(result)
+  }
+}
+
+impl<T> Default for Resource<T> {
+  fn default() -> Self {
+    Resource::new()
+  }
+}
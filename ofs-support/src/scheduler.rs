@@ -0,0 +1,103 @@
+//! A static cooperative scheduler.
+//!
+//! Interrupts only record that work is due with [`Scheduler::signal`], and the
+//! main loop runs the due tasks with [`Scheduler::run_next`], highest priority
+//! first. Tasks run to completion, so each one should only hold interrupts off
+//! for as long as it takes to touch shared state.
+//!
+//! Pending flags are plain atomic stores, as the AVR has no compare and swap.
+//! A signal that arrives while its task is being started is merged into that
+//! run, which is fine for tasks that pick up the latest state rather than
+//! counting signals.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Tasks a scheduler can hold. A task's id is its priority, lower ids run
+/// first.
+pub const MAX_TASKS: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TaskId(pub u8);
+
+pub struct Scheduler {
+  pending: [AtomicBool; MAX_TASKS],
+}
+
+impl Scheduler {
+  pub const fn new() -> Scheduler {
+    // AtomicBool is not Copy, so the array can not be built from one value
+    Scheduler {
+      pending: [
+        AtomicBool::new(false),
+        AtomicBool::new(false),
+        AtomicBool::new(false),
+        AtomicBool::new(false),
+        AtomicBool::new(false),
+        AtomicBool::new(false),
+        AtomicBool::new(false),
+        AtomicBool::new(false),
+      ],
+    }
+  }
+
+  /// Marks a task as due, safe to call from an interrupt.
+  pub fn signal(&self, task: TaskId) {
+    self.pending[task.0 as usize].store(true, Ordering::Release);
+  }
+
+  pub fn is_pending(&self, task: TaskId) -> bool {
+    self.pending[task.0 as usize].load(Ordering::Acquire)
+  }
+
+  /// Runs the highest priority task that is due, returning whether there was
+  /// one.
+  pub fn run_next<F: FnOnce(TaskId)>(&self, run: F) -> bool {
+    for (id, pending) in self.pending.iter().enumerate() {
+      if pending.load(Ordering::Acquire) {
+        pending.store(false, Ordering::Release);
+        run(TaskId(id as u8));
+        return true;
+      }
+    }
+    false
+  }
+}
+
+impl Default for Scheduler {
+  fn default() -> Self {
+    Scheduler::new()
+  }
+}
+
+/// Worst time interrupts were held off, in timer ticks.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct CriticalSectionStats {
+  pub worst_ticks: u16,
+  pub sections: u32,
+}
+
+impl CriticalSectionStats {
+  pub const fn new() -> CriticalSectionStats {
+    CriticalSectionStats {
+      worst_ticks: 0,
+      sections: 0,
+    }
+  }
+
+  pub fn record(&mut self, ticks: u16) {
+    self.worst_ticks = self.worst_ticks.max(ticks);
+    self.sections = self.sections.wrapping_add(1);
+  }
+}
+
+/// Ticks between two readings of a timer in CTC mode that counts
+/// `0..=compare`. `wrapped` is whether the compare match flag was raised
+/// between them; sections of more than one full period read as less.
+pub fn ticks_between(start: u8, end: u8, wrapped: bool, compare: u8) -> u16 {
+  let period = compare as u16 + 1;
+  if wrapped {
+    period + end as u16 - start as u16
+  } else {
+    (end as u16).saturating_sub(start as u16)
+  }
+}
@@ -0,0 +1,118 @@
+//! The usb serial number, which tells sticks apart.
+//!
+//! Hosts order players by serial number, so two sticks without one swap
+//! places between reboots. Each stick makes its serial number once, from the
+//! usb chip's signature row or from random bits on its first boot, and keeps
+//! it in the usb chip's EEPROM. The vendor request [`SET_SERIAL_NUMBER`]
+//! replaces it, taking effect the next time the host enumerates the stick.
+
+use crate::descriptor_builder::STRING_TYPE;
+use crate::settings::checksum;
+
+/// `bRequest` of the vendor request that sets the serial number, sent to the
+/// device with the characters as its data stage.
+pub const SET_SERIAL_NUMBER: u8 = 1;
+
+/// Most characters a serial number can have.
+pub const SERIAL_NUMBER_MAX: usize = 16;
+
+/// Hex digits in a generated serial number.
+const GENERATED_LENGTH: usize = 8;
+
+const MAGIC: [u8; 2] = [0x4f, 0x53];
+const HEADER_SIZE: usize = 3;
+
+/// Size of the persisted serial number image, including header and checksum.
+pub const SERIAL_IMAGE_SIZE: usize = HEADER_SIZE + SERIAL_NUMBER_MAX + 1;
+
+/// Size of the string descriptor of the longest serial number.
+pub const SERIAL_DESCRIPTOR_MAX: usize = 2 + 2 * SERIAL_NUMBER_MAX;
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+/// One to [`SERIAL_NUMBER_MAX`] printable ASCII characters.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SerialNumber {
+  chars: [u8; SERIAL_NUMBER_MAX],
+  length: u8,
+}
+
+impl SerialNumber {
+  /// Returns `None` if `chars` is empty, too long, or not printable ASCII.
+  pub fn new(chars: &[u8]) -> Option<SerialNumber> {
+    if chars.is_empty() || chars.len() > SERIAL_NUMBER_MAX {
+      return None;
+    }
+    if !chars.iter().all(|&c| (0x20..0x7F).contains(&c)) {
+      return None;
+    }
+
+    let mut serial = SerialNumber {
+      chars: [0; SERIAL_NUMBER_MAX],
+      length: chars.len() as u8,
+    };
+    serial.chars[..chars.len()].copy_from_slice(chars);
+    Some(serial)
+  }
+
+  /// Hashes `id`, the signature row or random bits, to eight hex digits.
+  pub fn from_id(id: &[u8]) -> SerialNumber {
+    // 32 bit FNV-1a
+    let hash = id
+      .iter()
+      .fold(0x811C_9DC5u32, |hash, &byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193));
+
+    let mut chars = [0; GENERATED_LENGTH];
+    for (i, c) in chars.iter_mut().enumerate() {
+      let digit = hash >> (4 * (GENERATED_LENGTH - 1 - i)) & 0xF;
+      *c = HEX_DIGITS[digit as usize];
+    }
+    SerialNumber::new(&chars).unwrap()
+  }
+
+  pub fn as_bytes(&self) -> &[u8] {
+    &self.chars[..self.length as usize]
+  }
+
+  /// The string descriptor, `bLength` long.
+  pub fn string_descriptor(&self) -> [u8; SERIAL_DESCRIPTOR_MAX] {
+    let mut descriptor = [0; SERIAL_DESCRIPTOR_MAX];
+    descriptor[0] = 2 + 2 * self.length;
+    descriptor[1] = STRING_TYPE;
+    // ASCII is its own UTF-16, with a zero high byte
+    for (unit, &c) in descriptor[2..].chunks_mut(2).zip(self.as_bytes()) {
+      unit[0] = c;
+    }
+    descriptor
+  }
+
+  pub fn to_bytes(&self) -> [u8; SERIAL_IMAGE_SIZE] {
+    let mut bytes = [0; SERIAL_IMAGE_SIZE];
+    bytes[0] = MAGIC[0];
+    bytes[1] = MAGIC[1];
+    bytes[2] = self.length;
+    bytes[HEADER_SIZE..HEADER_SIZE + SERIAL_NUMBER_MAX].copy_from_slice(&self.chars);
+    bytes[SERIAL_IMAGE_SIZE - 1] = checksum(&bytes[..SERIAL_IMAGE_SIZE - 1]);
+    bytes
+  }
+
+  /// Decodes a serial number image, returning `None` if it was never
+  /// written.
+  pub fn from_bytes(bytes: &[u8; SERIAL_IMAGE_SIZE]) -> Option<SerialNumber> {
+    if bytes[0..2] != MAGIC || checksum(&bytes[..SERIAL_IMAGE_SIZE - 1]) != bytes[SERIAL_IMAGE_SIZE - 1] {
+      return None;
+    }
+
+    let length = bytes[2] as usize;
+    if length > SERIAL_NUMBER_MAX {
+      return None;
+    }
+    SerialNumber::new(&bytes[HEADER_SIZE..HEADER_SIZE + length])
+  }
+}
+
+/// Whether a signature row holds an id. Chips without one read back erased
+/// or zeroed bytes.
+pub fn is_usable_id(id: &[u8]) -> bool {
+  !id.iter().all(|&byte| byte == 0xFF) && !id.iter().all(|&byte| byte == 0)
+}
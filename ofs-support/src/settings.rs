@@ -0,0 +1,191 @@
+use crate::analog::{AxisCalibration, StickCalibration};
+use crate::fightstick::{Button, Fightstick};
+use crate::input::{PhysicalButtons, MAX_PHYSICAL_BUTTONS};
+
+/// Persisted for a physical button that does not drive any logical button.
+const UNMAPPED: u8 = 0xFF;
+/// Persisted for a measurement that was never taken.
+const UNSET: u8 = 0xFF;
+
+pub const PROFILE_COUNT: usize = 4;
+
+const MAGIC: [u8; 2] = [0x4f, 0x46];
+const VERSION: u8 = 4;
+
+const FLAG_LOCKED: u8 = 1 << 0;
+
+const HEADER_SIZE: usize = 5;
+const PROFILE_SIZE: usize = MAX_PHYSICAL_BUTTONS;
+const PROFILES_START: usize = HEADER_SIZE;
+const STICK_SIZE: usize = 13;
+const STICKS_START: usize = PROFILES_START + PROFILE_COUNT * PROFILE_SIZE;
+
+pub const STICK_COUNT: usize = 2;
+
+/// Size of the persisted settings image, including header and checksum.
+pub const SETTINGS_SIZE: usize = STICKS_START + STICK_COUNT * STICK_SIZE + 1;
+
+/// Maps every physical button to the logical button it reports as.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Profile {
+  pub map: [Option<Button>; MAX_PHYSICAL_BUTTONS],
+}
+
+impl Profile {
+  pub const fn new(map: [Option<Button>; MAX_PHYSICAL_BUTTONS]) -> Profile {
+    Profile { map }
+  }
+
+  pub fn logical(&self, physical: u8) -> Option<Button> {
+    self.map.get(physical as usize).copied().flatten()
+  }
+
+  pub fn assign(&mut self, physical: u8, logical: Button) {
+    if let Some(slot) = self.map.get_mut(physical as usize) {
+      *slot = Some(logical);
+    }
+  }
+
+  /// Presses the logical buttons of `fightstick` for every held physical
+  /// button.
+  pub fn apply(&self, physical: PhysicalButtons, fightstick: &mut Fightstick) {
+    for index in physical.iter() {
+      if let Some(logical) = self.logical(index) {
+        fightstick.set_button(logical, true);
+      }
+    }
+  }
+
+  fn write(&self, bytes: &mut [u8]) {
+    for (byte, logical) in bytes.iter_mut().zip(self.map.iter()) {
+      *byte = logical.map_or(UNMAPPED, |button| button as u8);
+    }
+  }
+
+  fn read(bytes: &[u8]) -> Profile {
+    let mut profile = Profile::new([None; MAX_PHYSICAL_BUTTONS]);
+    for (logical, &byte) in profile.map.iter_mut().zip(bytes.iter()) {
+      *logical = Button::from_u8(byte);
+    }
+    profile
+  }
+}
+
+/// Everything the controller keeps across power cycles.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Settings {
+  pub active_profile: u8,
+  pub profiles: [Profile; PROFILE_COUNT],
+  /// Tournament lock, see [`crate::lock`].
+  pub locked: bool,
+  /// Analog calibration, see [`crate::calibration`].
+  pub sticks: [StickCalibration; STICK_COUNT],
+}
+
+impl Settings {
+  pub const fn new(default_profile: Profile) -> Settings {
+    Settings {
+      active_profile: 0,
+      profiles: [default_profile; PROFILE_COUNT],
+      locked: false,
+      sticks: [StickCalibration::DEFAULT; STICK_COUNT],
+    }
+  }
+
+  pub fn active_profile(&self) -> &Profile {
+    &self.profiles[self.active_profile as usize % PROFILE_COUNT]
+  }
+
+  pub fn active_profile_mut(&mut self) -> &mut Profile {
+    &mut self.profiles[self.active_profile as usize % PROFILE_COUNT]
+  }
+
+  pub fn to_bytes(&self) -> [u8; SETTINGS_SIZE] {
+    let mut bytes = [0; SETTINGS_SIZE];
+    bytes[0] = MAGIC[0];
+    bytes[1] = MAGIC[1];
+    bytes[2] = VERSION;
+    bytes[3] = self.active_profile;
+    bytes[4] = if self.locked { FLAG_LOCKED } else { 0 };
+
+    for (i, profile) in self.profiles.iter().enumerate() {
+      let start = PROFILES_START + i * PROFILE_SIZE;
+      profile.write(&mut bytes[start..start + PROFILE_SIZE]);
+    }
+
+    for (i, stick) in self.sticks.iter().enumerate() {
+      let start = STICKS_START + i * STICK_SIZE;
+      write_stick(stick, &mut bytes[start..start + STICK_SIZE]);
+    }
+
+    bytes[SETTINGS_SIZE - 1] = checksum(&bytes[..SETTINGS_SIZE - 1]);
+    bytes
+  }
+
+  /// Decodes a settings image, returning `None` if it was never written or
+  /// does not match this firmware's layout.
+  pub fn from_bytes(bytes: &[u8; SETTINGS_SIZE]) -> Option<Settings> {
+    if bytes[0..2] != MAGIC || bytes[2] != VERSION {
+      return None;
+    }
+
+    if checksum(&bytes[..SETTINGS_SIZE - 1]) != bytes[SETTINGS_SIZE - 1] {
+      return None;
+    }
+
+    let active_profile = bytes[3];
+    if active_profile as usize >= PROFILE_COUNT {
+      return None;
+    }
+
+    let mut profiles = [Profile::new([None; MAX_PHYSICAL_BUTTONS]); PROFILE_COUNT];
+    for (i, profile) in profiles.iter_mut().enumerate() {
+      let start = PROFILES_START + i * PROFILE_SIZE;
+      *profile = Profile::read(&bytes[start..start + PROFILE_SIZE]);
+    }
+
+    let mut sticks = [StickCalibration::DEFAULT; STICK_COUNT];
+    for (i, stick) in sticks.iter_mut().enumerate() {
+      let start = STICKS_START + i * STICK_SIZE;
+      *stick = read_stick(&bytes[start..start + STICK_SIZE]);
+    }
+
+    Some(Settings {
+      active_profile,
+      profiles,
+      locked: bytes[4] & FLAG_LOCKED != 0,
+      sticks,
+    })
+  }
+}
+
+fn write_stick(stick: &StickCalibration, bytes: &mut [u8]) {
+  for (axis, bytes) in [stick.x, stick.y].iter().zip(bytes.chunks_mut(6)) {
+    bytes[0..2].copy_from_slice(&axis.min.to_le_bytes());
+    bytes[2..4].copy_from_slice(&axis.center.to_le_bytes());
+    bytes[4..6].copy_from_slice(&axis.max.to_le_bytes());
+  }
+  bytes[12] = stick.deadzone.unwrap_or(UNSET);
+}
+
+fn read_stick(bytes: &[u8]) -> StickCalibration {
+  let word = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+  let axis = |offset: usize| AxisCalibration {
+    min: word(offset),
+    center: word(offset + 2),
+    max: word(offset + 4),
+  };
+
+  StickCalibration {
+    x: axis(0),
+    y: axis(6),
+    deadzone: match bytes[12] {
+      UNSET => None,
+      percent => Some(percent),
+    },
+  }
+}
+
+pub(crate) fn checksum(bytes: &[u8]) -> u8 {
+  bytes.iter().fold(0u8, |sum, byte| sum.rotate_left(1) ^ byte)
+}
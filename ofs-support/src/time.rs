@@ -0,0 +1,125 @@
+//! Monotonic millisecond time shared by both firmwares.
+//!
+//! An [`Instant`] is a wrapping count of milliseconds since startup, so it
+//! rolls over after about 49 days. Comparisons go through
+//! [`Instant::duration_since`], which stays correct across a rollover as long
+//! as the two instants are less than half the range apart.
+
+use core::cell::Cell;
+use core::ops::{Add, Sub};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Debug)]
+pub struct Duration(u32);
+
+impl Duration {
+  pub const ZERO: Duration = Duration(0);
+
+  pub const fn from_millis(millis: u32) -> Duration {
+    Duration(millis)
+  }
+
+  pub const fn from_secs(secs: u32) -> Duration {
+    Duration(secs * 1000)
+  }
+
+  pub const fn as_millis(&self) -> u32 {
+    self.0
+  }
+
+  /// Milliseconds for the `u16` timers of the configuration modes,
+  /// saturating.
+  pub fn as_millis_u16(&self) -> u16 {
+    self.0.min(u16::MAX as u32) as u16
+  }
+}
+
+impl Add for Duration {
+  type Output = Duration;
+
+  fn add(self, other: Duration) -> Duration {
+    Duration(self.0.saturating_add(other.0))
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Instant(u32);
+
+impl Instant {
+  pub const ZERO: Instant = Instant(0);
+
+  pub const fn from_millis(millis: u32) -> Instant {
+    Instant(millis)
+  }
+
+  pub const fn as_millis(&self) -> u32 {
+    self.0
+  }
+
+  /// Time from `earlier` to `self`, zero if `earlier` is actually later.
+  pub fn duration_since(&self, earlier: Instant) -> Duration {
+    let elapsed = self.0.wrapping_sub(earlier.0);
+    if elapsed > u32::MAX / 2 {
+      Duration::ZERO
+    } else {
+      Duration(elapsed)
+    }
+  }
+
+  /// Whether `now` has reached `self`, for an instant used as a deadline.
+  pub fn has_passed(&self, now: Instant) -> bool {
+    now.0.wrapping_sub(self.0) <= u32::MAX / 2
+  }
+
+  /// Whether `duration` has passed since `self`, as of `now`.
+  pub fn has_elapsed(&self, now: Instant, duration: Duration) -> bool {
+    now.duration_since(*self) >= duration
+  }
+}
+
+impl Add<Duration> for Instant {
+  type Output = Instant;
+
+  fn add(self, duration: Duration) -> Instant {
+    Instant(self.0.wrapping_add(duration.0))
+  }
+}
+
+impl Sub for Instant {
+  type Output = Duration;
+
+  fn sub(self, earlier: Instant) -> Duration {
+    self.duration_since(earlier)
+  }
+}
+
+/// A source of the current time, backed by a hardware timer on the chips and
+/// by [`MockClock`] on the host.
+pub trait Clock {
+  fn now(&self) -> Instant;
+}
+
+/// A clock that only moves when told to.
+#[derive(Default)]
+pub struct MockClock {
+  now: Cell<Instant>,
+}
+
+impl MockClock {
+  pub const fn new(start: Instant) -> MockClock {
+    MockClock { now: Cell::new(start) }
+  }
+
+  pub fn advance(&self, duration: Duration) {
+    self.now.set(self.now.get() + duration);
+  }
+
+  pub fn set(&self, now: Instant) {
+    self.now.set(now);
+  }
+}
+
+impl Clock for MockClock {
+  fn now(&self) -> Instant {
+    self.now.get()
+  }
+}
@@ -0,0 +1,120 @@
+//! Compile-time timer configuration.
+//!
+//! Both chips share the same clock select codes and CTC (clear timer on
+//! compare match) mode, so a period or frequency can be turned into a
+//! prescaler and compare value once, in a `const`, instead of hardcoding
+//! register values. A request that no prescaler can reach to within
+//! [`MAX_ERROR_PERCENT`] fails const evaluation, stopping the build.
+
+/// CPU clock of both the controller and the usb chip.
+pub const CPU_HZ: u32 = 16_000_000;
+
+/// Largest compare value of an 8 bit timer.
+pub const TIMER8_TOP: u16 = u8::MAX as u16;
+/// Largest compare value of a 16 bit timer.
+pub const TIMER16_TOP: u16 = u16::MAX;
+
+/// Largest difference allowed between the requested and achieved period.
+pub const MAX_ERROR_PERCENT: u32 = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Prescaler {
+  Div1,
+  Div8,
+  Div64,
+  Div256,
+  Div1024,
+}
+
+impl Prescaler {
+  /// Smallest first, so the first that fits has the finest resolution.
+  pub const ALL: [Prescaler; 5] = [
+    Prescaler::Div1,
+    Prescaler::Div8,
+    Prescaler::Div64,
+    Prescaler::Div256,
+    Prescaler::Div1024,
+  ];
+
+  pub const fn divisor(self) -> u32 {
+    match self {
+      Prescaler::Div1 => 1,
+      Prescaler::Div8 => 8,
+      Prescaler::Div64 => 64,
+      Prescaler::Div256 => 256,
+      Prescaler::Div1024 => 1024,
+    }
+  }
+
+  /// Value of the `CSn2:0` clock select bits.
+  pub const fn clock_select(self) -> u8 {
+    match self {
+      Prescaler::Div1 => 1,
+      Prescaler::Div8 => 2,
+      Prescaler::Div64 => 3,
+      Prescaler::Div256 => 4,
+      Prescaler::Div1024 => 5,
+    }
+  }
+}
+
+/// Prescaler and compare value for a timer in CTC mode. The timer counts
+/// `0..=compare`, so one period is `compare + 1` ticks.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimerConfig {
+  pub prescaler: Prescaler,
+  pub compare: u16,
+}
+
+impl TimerConfig {
+  /// A timer firing `hz` times a second.
+  pub const fn from_hz(cpu_hz: u32, hz: u32, top: u16) -> TimerConfig {
+    TimerConfig::from_cycles(cpu_hz / hz, top)
+  }
+
+  /// A timer firing every `period_us` microseconds.
+  pub const fn from_period_us(cpu_hz: u32, period_us: u32, top: u16) -> TimerConfig {
+    TimerConfig::from_cycles((cpu_hz as u64 * period_us as u64 / 1_000_000) as u32, top)
+  }
+
+  const fn from_cycles(cycles: u32, top: u16) -> TimerConfig {
+    let mut index = 0;
+    while index < Prescaler::ALL.len() {
+      let prescaler = Prescaler::ALL[index];
+      let divisor = prescaler.divisor();
+      let ticks = (cycles + divisor / 2) / divisor;
+
+      if ticks >= 1 && ticks - 1 <= top as u32 {
+        let achieved = (ticks * divisor) as u64 * 100;
+        let (low, high) = (
+          cycles as u64 * (100 - MAX_ERROR_PERCENT) as u64,
+          cycles as u64 * (100 + MAX_ERROR_PERCENT) as u64,
+        );
+        if achieved < low || achieved > high {
+          break;
+        }
+
+        return TimerConfig {
+          prescaler,
+          compare: (ticks - 1) as u16,
+        };
+      }
+      index += 1;
+    }
+
+    // Indexing past the end fails const evaluation, so an impossible timer
+    // is a compile error rather than a wrong period
+    let unreachable: [TimerConfig; 0] = [];
+    unreachable[cycles as usize]
+  }
+
+  /// Length of one timer tick in nanoseconds.
+  pub const fn tick_ns(&self, cpu_hz: u32) -> u32 {
+    (self.prescaler.divisor() as u64 * 1_000_000_000 / cpu_hz as u64) as u32
+  }
+
+  /// Length of one full period in microseconds.
+  pub const fn period_us(&self, cpu_hz: u32) -> u32 {
+    ((self.compare as u64 + 1) * self.prescaler.divisor() as u64 * 1_000_000 / cpu_hz as u64) as u32
+  }
+}
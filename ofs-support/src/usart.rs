@@ -1,19 +1,31 @@
 pub enum UsartCommand {
   Introduction,
   SendData,
+  /// The host suspended the bus, the controller should idle.
+  Suspend,
+  /// The host resumed the bus.
+  Resume,
+  /// From the controller, a button was pressed while suspended.
+  Wakeup,
   Unknown,
 }
 
 pub const INTRODUCTION: u8 = 0x30;
 pub const SEND_DATA: u8 = 0x31;
+pub const SUSPEND: u8 = 0x32;
+pub const RESUME: u8 = 0x33;
+pub const WAKEUP: u8 = 0x34;
 pub const UNKNOWN: u8 = 0x00;
 
-impl Into<u8> for UsartCommand {
-  fn into(self) -> u8 {
-    match self {
-      Self::Introduction => INTRODUCTION,
-      Self::SendData => SEND_DATA,
-      Self::Unknown => UNKNOWN,
+impl From<UsartCommand> for u8 {
+  fn from(command: UsartCommand) -> Self {
+    match command {
+      UsartCommand::Introduction => INTRODUCTION,
+      UsartCommand::SendData => SEND_DATA,
+      UsartCommand::Suspend => SUSPEND,
+      UsartCommand::Resume => RESUME,
+      UsartCommand::Wakeup => WAKEUP,
+      UsartCommand::Unknown => UNKNOWN,
     }
   }
 }
@@ -23,6 +35,9 @@ impl From<u8> for UsartCommand {
     match data {
       INTRODUCTION => Self::Introduction,
       SEND_DATA => Self::SendData,
+      SUSPEND => Self::Suspend,
+      RESUME => Self::Resume,
+      WAKEUP => Self::Wakeup,
       _ => Self::Unknown,
     }
   }
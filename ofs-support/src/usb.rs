@@ -0,0 +1,782 @@
+//! Control transfers on endpoint 0 and input reports on the gamepad
+//! endpoint, over any [`UsbController`].
+//!
+//! The firmware runs this against the usb chip from the main loop, and the
+//! host tests run it against `mock::MockUsb`. Waits on the host spin with
+//! interrupts enabled and give up as soon as `aborted` returns true, which
+//! the firmware uses for a pending bus reset. Reports never wait, see
+//! [`send_report`].
+
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use crate::descriptors::{
+  DESCRIPTOR_LIST, ENDPOINT0_CONFIG, ENDPOINT0_SIZE, ENDPOINT_TABLE, GAMEPAD_ENDPOINT, GAMEPAD_INTERFACE, INIT_BYTES,
+  SERIAL_NUMBER_STRING,
+};
+use crate::endpoint::endpoint_mask;
+use crate::fightstick::FightstickDescriptor;
+use crate::hal::{
+  UsbController, EORSTI, EPEN, FIFOCON, NAKINI, RSTDT, RWAL, RXOUTI, RXSTPE, RXSTPI, STALLRQ, STALLRQC, SUSPE, SUSPI,
+  TXINI, WAKEUPE, WAKEUPI,
+};
+use crate::serial::{SerialNumber, SERIAL_NUMBER_MAX, SET_SERIAL_NUMBER};
+use crate::time::{Duration, Instant};
+
+/// HID report types, the high byte of `wValue` in GET_REPORT and SET_REPORT.
+pub const INPUT_REPORT: u8 = 1;
+pub const OUTPUT_REPORT: u8 = 2;
+pub const FEATURE_REPORT: u8 = 3;
+
+/// Recipients, the low bits of `bmRequestType`.
+pub const RECIPIENT_DEVICE: u8 = 0;
+pub const RECIPIENT_INTERFACE: u8 = 1;
+pub const RECIPIENT_ENDPOINT: u8 = 2;
+const RECIPIENT_MASK: u8 = 0x1F;
+
+/// Standard feature selectors, for CLEAR_FEATURE and SET_FEATURE.
+pub const ENDPOINT_HALT: u16 = 0;
+pub const DEVICE_REMOTE_WAKEUP: u16 = 1;
+
+/// Marks an IN endpoint address.
+const ENDPOINT_IN: u8 = 0x80;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RequestType {
+  GetStatus,
+  ClearFeature,
+  SetFeature,
+  SetAddress,
+  GetDescriptor,
+  GetConfiguration,
+  SetConfiguration,
+  GetInterface,
+  SetInterface,
+  HidGetReport,
+  HidSetReport,
+  HidGetIdle,
+  HidSetIdle,
+  HidGetProtocol,
+  HidSetProtocol,
+  VendorSetSerialNumber,
+  Stall,
+}
+
+impl RequestType {
+  pub fn from_u8(request_type: u8, request_num: u8, index: u16) -> RequestType {
+    const INTERFACE: u16 = GAMEPAD_INTERFACE as u16;
+
+    match (request_type, request_num, index) {
+      (0x80, 0, _) | (0x81, 0, _) | (0x82, 0, _) => RequestType::GetStatus,
+      (0x00, 1, _) | (0x01, 1, _) | (0x02, 1, _) => RequestType::ClearFeature,
+      (0x00, 3, _) | (0x01, 3, _) | (0x02, 3, _) => RequestType::SetFeature,
+      (0x00, 5, _) => RequestType::SetAddress,
+      (0x80, 6, _) | (0x81, 6, _) => RequestType::GetDescriptor,
+      (0x80, 8, _) => RequestType::GetConfiguration,
+      (0, 9, _) => RequestType::SetConfiguration,
+      (0x81, 10, _) => RequestType::GetInterface,
+      (0x01, 11, _) => RequestType::SetInterface,
+      (0xA1, 1, INTERFACE) => RequestType::HidGetReport,
+      (0xA1, 2, INTERFACE) => RequestType::HidGetIdle,
+      (0xA1, 3, INTERFACE) => RequestType::HidGetProtocol,
+      (0x21, 9, INTERFACE) => RequestType::HidSetReport,
+      (0x21, 10, INTERFACE) => RequestType::HidSetIdle,
+      (0x21, 11, INTERFACE) => RequestType::HidSetProtocol,
+      (0x40, SET_SERIAL_NUMBER, _) => RequestType::VendorSetSerialNumber,
+      _ => RequestType::Stall,
+    }
+  }
+}
+
+/// The eight bytes of a setup packet.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SetupPacket {
+  pub request_type: u8,
+  pub request: u8,
+  pub value: u16,
+  pub index: u16,
+  pub length: u16,
+}
+
+impl SetupPacket {
+  pub const SIZE: usize = 8;
+
+  pub const fn from_bytes(bytes: [u8; SetupPacket::SIZE]) -> SetupPacket {
+    SetupPacket {
+      request_type: bytes[0],
+      request: bytes[1],
+      value: bytes[2] as u16 | (bytes[3] as u16) << 8,
+      index: bytes[4] as u16 | (bytes[5] as u16) << 8,
+      length: bytes[6] as u16 | (bytes[7] as u16) << 8,
+    }
+  }
+
+  pub fn to_bytes(&self) -> [u8; SetupPacket::SIZE] {
+    [
+      self.request_type,
+      self.request,
+      self.value as u8,
+      (self.value >> 8) as u8,
+      self.index as u8,
+      (self.index >> 8) as u8,
+      self.length as u8,
+      (self.length >> 8) as u8,
+    ]
+  }
+
+  /// Reads a setup packet out of the selected endpoint's FIFO.
+  pub fn read<U: UsbController>(usb: &mut U) -> SetupPacket {
+    let mut bytes = [0; SetupPacket::SIZE];
+    for byte in bytes.iter_mut() {
+      *byte = usb.read_fifo();
+    }
+    SetupPacket::from_bytes(bytes)
+  }
+
+  pub fn request_type(&self) -> RequestType {
+    RequestType::from_u8(self.request_type, self.request, self.index)
+  }
+
+  pub fn recipient(&self) -> u8 {
+    self.request_type & RECIPIENT_MASK
+  }
+
+  /// Report type of a GET_REPORT or SET_REPORT.
+  pub fn report_type(&self) -> u8 {
+    (self.value >> 8) as u8
+  }
+
+  /// Report id of a HID class request, zero for every report. The device does
+  /// not use report ids, so that is the only one it has.
+  pub fn report_id(&self) -> u8 {
+    self.value as u8
+  }
+}
+
+/// Device state set by control transfers, shared with the report task.
+pub struct UsbState {
+  configuration: AtomicU8,
+  idle: AtomicU8,
+  /// Halted endpoints, bit `n` for endpoint `n`. Only control transfers
+  /// change it, so loads and stores are enough without compare and swap.
+  halted: AtomicU8,
+  /// Whether the host allows the device to wake it, set with SET_FEATURE.
+  remote_wakeup: AtomicBool,
+  /// Whether the bus is suspended, following the general usb interrupt.
+  suspended: AtomicBool,
+  /// Whether the firmware last powered down for a suspended bus, following
+  /// [`update_power`].
+  powered_down: AtomicBool,
+}
+
+impl UsbState {
+  pub const fn new() -> UsbState {
+    UsbState {
+      configuration: AtomicU8::new(0),
+      idle: AtomicU8::new(0),
+      halted: AtomicU8::new(0),
+      remote_wakeup: AtomicBool::new(false),
+      suspended: AtomicBool::new(false),
+      powered_down: AtomicBool::new(false),
+    }
+  }
+
+  /// Selected configuration, zero until the host configures the device.
+  pub fn configuration(&self) -> u8 {
+    self.configuration.load(Ordering::Acquire)
+  }
+
+  /// Idle rate in units of 4 ms, zero to only report on change.
+  pub fn idle(&self) -> u8 {
+    self.idle.load(Ordering::Acquire)
+  }
+
+  /// Whether the host has halted `endpoint` with SET_FEATURE.
+  pub fn is_halted(&self, endpoint: u8) -> bool {
+    self.halted.load(Ordering::Acquire) & (1 << endpoint) != 0
+  }
+
+  pub fn remote_wakeup(&self) -> bool {
+    self.remote_wakeup.load(Ordering::Acquire)
+  }
+
+  pub fn is_suspended(&self) -> bool {
+    self.suspended.load(Ordering::Acquire)
+  }
+
+  fn set_halted(&self, endpoint: u8, halted: bool) {
+    let mask = 1 << endpoint;
+    let current = self.halted.load(Ordering::Acquire);
+    let next = if halted { current | mask } else { current & !mask };
+    self.halted.store(next, Ordering::Release);
+  }
+}
+
+impl Default for UsbState {
+  fn default() -> Self {
+    UsbState::new()
+  }
+}
+
+/// The last input report sent and when, deciding when the next one is due
+/// under the idle rate set with SET_IDLE.
+#[derive(Clone, Default, Debug)]
+pub struct ReportCache {
+  last: Option<(FightstickDescriptor, Instant)>,
+}
+
+impl ReportCache {
+  pub const fn new() -> ReportCache {
+    ReportCache { last: None }
+  }
+
+  /// Whether `report` should be sent at `now`. A report that differs from
+  /// the last one sent, or carries relative motion, goes out straight away.
+  /// An unchanged one waits for the idle period, `idle` in units of 4 ms, and
+  /// is never repeated for an idle rate of zero.
+  pub fn is_due(&self, report: &FightstickDescriptor, idle: u8, now: Instant) -> bool {
+    match &self.last {
+      None => true,
+      Some((last, sent_at)) => {
+        if report != last || report.has_relative() {
+          return true;
+        }
+
+        idle != 0 && sent_at.has_elapsed(now, idle_period(idle))
+      },
+    }
+  }
+
+  pub fn sent(&mut self, report: FightstickDescriptor, now: Instant) {
+    self.last = Some((report, now));
+  }
+
+  /// Forgets the last report, so the next one is sent whatever it holds.
+  pub fn clear(&mut self) {
+    self.last = None;
+  }
+}
+
+/// Length of an idle rate given to SET_IDLE.
+pub fn idle_period(idle: u8) -> Duration {
+  Duration::from_millis(idle as u32 * 4)
+}
+
+/// Offers the input report to the host without waiting on it. `peek` gives
+/// the latest report, which is only written once it is due under `cache` and
+/// the gamepad endpoint has a free bank. Otherwise nothing is taken, and the
+/// next call tries again. `take` is called for the report actually written,
+/// so relative motion stays queued until the host can be sent it. Returns
+/// whether a report was sent, and leaves the gamepad endpoint selected.
+pub fn send_report<U, P, T>(
+  usb: &mut U,
+  state: &UsbState,
+  cache: &mut ReportCache,
+  now: Instant,
+  peek: P,
+  take: T,
+) -> bool
+where
+  U: UsbController,
+  P: FnOnce() -> FightstickDescriptor,
+  T: FnOnce() -> FightstickDescriptor,
+{
+  if state.configuration() == 0 {
+    // The first report once configured goes out whatever it holds
+    cache.clear();
+    return false;
+  }
+
+  // The host is not taking reports until it clears the halt, or resumes
+  if state.is_halted(GAMEPAD_ENDPOINT) || state.is_suspended() {
+    return false;
+  }
+
+  if !cache.is_due(&peek(), state.idle(), now) {
+    return false;
+  }
+
+  usb.select_endpoint(GAMEPAD_ENDPOINT);
+  // The host has not taken the last report yet
+  if !usb.is_endpoint_flag_set(RWAL) {
+    return false;
+  }
+
+  let report = take();
+  for data in report.0.iter() {
+    usb.write_fifo(*data);
+  }
+  // Clearing FIFOCON hands the bank to the hardware
+  usb.write_endpoint_flags(!(TXINI | RXOUTI | NAKINI | FIFOCON));
+  cache.sent(report, now);
+  true
+}
+
+/// Sets endpoint 0 back up after the host resets the bus, which also puts
+/// the HID interface back to its defaults.
+pub fn handle_bus_reset<U: UsbController>(usb: &mut U, state: &UsbState) {
+  usb.select_endpoint(0);
+  usb.write_endpoint_control(EPEN);
+  usb.configure_endpoint(ENDPOINT0_CONFIG.config0(), ENDPOINT0_CONFIG.config1());
+  usb.set_endpoint_interrupts(RXSTPE);
+  state.configuration.store(0, Ordering::Release);
+  state.idle.store(0, Ordering::Release);
+  state.halted.store(0, Ordering::Release);
+  state.remote_wakeup.store(false, Ordering::Release);
+}
+
+/// Bus events seen by the general usb interrupt.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct BusEvents {
+  pub reset: bool,
+  pub suspend: bool,
+  pub wakeup: bool,
+}
+
+/// Reads the device interrupts for the general usb interrupt, clearing only
+/// the flags it handles. Suspend and wakeup are enabled in turn, only suspend
+/// while the bus is active and only wakeup while it is suspended, so each
+/// fires once per change. Besides interrupt flags and enables, it only starts
+/// the usb clock again on wakeup, as WAKEUPI can not be cleared while the
+/// clock is frozen.
+pub fn take_bus_events<U: UsbController>(usb: &mut U, state: &UsbState) -> BusEvents {
+  let flags = usb.device_interrupts();
+  let enables = usb.device_interrupt_enables();
+
+  let events = BusEvents {
+    reset: flags & EORSTI != 0,
+    suspend: flags & enables & SUSPI != 0,
+    wakeup: flags & enables & WAKEUPI != 0,
+  };
+
+  let mut handled = flags & EORSTI;
+  if events.suspend {
+    handled |= SUSPI;
+    usb.set_device_interrupt_enables((enables & !SUSPE) | WAKEUPE);
+    state.suspended.store(true, Ordering::Release);
+  } else if events.wakeup {
+    handled |= WAKEUPI;
+    if usb.is_clock_frozen() {
+      usb.freeze_clock(false);
+    }
+    usb.set_device_interrupt_enables((enables & !WAKEUPE) | SUSPE);
+    state.suspended.store(false, Ordering::Release);
+  }
+  if handled != 0 {
+    usb.clear_device_interrupts(handled);
+  }
+
+  events
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PowerChange {
+  Suspended,
+  Resumed,
+}
+
+/// Freezes the usb clock while the bus is suspended and makes sure it runs
+/// once the bus resumes, returning the change made. With `wakeup`, a
+/// suspended bus is woken through remote wakeup instead, if the host enabled
+/// it.
+pub fn update_power<U: UsbController>(usb: &mut U, state: &UsbState, wakeup: bool) -> Option<PowerChange> {
+  let mut suspended = state.is_suspended();
+
+  if wakeup && suspended && state.remote_wakeup() {
+    if usb.is_clock_frozen() {
+      usb.freeze_clock(false);
+    }
+    usb.send_remote_wakeup();
+    // The host answers with resume signalling, which raises WAKEUPI
+    state.suspended.store(false, Ordering::Release);
+    suspended = false;
+  }
+
+  if state.powered_down.load(Ordering::Acquire) == suspended {
+    return None;
+  }
+
+  // The general usb interrupt has usually restarted the clock already, but
+  // may have run before it was frozen
+  if suspended || usb.is_clock_frozen() {
+    usb.freeze_clock(suspended);
+  }
+  state.powered_down.store(suspended, Ordering::Release);
+  if suspended {
+    Some(PowerChange::Suspended)
+  } else {
+    Some(PowerChange::Resumed)
+  }
+}
+
+/// Endpoint 0 while a control transfer is answered. `report` gives the input
+/// report for GET_REPORT, and `serial` is the serial number, which
+/// [`SET_SERIAL_NUMBER`] replaces.
+pub struct ControlEndpoint<'a, U, R, A> {
+  usb: &'a mut U,
+  state: &'a UsbState,
+  serial: &'a mut SerialNumber,
+  report: R,
+  aborted: A,
+}
+
+impl<'a, U, R, A> ControlEndpoint<'a, U, R, A>
+where
+  U: UsbController,
+  R: FnMut() -> FightstickDescriptor,
+  A: Fn() -> bool,
+{
+  pub fn new(
+    usb: &'a mut U,
+    state: &'a UsbState,
+    serial: &'a mut SerialNumber,
+    report: R,
+    aborted: A,
+  ) -> ControlEndpoint<'a, U, R, A> {
+    ControlEndpoint {
+      usb,
+      state,
+      serial,
+      report,
+      aborted,
+    }
+  }
+
+  /// Answers the setup packet waiting on endpoint 0, if there is one,
+  /// returning it.
+  pub fn handle_setup(&mut self) -> Option<SetupPacket> {
+    self.usb.select_endpoint(0);
+    if !self.usb.is_endpoint_flag_set(RXSTPI) {
+      return None;
+    }
+
+    let setup = SetupPacket::read(self.usb);
+    self.usb.write_endpoint_flags(!(RXSTPI | RXOUTI | TXINI));
+
+    let value = setup.value;
+    match setup.request_type() {
+      RequestType::GetDescriptor => self.get_descriptor(value, setup.index, setup.length),
+      RequestType::SetAddress => self.set_address(value),
+      RequestType::SetConfiguration => {
+        if setup.request_type == 0 && value <= 1 {
+          self.set_configuration(value);
+        } else {
+          self.stall();
+        }
+      },
+      RequestType::GetConfiguration => {
+        if setup.request_type == 0x80 {
+          self.send_byte(self.state.configuration());
+        } else {
+          self.stall()
+        }
+      },
+      RequestType::GetStatus => self.get_status(setup),
+      RequestType::ClearFeature => self.set_feature(setup, false),
+      RequestType::SetFeature => self.set_feature(setup, true),
+      RequestType::GetInterface => {
+        if self.has_interface(setup.index) {
+          self.send_data(&[0], setup.length);
+        } else {
+          self.stall();
+        }
+      },
+      RequestType::SetInterface => {
+        // Only alternate setting 0 exists. Selecting it again resets the
+        // interface's endpoint, halt and data toggle included.
+        if self.has_interface(setup.index) && value == 0 {
+          self.set_halt(GAMEPAD_ENDPOINT, false);
+          self.send_in();
+        } else {
+          self.stall();
+        }
+      },
+      RequestType::HidGetReport => self.get_report(setup),
+      RequestType::HidSetReport => self.set_report(setup),
+      RequestType::HidGetIdle => {
+        if setup.report_id() == 0 {
+          self.send_data(&[self.state.idle()], setup.length);
+        } else {
+          self.stall();
+        }
+      },
+      RequestType::HidSetIdle => {
+        if setup.report_id() == 0 {
+          self.state.idle.store((value >> 8) as u8, Ordering::Release);
+          self.send_in();
+        } else {
+          self.stall();
+        }
+      },
+      // Only boot interfaces take GET_PROTOCOL and SET_PROTOCOL (HID 1.11,
+      // 7.2.5 and 7.2.6), and the gamepad interface has no boot subclass
+      RequestType::HidGetProtocol | RequestType::HidSetProtocol => self.stall(),
+      RequestType::VendorSetSerialNumber => self.set_serial_number(setup.length),
+      RequestType::Stall => self.stall(),
+    }
+
+    Some(setup)
+  }
+
+  /// Spins until `ready`, returning false if the transfer was aborted first.
+  fn wait_until<F: FnMut(&mut U) -> bool>(&mut self, mut ready: F) -> bool {
+    loop {
+      if ready(self.usb) {
+        return true;
+      }
+
+      if (self.aborted)() {
+        return false;
+      }
+    }
+  }
+
+  fn send_in(&mut self) {
+    self.usb.clear_endpoint_flags(TXINI);
+  }
+
+  fn wait_in_ready(&mut self) -> bool {
+    self.wait_until(|usb| usb.is_endpoint_flag_set(TXINI))
+  }
+
+  fn wait_for_host_ready(&mut self) -> bool {
+    self.wait_until(|usb| {
+      let flags = usb.endpoint_flags();
+      flags & TXINI == 0 || flags & RXOUTI == 0
+    })
+  }
+
+  fn wait_receive_out(&mut self) -> bool {
+    self.wait_until(|usb| usb.is_endpoint_flag_set(RXOUTI))
+  }
+
+  fn ack_out(&mut self) {
+    self.usb.write_endpoint_flags(!RXOUTI);
+  }
+
+  fn stall(&mut self) {
+    self.usb.write_endpoint_control(STALLRQ | EPEN);
+  }
+
+  /// Writes a single byte reply to the host once endpoint 0 is ready for it.
+  fn send_byte(&mut self, data: u8) {
+    if self.wait_in_ready() {
+      self.usb.write_fifo(data);
+      self.send_in();
+    }
+  }
+
+  /// Sends `data` in the data stage, cut short to the `length` the host
+  /// asked for.
+  fn send_data(&mut self, data: &[u8], length: u16) {
+    let mut len = (length.min(255) as u8).min(data.len() as u8);
+    let mut table_index: u8 = 0;
+    loop {
+      if !self.wait_for_host_ready() {
+        return;
+      }
+      if self.usb.is_endpoint_flag_set(RXOUTI) {
+        return;
+      }
+      let n = ENDPOINT0_SIZE.min(len);
+      for _ in 0..n {
+        self.usb.write_fifo(data[table_index as usize]);
+        table_index += 1;
+      }
+      len -= n;
+      self.send_in();
+
+      if !(len > 0 || n == ENDPOINT0_SIZE) {
+        break;
+      }
+    }
+  }
+
+  fn is_configured(&self) -> bool {
+    self.state.configuration() != 0
+  }
+
+  fn has_interface(&self, index: u16) -> bool {
+    self.is_configured() && index == GAMEPAD_INTERFACE as u16
+  }
+
+  /// The endpoint number for an endpoint address from `wIndex`. Endpoint 0
+  /// answers in either direction, the gamepad endpoint only once configured.
+  fn endpoint(&self, index: u16) -> Option<u8> {
+    match index {
+      0 => Some(0),
+      address if address == ENDPOINT_IN as u16 => Some(0),
+      address if address == (ENDPOINT_IN | GAMEPAD_ENDPOINT) as u16 && self.is_configured() => {
+        Some(GAMEPAD_ENDPOINT)
+      },
+      _ => None,
+    }
+  }
+
+  /// The device is bus powered, so it only reports remote wakeup, bit 1.
+  /// Interfaces have no status bits, and endpoints only the halt bit.
+  fn get_status(&mut self, setup: SetupPacket) {
+    let status = match setup.recipient() {
+      RECIPIENT_DEVICE => Some((self.state.remote_wakeup() as u8) << 1),
+      RECIPIENT_INTERFACE if self.has_interface(setup.index) => Some(0),
+      RECIPIENT_ENDPOINT => self
+        .endpoint(setup.index)
+        .map(|endpoint| self.state.is_halted(endpoint) as u8),
+      _ => None,
+    };
+
+    match status {
+      Some(status) => self.send_data(&[status, 0], setup.length),
+      None => self.stall(),
+    }
+  }
+
+  /// Remote wakeup can be set on the device, and halt on the gamepad endpoint.
+  /// Endpoint 0 does not implement halt, clearing it is accepted and does
+  /// nothing.
+  fn set_feature(&mut self, setup: SetupPacket, set: bool) {
+    if setup.recipient() == RECIPIENT_DEVICE && setup.value == DEVICE_REMOTE_WAKEUP {
+      self.state.remote_wakeup.store(set, Ordering::Release);
+      self.send_in();
+      return;
+    }
+
+    if setup.recipient() != RECIPIENT_ENDPOINT || setup.value != ENDPOINT_HALT {
+      self.stall();
+      return;
+    }
+
+    match self.endpoint(setup.index) {
+      Some(0) if !set => self.send_in(),
+      Some(endpoint) if endpoint != 0 => {
+        self.set_halt(endpoint, set);
+        self.send_in();
+      },
+      _ => self.stall(),
+    }
+  }
+
+  /// Halts an endpoint, or clears the halt and resets its data toggle and
+  /// FIFO. Leaves endpoint 0 selected.
+  fn set_halt(&mut self, endpoint: u8, halt: bool) {
+    self.usb.select_endpoint(endpoint);
+    if halt {
+      self.usb.write_endpoint_control(EPEN | STALLRQ);
+    } else {
+      self.usb.write_endpoint_control(EPEN | STALLRQC | RSTDT);
+      self.usb.reset_endpoints(1 << endpoint);
+    }
+    self.usb.select_endpoint(0);
+    self.state.set_halted(endpoint, halt);
+  }
+
+  fn get_descriptor(&mut self, value: u16, index: u16, length: u16) {
+    if value == 0x0300 | SERIAL_NUMBER_STRING as u16 && index == 0x0409 {
+      let descriptor = self.serial.string_descriptor();
+      self.send_data(&descriptor[..descriptor[0] as usize], length);
+      return;
+    }
+
+    let descriptor_option = DESCRIPTOR_LIST.iter().find(|f| f.value == value && f.index == index);
+    if let Some(descriptor) = descriptor_option {
+      self.send_data(descriptor.data, length);
+      return;
+    }
+    self.stall();
+  }
+
+  /// The input report is the one sent on the gamepad endpoint, the feature
+  /// report is what a PS3 expects of a controller. There is no output report.
+  fn get_report(&mut self, setup: SetupPacket) {
+    match (setup.report_type(), setup.report_id()) {
+      (INPUT_REPORT, 0) => {
+        let report = (self.report)();
+        self.send_data(&report.0, setup.length);
+      },
+      (FEATURE_REPORT, 0) => self.send_data(&INIT_BYTES, setup.length),
+      _ => self.stall(),
+    }
+  }
+
+  /// Feature reports are read and dropped, the PS3 sends some of its own.
+  /// Input reports can not be set and there is no output report.
+  fn set_report(&mut self, setup: SetupPacket) {
+    if setup.report_type() != FEATURE_REPORT {
+      self.stall();
+      return;
+    }
+
+    let mut remaining = setup.length;
+    while remaining > 0 {
+      if !self.wait_receive_out() {
+        return;
+      }
+      let n = remaining.min(ENDPOINT0_SIZE as u16);
+      for _ in 0..n {
+        self.usb.read_fifo();
+      }
+      remaining -= n;
+      self.ack_out();
+    }
+
+    if self.wait_in_ready() {
+      self.send_in();
+    }
+  }
+
+  /// Reads the new serial number out of the data stage, stalling the status
+  /// stage if it is not a valid one.
+  fn set_serial_number(&mut self, length: u16) {
+    let mut chars = [0; SERIAL_NUMBER_MAX];
+    let mut received = 0;
+    while received < length {
+      if !self.wait_receive_out() {
+        return;
+      }
+      let n = (length - received).min(ENDPOINT0_SIZE as u16);
+      for _ in 0..n {
+        let c = self.usb.read_fifo();
+        if let Some(slot) = chars.get_mut(received as usize) {
+          *slot = c;
+        }
+        received += 1;
+      }
+      self.ack_out();
+    }
+
+    let serial = chars.get(..length as usize).and_then(SerialNumber::new);
+    match serial {
+      Some(serial) => {
+        *self.serial = serial;
+        if self.wait_in_ready() {
+          self.send_in();
+        }
+      },
+      None => self.stall(),
+    }
+  }
+
+  fn set_address(&mut self, value: u16) {
+    self.send_in();
+    if self.wait_in_ready() {
+      self.usb.set_address(value as u8);
+    }
+  }
+
+  /// Configuration 1 enables the endpoints, 0 goes back to the address state
+  /// and disables them.
+  fn set_configuration(&mut self, value: u16) {
+    self.state.configuration.store(value as u8, Ordering::Release);
+    self.state.halted.store(0, Ordering::Release);
+    self.send_in();
+    for config in ENDPOINT_TABLE.iter() {
+      self.usb.select_endpoint(config.number);
+      if value == 0 {
+        self.usb.write_endpoint_control(0);
+      } else {
+        self.usb.write_endpoint_control(EPEN);
+        self.usb.configure_endpoint(config.config0(), config.config1());
+      }
+    }
+    self.usb.reset_endpoints(endpoint_mask(&ENDPOINT_TABLE));
+  }
+}
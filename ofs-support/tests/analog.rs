@@ -0,0 +1,137 @@
+//! Analog readings to report axes: oversampling, calibration, deadzones and
+//! curves.
+
+use ofs_support::analog::{
+  process_stick, AxisCalibration, Curve, Deadzone, Oversampler, StickCalibration, StickConfig, AXIS_MAX, RAW_MAX,
+};
+
+const AXIS: AxisCalibration = AxisCalibration {
+  min: 200,
+  center: 2000,
+  max: 3900,
+};
+
+const CALIBRATION: StickCalibration = StickCalibration {
+  x: AXIS,
+  y: AXIS,
+  deadzone: None,
+};
+
+const LINEAR: StickConfig = StickConfig {
+  deadzone: Deadzone::Axial(0),
+  curve: Curve::Linear,
+};
+
+const CURVES: [Curve; 3] = [Curve::Linear, Curve::Quadratic, Curve::Cubic];
+
+#[test]
+fn oversampler_averages_sixteen_conversions() {
+  let mut oversampler = Oversampler::new();
+  for _ in 0..15 {
+    assert_eq!(oversampler.push(1023), None);
+  }
+  assert_eq!(oversampler.push(1023), Some(16 * 1023 / 4));
+
+  // Starts over for the next reading
+  for sample in 0..15 {
+    assert_eq!(oversampler.push(sample), None);
+  }
+  assert_eq!(oversampler.push(15), Some((0..16).sum::<u16>() / 4));
+}
+
+#[test]
+fn centre_and_ends_map_to_the_report_range() {
+  assert_eq!(process_stick([2000, 2000], &CALIBRATION, &LINEAR), (0, 0));
+  assert_eq!(process_stick([200, 3900], &CALIBRATION, &LINEAR), (-128, 127));
+
+  // Past the calibrated ends is still full deflection
+  assert_eq!(process_stick([0, RAW_MAX], &CALIBRATION, &LINEAR), (-128, 127));
+}
+
+#[test]
+fn each_side_scales_against_its_own_range() {
+  assert_eq!(AXIS.normalise(2000 + 950), AXIS_MAX / 2);
+  assert_eq!(AXIS.normalise(2000 - 900), -AXIS_MAX / 2);
+
+  // A collapsed range reads as centred rather than dividing by zero
+  let stuck = AxisCalibration {
+    min: 2000,
+    center: 2000,
+    max: 2000,
+  };
+  assert_eq!(stuck.normalise(0), 0);
+  assert_eq!(stuck.normalise(RAW_MAX), 0);
+}
+
+#[test]
+fn axial_deadzone_edges() {
+  let deadzone = Deadzone::Axial(10);
+  let threshold = AXIS_MAX / 10;
+
+  assert_eq!(deadzone.apply(threshold, -threshold), (0, 0));
+  let (x, y) = deadzone.apply(threshold + 10, -threshold - 10);
+  assert!(x > 0 && x < 100, "{}", x);
+  assert!(y < 0 && y > -100, "{}", y);
+  assert_eq!(deadzone.apply(AXIS_MAX, -AXIS_MAX), (AXIS_MAX, -AXIS_MAX));
+
+  // Each axis on its own, a diagonal inside both thresholds is dropped
+  assert_eq!(deadzone.apply(threshold - 100, threshold - 100), (0, 0));
+}
+
+#[test]
+fn radial_deadzone_edges() {
+  let deadzone = Deadzone::Radial(10);
+  let threshold = AXIS_MAX / 10;
+
+  assert_eq!(deadzone.apply(threshold, 0), (0, 0));
+  assert_eq!(deadzone.apply(0, -threshold), (0, 0));
+  let (x, _) = deadzone.apply(threshold + 10, 0);
+  assert!(x > 0 && x < 100, "{}", x);
+  assert_eq!(deadzone.apply(AXIS_MAX, 0), (AXIS_MAX, 0));
+
+  // The same diagonal the axial deadzone drops is outside the circle, and
+  // keeps its angle
+  let (x, y) = deadzone.apply(threshold - 100, threshold - 100);
+  assert!(x > 0);
+  assert_eq!(x, y);
+}
+
+#[test]
+fn full_deadzone_drops_everything() {
+  for &deadzone in [Deadzone::Axial(100), Deadzone::Radial(100)].iter() {
+    assert_eq!(deadzone.apply(AXIS_MAX, -AXIS_MAX), (0, 0));
+  }
+}
+
+#[test]
+fn measured_deadzone_replaces_the_configured_one() {
+  let config = StickConfig {
+    deadzone: Deadzone::Radial(0),
+    curve: Curve::Linear,
+  };
+  let raw = [2000 + 190, 2000];
+  assert_ne!(process_stick(raw, &CALIBRATION, &config), (0, 0));
+
+  let measured = StickCalibration {
+    deadzone: Some(15),
+    ..CALIBRATION
+  };
+  assert_eq!(process_stick(raw, &measured, &config), (0, 0));
+}
+
+#[test]
+fn curves_keep_their_end_points() {
+  for curve in CURVES.iter() {
+    assert_eq!(curve.apply(0), 0, "{:?}", curve);
+    assert_eq!(curve.apply(AXIS_MAX), AXIS_MAX, "{:?}", curve);
+    assert_eq!(curve.apply(-AXIS_MAX), -AXIS_MAX, "{:?}", curve);
+  }
+
+  // Halfway out, each curve gives less than the one before
+  let half = AXIS_MAX / 2;
+  let shaped: Vec<i16> = CURVES.iter().map(|curve| curve.apply(half)).collect();
+  assert_eq!(shaped[0], half);
+  assert!(shaped[1] < shaped[0] && shaped[2] < shaped[1], "{:?}", shaped);
+  assert_eq!(Curve::Quadratic.apply(-half), -shaped[1]);
+  assert_eq!(Curve::Cubic.apply(-half), -shaped[2]);
+}
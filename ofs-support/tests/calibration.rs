@@ -0,0 +1,200 @@
+//! Guided calibration fed with recorded stick traces.
+
+use ofs_support::analog::AxisCalibration;
+use ofs_support::calibration::{
+  AxisTrace, CalibrationConfig, CalibrationError, CalibrationEvent, CalibrationMachine, CalibrationState,
+  CALIBRATED_AXES,
+};
+use ofs_support::input::PhysicalButtons;
+
+const START: u8 = 8;
+const D_A: u8 = 4;
+
+const SCAN_MS: u16 = 10;
+
+const CONFIG: CalibrationConfig = CalibrationConfig {
+  combo: PhysicalButtons::from_indices(&[START, D_A]),
+  hold_ms: 3000,
+  min_travel_percent: 40,
+};
+
+/// A left stick at rest, as read from a stick with a little jitter. Each
+/// reading is `[left x, left y, right x, right y]`.
+const LEFT_REST: [[u16; 2]; 8] = [
+  [2051, 2040],
+  [2049, 2043],
+  [2053, 2041],
+  [2050, 2044],
+  [2048, 2042],
+  [2052, 2040],
+  [2050, 2043],
+  [2051, 2041],
+];
+
+/// One turn of a left stick around its square gate, from the right edge
+/// anticlockwise.
+const LEFT_SWEEP: [[u16; 2]; 16] = [
+  [3860, 2045],
+  [3870, 3100],
+  [3855, 3905],
+  [3000, 3910],
+  [2050, 3902],
+  [1100, 3908],
+  [262, 3900],
+  [255, 3000],
+  [258, 2041],
+  [261, 1050],
+  [260, 212],
+  [1000, 205],
+  [2049, 210],
+  [3100, 208],
+  [3862, 215],
+  [3865, 1000],
+];
+
+/// Right stick readings while only the left stick is handled.
+const RIGHT_IDLE: [u16; 2] = [2010, 2090];
+
+fn readings(left: [u16; 2], right: [u16; 2]) -> [u16; CALIBRATED_AXES] {
+  [left[0], left[1], right[0], right[1]]
+}
+
+/// Runs a whole attempt: the combo held with the sticks at rest, the sweep,
+/// then the combo released. Returns every event.
+fn calibrate(rest: &[[u16; CALIBRATED_AXES]], sweep: &[[u16; CALIBRATED_AXES]]) -> Vec<CalibrationEvent> {
+  let mut machine = CalibrationMachine::new(CONFIG);
+  let mut events = Vec::new();
+
+  let arming_scans = (CONFIG.hold_ms / SCAN_MS) as usize + 1;
+  for reading in rest.iter().cycle().take(arming_scans) {
+    events.extend(machine.update(CONFIG.combo, *reading, SCAN_MS));
+  }
+  assert_eq!(machine.state(), CalibrationState::Sweeping);
+
+  for reading in sweep.iter() {
+    events.extend(machine.update(CONFIG.combo, *reading, SCAN_MS));
+  }
+  events.extend(machine.update(PhysicalButtons::NONE, sweep[sweep.len() - 1], SCAN_MS));
+  assert!(!machine.is_active());
+  events
+}
+
+fn left_only(left: &[[u16; 2]]) -> Vec<[u16; CALIBRATED_AXES]> {
+  left.iter().map(|&left| readings(left, RIGHT_IDLE)).collect()
+}
+
+#[test]
+fn good_sweep_calibrates_the_stick() {
+  let events = calibrate(&left_only(&LEFT_REST), &left_only(&LEFT_SWEEP));
+
+  let stick = match events[..] {
+    [CalibrationEvent::Started, CalibrationEvent::Calibrated([Some(stick), None])] => stick,
+    _ => panic!("{:?}", events),
+  };
+  assert_eq!(
+    stick.x,
+    AxisCalibration {
+      min: 255,
+      center: 2050,
+      max: 3870,
+    }
+  );
+  assert_eq!(
+    stick.y,
+    AxisCalibration {
+      min: 205,
+      center: 2041,
+      max: 3910,
+    }
+  );
+  // Five counts of noise against roughly 1800 each side
+  assert_eq!(stick.deadzone, Some(1));
+}
+
+#[test]
+fn both_sticks_calibrate_together() {
+  let rest: Vec<_> = LEFT_REST.iter().map(|&left| readings(left, left)).collect();
+  let sweep: Vec<_> = LEFT_SWEEP.iter().map(|&left| readings(left, left)).collect();
+
+  match calibrate(&rest, &sweep)[..] {
+    [CalibrationEvent::Started, CalibrationEvent::Calibrated([Some(left), Some(right)])] => {
+      assert_eq!(left, right)
+    },
+    ref events => panic!("{:?}", events),
+  }
+}
+
+#[test]
+fn untouched_sticks_are_rejected() {
+  let rest = left_only(&LEFT_REST);
+  let events = calibrate(&rest, &rest);
+  assert!(
+    matches!(
+      events[..],
+      [
+        CalibrationEvent::Started,
+        CalibrationEvent::Rejected(CalibrationError::NotMoved { .. })
+      ]
+    ),
+    "{:?}",
+    events
+  );
+
+  // One axis of a stick moved and the other did not
+  let sweep: Vec<_> = LEFT_SWEEP.iter().map(|&[x, _]| readings([x, 2042], RIGHT_IDLE)).collect();
+  assert_eq!(
+    calibrate(&rest, &sweep)[1],
+    CalibrationEvent::Rejected(CalibrationError::NotMoved { axis: 1 })
+  );
+}
+
+#[test]
+fn partial_sweep_is_too_narrow() {
+  // Nudged around the centre rather than rotated to the gate
+  let sweep: Vec<_> = LEFT_SWEEP
+    .iter()
+    .map(|&[x, y]| readings([1700 + x / 6, 1700 + y / 6], RIGHT_IDLE))
+    .collect();
+
+  assert_eq!(
+    calibrate(&left_only(&LEFT_REST), &sweep)[1],
+    CalibrationEvent::Rejected(CalibrationError::RangeTooNarrow { axis: 0 })
+  );
+}
+
+#[test]
+fn held_stick_puts_the_centre_out_of_range() {
+  // Pushed right while the centre was sampled
+  let rest: Vec<_> = LEFT_REST.iter().map(|&[x, y]| readings([x + 1600, y], RIGHT_IDLE)).collect();
+
+  assert_eq!(
+    calibrate(&rest, &left_only(&LEFT_SWEEP))[1],
+    CalibrationEvent::Rejected(CalibrationError::CenterOutOfRange { axis: 0 })
+  );
+}
+
+#[test]
+fn tiny_travel_is_rejected_without_any_minimum() {
+  let mut trace = AxisTrace::new();
+  trace.rest(10);
+  trace.sweep(11);
+  assert_eq!(trace.finish(0, 0), Err(CalibrationError::CenterOutOfRange { axis: 0 }));
+
+  let mut trace = AxisTrace::new();
+  trace.rest(11);
+  trace.sweep(10);
+  trace.sweep(12);
+  assert!(trace.finish(0, 0).is_ok());
+}
+
+#[test]
+fn releasing_early_does_not_start() {
+  let mut machine = CalibrationMachine::new(CONFIG);
+  let rest = readings(LEFT_REST[0], RIGHT_IDLE);
+
+  for _ in 0..(CONFIG.hold_ms / SCAN_MS) - 1 {
+    assert_eq!(machine.update(CONFIG.combo, rest, SCAN_MS), None);
+  }
+  assert_eq!(machine.update(PhysicalButtons::NONE, rest, SCAN_MS), None);
+  assert_eq!(machine.state(), CalibrationState::Idle);
+}
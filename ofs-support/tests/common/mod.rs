@@ -0,0 +1,135 @@
+//! A host that replays setup packets against the control endpoint.
+
+// Each test binary uses a different part of the harness
+#![allow(dead_code)]
+
+use std::cell::Cell;
+use std::vec::Vec;
+
+use ofs_support::fightstick::FightstickDescriptor;
+use ofs_support::hal::{EPEN, RXSTPI, STALLRQ, TXINI};
+use ofs_support::mock::MockUsb;
+use ofs_support::serial::SerialNumber;
+use ofs_support::usb::{handle_bus_reset, ControlEndpoint, SetupPacket, UsbState};
+
+/// Serial number of every host's device.
+pub const SERIAL: &[u8] = b"0123ABCD";
+
+/// Selects configuration 1.
+pub const SET_CONFIGURATION: [u8; 8] = [0x00, 9, 1, 0, 0, 0, 0, 0];
+
+/// Polls of a wait before the transfer is treated as hung.
+const SPIN_LIMIT: u32 = 1000;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Reply {
+  /// IN packets sent by the device, in order. A request without a data stage
+  /// answers with a single zero length packet.
+  Packets(Vec<Vec<u8>>),
+  Stall,
+}
+
+impl Reply {
+  pub fn data(packets: &[&[u8]]) -> Reply {
+    Reply::Packets(packets.iter().map(|packet| packet.to_vec()).collect())
+  }
+
+  /// A status stage with no data.
+  pub fn status() -> Reply {
+    Reply::Packets(vec![Vec::new()])
+  }
+
+  /// `bytes` split into packets the way endpoint 0 sends them.
+  pub fn chunked(bytes: &[u8], packet_size: usize) -> Reply {
+    let mut packets: Vec<Vec<u8>> = bytes.chunks(packet_size).map(|chunk| chunk.to_vec()).collect();
+    // A reply that fills its last packet is ended by a short one
+    match packets.last() {
+      Some(last) if last.len() < packet_size => {},
+      _ => packets.push(Vec::new()),
+    }
+    Reply::Packets(packets)
+  }
+}
+
+pub struct Host {
+  pub usb: MockUsb,
+  pub state: UsbState,
+  /// Input report the device answers GET_REPORT with.
+  pub report: FightstickDescriptor,
+  pub serial: SerialNumber,
+}
+
+impl Host {
+  /// A device just attached, with the bus reset once.
+  pub fn new() -> Host {
+    let mut usb = MockUsb::new();
+    usb.host_takes_in = true;
+    let mut host = Host {
+      usb,
+      state: UsbState::new(),
+      report: FightstickDescriptor::default(),
+      serial: SerialNumber::new(SERIAL).unwrap(),
+    };
+    host.bus_reset();
+    host
+  }
+
+  /// A host that has configured the device.
+  pub fn configured() -> Host {
+    let mut host = Host::new();
+    host.replay(&[(SET_CONFIGURATION, Reply::status())]);
+    host
+  }
+
+  pub fn bus_reset(&mut self) {
+    handle_bus_reset(&mut self.usb, &self.state);
+  }
+
+  /// Sends a setup packet and runs the control endpoint until it answers.
+  pub fn setup(&mut self, packet: [u8; SetupPacket::SIZE]) -> Reply {
+    self.setup_out(packet, &[])
+  }
+
+  /// Sends a setup packet followed by a data stage of OUT packets.
+  pub fn setup_out(&mut self, packet: [u8; SetupPacket::SIZE], data: &[&[u8]]) -> Reply {
+    let endpoint = self.usb.endpoint(0);
+    endpoint.received.extend(packet.iter());
+    endpoint.out_packets.extend(data.iter().map(|packet| packet.to_vec()));
+    endpoint.flags |= RXSTPI | TXINI;
+    endpoint.sent.clear();
+    // A new setup packet clears a stall
+    endpoint.control = EPEN;
+
+    let spins = Cell::new(0);
+    let aborted = || {
+      spins.set(spins.get() + 1);
+      spins.get() > SPIN_LIMIT
+    };
+    let report = self.report.clone();
+    let handled =
+      ControlEndpoint::new(&mut self.usb, &self.state, &mut self.serial, || report.clone(), aborted).handle_setup();
+
+    assert!(spins.get() <= SPIN_LIMIT, "{:02x?} hung waiting on the host", packet);
+    assert_eq!(handled, Some(SetupPacket::from_bytes(packet)));
+
+    let endpoint = self.usb.endpoint(0);
+    assert!(endpoint.received.is_empty(), "{:02x?} left bytes in the FIFO", packet);
+    assert!(
+      endpoint.out_packets.is_empty(),
+      "{:02x?} left OUT packets unread",
+      packet
+    );
+    if endpoint.control & STALLRQ != 0 {
+      Reply::Stall
+    } else {
+      Reply::Packets(endpoint.sent.clone())
+    }
+  }
+
+  /// Replays a capture, checking every reply in turn.
+  pub fn replay(&mut self, capture: &[([u8; SetupPacket::SIZE], Reply)]) {
+    for (packet, expected) in capture.iter() {
+      assert_eq!(&self.setup(*packet), expected, "reply to {:02x?}", packet);
+    }
+  }
+}
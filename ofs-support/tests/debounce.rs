@@ -0,0 +1,88 @@
+//! Edge debouncing of directly wired inputs, and the lever axes they make.
+
+use ofs_support::debounce::{DebounceConfig, Debouncer};
+use ofs_support::input::determine_axis;
+use ofs_support::time::{Duration, Instant};
+
+const CONFIG: DebounceConfig = DebounceConfig {
+  lockout: Duration::from_millis(5),
+};
+
+const LINE: u16 = 1 << 3;
+
+fn at(millis: u32) -> Instant {
+  Instant::from_millis(millis)
+}
+
+#[test]
+fn first_edge_is_taken_straight_away() {
+  let mut debouncer = Debouncer::new(CONFIG);
+  assert!(debouncer.sample(LINE, at(1000)));
+  assert_eq!(debouncer.state(), LINE);
+}
+
+#[test]
+fn bounces_inside_the_lockout_are_ignored() {
+  let mut debouncer = Debouncer::new(CONFIG);
+  debouncer.sample(LINE, at(1000));
+
+  for (millis, raw) in [(1001, 0), (1002, LINE), (1003, 0), (1004, LINE)].iter() {
+    assert!(!debouncer.sample(*raw, at(*millis)));
+    assert_eq!(debouncer.state(), LINE);
+  }
+}
+
+#[test]
+fn release_after_the_lockout_is_taken() {
+  let mut debouncer = Debouncer::new(CONFIG);
+  debouncer.sample(LINE, at(1000));
+
+  // Opened during the lockout, picked up by the first sample after it
+  assert!(!debouncer.sample(0, at(1003)));
+  assert!(debouncer.sample(0, at(1005)));
+  assert_eq!(debouncer.state(), 0);
+
+  // And the next press is its own edge
+  assert!(!debouncer.sample(LINE, at(1007)));
+  assert!(debouncer.sample(LINE, at(1010)));
+  assert_eq!(debouncer.state(), LINE);
+}
+
+#[test]
+fn lines_lock_out_on_their_own() {
+  let other = 1 << 9;
+  let mut debouncer = Debouncer::new(CONFIG);
+  debouncer.sample(LINE, at(1000));
+
+  assert!(debouncer.sample(LINE | other, at(1002)));
+  assert_eq!(debouncer.state(), LINE | other);
+  // The first line settles before the second
+  assert!(debouncer.sample(other, at(1005)));
+  assert_eq!(debouncer.state(), other);
+  assert!(!debouncer.sample(0, at(1006)));
+  assert!(debouncer.sample(0, at(1007)));
+  assert_eq!(debouncer.state(), 0);
+}
+
+#[test]
+fn lockout_holds_across_the_clock_wrapping() {
+  let mut debouncer = Debouncer::new(CONFIG);
+  let before_wrap = at(u32::MAX - 1);
+  assert!(debouncer.sample(LINE, before_wrap));
+
+  // Two milliseconds on, the counter has wrapped to zero
+  assert!(!debouncer.sample(0, before_wrap + Duration::from_millis(2)));
+  assert_eq!(debouncer.state(), LINE);
+  assert!(debouncer.sample(0, before_wrap + CONFIG.lockout));
+  assert_eq!(debouncer.state(), 0);
+}
+
+#[test]
+fn axes_are_positive_right_and_down() {
+  // `determine_axis(right, left)` and `determine_axis(down, up)`, with
+  // whether each direction is held
+  assert_eq!(determine_axis(true, false), 127);
+  assert_eq!(determine_axis(false, true), -127);
+  assert_eq!(determine_axis(true, true), 0);
+  assert_eq!(determine_axis(false, false), 0);
+}
@@ -0,0 +1,179 @@
+//! Descriptors from the builder, parsed back field by field.
+
+use std::string::String;
+use std::vec::Vec;
+
+use ofs_support::descriptor_builder::{
+  string_descriptor, string_descriptor_length, Configuration, DescriptorBuilder, Interface, CONFIGURATION_TYPE,
+  DEVICE_TYPE, ENDPOINT_TYPE, HID_REPORT_TYPE, HID_TYPE, INTERFACE_TYPE, REMOTE_WAKEUP, STRING_TYPE,
+};
+use ofs_support::descriptors::{
+  CONFIG1_DESC, DEVICE_DESCRIPTOR, ENDPOINT0_SIZE, GAMEPAD_CONFIG, GAMEPAD_INTERFACE, HID, HID_REPORT_DESC,
+  MANUFACTURER, PRODUCT, PRODUCT_ID, SERIAL_NUMBER_STRING, VENDOR_ID,
+};
+use ofs_support::endpoint::{Banks, Direction, EndpointConfig, EndpointType};
+
+fn word(bytes: &[u8], at: usize) -> u16 {
+  u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+/// Decodes a string descriptor back to text.
+fn string(descriptor: &[u8]) -> String {
+  assert_eq!(descriptor[0] as usize, descriptor.len());
+  assert_eq!(descriptor[1], STRING_TYPE);
+  let units: Vec<u16> = descriptor[2..].chunks(2).map(|unit| word(unit, 0)).collect();
+  String::from_utf16(&units).unwrap()
+}
+
+/// Splits concatenated descriptors by their `bLength`.
+fn split(mut bytes: &[u8]) -> Vec<&[u8]> {
+  let mut descriptors = Vec::new();
+  while !bytes.is_empty() {
+    let length = bytes[0] as usize;
+    assert!(length >= 2 && length <= bytes.len(), "bad bLength {}", length);
+    descriptors.push(&bytes[..length]);
+    bytes = &bytes[length..];
+  }
+  descriptors
+}
+
+/// Checks a configuration's total length and its interface and endpoint
+/// counts against what follows it.
+fn check_counts(bytes: &[u8]) {
+  let descriptors = split(bytes);
+  assert_eq!(descriptors[0][1], CONFIGURATION_TYPE);
+  assert_eq!(word(descriptors[0], 2) as usize, bytes.len());
+
+  let interfaces: Vec<&&[u8]> = descriptors.iter().filter(|d| d[1] == INTERFACE_TYPE).collect();
+  assert_eq!(descriptors[0][4] as usize, interfaces.len());
+
+  for (n, descriptor) in descriptors.iter().enumerate() {
+    if descriptor[1] != INTERFACE_TYPE {
+      continue;
+    }
+    let endpoints = descriptors[n + 1..]
+      .iter()
+      .take_while(|d| d[1] != INTERFACE_TYPE)
+      .filter(|d| d[1] == ENDPOINT_TYPE)
+      .count();
+    assert_eq!(descriptor[4] as usize, endpoints);
+  }
+}
+
+#[test]
+fn device_descriptor_fields() {
+  let device = &DEVICE_DESCRIPTOR;
+  assert_eq!(split(device).len(), 1);
+  assert_eq!(device[1], DEVICE_TYPE);
+  assert_eq!(word(device, 2), 0x0110);
+  assert_eq!(device[7], ENDPOINT0_SIZE);
+  assert_eq!(word(device, 8), VENDOR_ID);
+  assert_eq!(word(device, 10), PRODUCT_ID);
+  assert_eq!(device[16], SERIAL_NUMBER_STRING);
+  assert_eq!(device[17], 1);
+}
+
+#[test]
+fn configuration_parses_back() {
+  check_counts(&CONFIG1_DESC);
+
+  let descriptors = split(&CONFIG1_DESC);
+  let types: Vec<u8> = descriptors.iter().map(|d| d[1]).collect();
+  assert_eq!(types, [CONFIGURATION_TYPE, INTERFACE_TYPE, HID_TYPE, ENDPOINT_TYPE]);
+
+  let configuration = descriptors[0];
+  assert_eq!(configuration[5], 1);
+  assert_eq!(configuration[7], 0x80 | REMOTE_WAKEUP);
+  assert_eq!(configuration[8], 50);
+
+  let interface = descriptors[1];
+  assert_eq!(interface[2], GAMEPAD_INTERFACE);
+  assert_eq!(interface[5], 0x03);
+}
+
+#[test]
+fn hid_descriptor_lists_the_report_descriptor() {
+  let hid = split(&CONFIG1_DESC)[2];
+  assert_eq!(hid, &HID);
+  assert_eq!(word(hid, 2), 0x0111);
+  assert_eq!(hid[5], 1);
+  assert_eq!(hid[6], HID_REPORT_TYPE);
+  assert_eq!(word(hid, 7) as usize, HID_REPORT_DESC.len());
+}
+
+#[test]
+fn endpoint_matches_its_configuration() {
+  let endpoint = split(&CONFIG1_DESC)[3];
+  assert_eq!(endpoint[2], 0x80 | GAMEPAD_CONFIG.number);
+  assert_eq!(endpoint[3], 0x03);
+  assert_eq!(word(endpoint, 4), GAMEPAD_CONFIG.size as u16);
+  assert_eq!(endpoint[6], 10);
+}
+
+#[test]
+fn counts_follow_what_is_added() {
+  const OUT: EndpointConfig = EndpointConfig::new(2, EndpointType::Bulk, Direction::Out, 16, Banks::Single);
+  const fn interface(number: u8) -> Interface {
+    Interface {
+      number,
+      alternate_setting: 0,
+      class: 0xFF,
+      subclass: 0,
+      protocol: 0,
+      string: 0,
+    }
+  }
+  const BYTES: [u8; 9 + 9 + 7 + 7 + 9 + 7] = DescriptorBuilder::new()
+    .configuration(&Configuration {
+      value: 1,
+      string: 0,
+      attributes: 0,
+      max_power_ma: 500,
+    })
+    .interface(&interface(0))
+    .endpoint(&GAMEPAD_CONFIG, 1)
+    .endpoint(&OUT, 0)
+    .interface(&interface(1))
+    .endpoint(&OUT, 0)
+    .build();
+
+  check_counts(&BYTES);
+  let descriptors = split(&BYTES);
+  assert_eq!(descriptors[0][4], 2);
+  assert_eq!(descriptors[0][8], 250);
+  assert_eq!(descriptors[1][4], 2);
+  assert_eq!(descriptors[4][4], 1);
+  assert_eq!(descriptors[3][2], 2);
+  assert_eq!(descriptors[3][3], 0x02);
+}
+
+#[test]
+fn strings_decode_to_their_text() {
+  assert_eq!(string(&MANUFACTURER), option_env!("OFS_MANUFACTURER").unwrap_or("OFS"));
+  assert_eq!(
+    string(&PRODUCT),
+    option_env!("OFS_PRODUCT").unwrap_or("Open Fight Stick v2a")
+  );
+}
+
+#[test]
+fn ascii_string_is_one_unit_per_character() {
+  const NAME: &str = "OFS";
+  const DESCRIPTOR: [u8; string_descriptor_length(NAME)] = string_descriptor(NAME);
+  assert_eq!(DESCRIPTOR, [8, 3, b'O', 0, b'F', 0, b'S', 0]);
+
+  const EMPTY: [u8; string_descriptor_length("")] = string_descriptor("");
+  assert_eq!(EMPTY, [2, 3]);
+}
+
+#[test]
+fn non_ascii_strings_are_utf16() {
+  // Two and three byte UTF-8, and a surrogate pair
+  const NAME: &str = "Stick é ★ 🕹";
+  const DESCRIPTOR: [u8; string_descriptor_length(NAME)] = string_descriptor(NAME);
+
+  let expected: Vec<u8> = NAME.encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect();
+  assert_eq!(DESCRIPTOR.len(), 2 + expected.len());
+  assert_eq!(&DESCRIPTOR[2..], &expected[..]);
+  assert_eq!(string(&DESCRIPTOR), NAME);
+}
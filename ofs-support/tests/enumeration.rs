@@ -0,0 +1,308 @@
+//! Enumeration sequences modelled on how each host is known to enumerate a
+//! device, replayed against the control endpoint.
+
+mod common;
+
+use common::{Host, Reply, SERIAL};
+use ofs_support::descriptors::{
+  CONFIG1_DESC, DEVICE_DESCRIPTOR, ENDPOINT0_SIZE, GAMEPAD_ENDPOINT, HID, HID_REPORT_DESC, HID_REPORT_DESC_SIZE,
+  INIT_BYTES, MANUFACTURER, PRODUCT,
+};
+use ofs_support::hal::{EPEN, RXSTPE};
+use ofs_support::usb::RequestType;
+
+const PACKET: usize = ENDPOINT0_SIZE as usize;
+const LANGUAGES: [u8; 4] = [4, 3, 0x09, 0x04];
+
+fn get_descriptor(request_type: u8, kind: u8, index: u8, language: u16, length: u16) -> [u8; 8] {
+  [
+    request_type,
+    6,
+    index,
+    kind,
+    language as u8,
+    (language >> 8) as u8,
+    length as u8,
+    (length >> 8) as u8,
+  ]
+}
+
+fn get_device(length: u16) -> [u8; 8] {
+  get_descriptor(0x80, 1, 0, 0, length)
+}
+
+fn get_config(length: u16) -> [u8; 8] {
+  get_descriptor(0x80, 2, 0, 0, length)
+}
+
+fn get_string(index: u8, length: u16) -> [u8; 8] {
+  let language = if index == 0 { 0 } else { 0x0409 };
+  get_descriptor(0x80, 3, index, language, length)
+}
+
+/// The string descriptor of the host's serial number.
+fn serial_string() -> Vec<u8> {
+  let mut descriptor = vec![2 + 2 * SERIAL.len() as u8, 3];
+  for &c in SERIAL.iter() {
+    descriptor.extend_from_slice(&[c, 0]);
+  }
+  descriptor
+}
+
+fn get_report_descriptor(length: u16) -> [u8; 8] {
+  get_descriptor(0x81, 0x22, 0, 0, length)
+}
+
+const SET_ADDRESS: [u8; 8] = [0x00, 5, 12, 0, 0, 0, 0, 0];
+const SET_CONFIGURATION: [u8; 8] = [0x00, 9, 1, 0, 0, 0, 0, 0];
+const GET_CONFIGURATION: [u8; 8] = [0x80, 8, 0, 0, 0, 0, 1, 0];
+const GET_DEVICE_STATUS: [u8; 8] = [0x80, 0, 0, 0, 0, 0, 2, 0];
+const SET_IDLE: [u8; 8] = [0x21, 10, 0, 0, 0, 0, 0, 0];
+
+fn assert_configured(host: &mut Host) {
+  assert_eq!(host.state.configuration(), 1);
+  let endpoint = host.usb.endpoint(GAMEPAD_ENDPOINT);
+  assert_eq!(endpoint.control, EPEN);
+  // Interrupt IN, 32 bytes, two banks
+  assert_eq!(endpoint.config, (0xC1, 0x26));
+}
+
+#[test]
+fn device_descriptor_bytes() {
+  let mut host = Host::new();
+  assert_eq!(
+    host.setup(get_device(18)),
+    Reply::data(&[&[18, 1, 0x10, 0x01, 0, 0, 0, 64, 0xC4, 0x10, 0xC0, 0x82, 0x00, 0x01, 1, 2, 3, 1]])
+  );
+}
+
+#[test]
+fn linux_enumeration() {
+  let mut host = Host::new();
+
+  host.replay(&[(get_device(64), Reply::data(&[&DEVICE_DESCRIPTOR]))]);
+  host.bus_reset();
+
+  host.replay(&[
+    (SET_ADDRESS, Reply::status()),
+    (get_device(18), Reply::data(&[&DEVICE_DESCRIPTOR])),
+    (get_config(9), Reply::data(&[&CONFIG1_DESC[..9]])),
+    (get_config(34), Reply::data(&[&CONFIG1_DESC])),
+    (get_string(0, 255), Reply::data(&[&LANGUAGES])),
+    (get_string(2, 255), Reply::data(&[&PRODUCT])),
+    (get_string(1, 255), Reply::data(&[&MANUFACTURER])),
+    (get_string(3, 255), Reply::data(&[&serial_string()])),
+    (SET_CONFIGURATION, Reply::status()),
+    (SET_IDLE, Reply::status()),
+    (
+      get_report_descriptor(HID_REPORT_DESC_SIZE as u16),
+      Reply::chunked(&HID_REPORT_DESC, PACKET),
+    ),
+  ]);
+
+  assert_eq!(host.usb.address, Some(12));
+  assert_configured(&mut host);
+  assert_eq!(host.state.idle(), 0);
+}
+
+#[test]
+fn windows_enumeration() {
+  let mut host = Host::new();
+
+  host.replay(&[(get_device(64), Reply::data(&[&DEVICE_DESCRIPTOR]))]);
+  host.bus_reset();
+
+  host.replay(&[
+    (SET_ADDRESS, Reply::status()),
+    (get_device(18), Reply::data(&[&DEVICE_DESCRIPTOR])),
+    (get_config(255), Reply::data(&[&CONFIG1_DESC])),
+    // The serial number comes first, Windows keys the device on it
+    (get_string(3, 255), Reply::data(&[&serial_string()])),
+    (get_string(0, 255), Reply::data(&[&LANGUAGES])),
+    (get_string(2, 255), Reply::data(&[&PRODUCT])),
+    // Device qualifier, a full speed only device has none
+    (get_descriptor(0x80, 6, 0, 0, 10), Reply::Stall),
+    (get_device(18), Reply::data(&[&DEVICE_DESCRIPTOR])),
+    (get_config(9), Reply::data(&[&CONFIG1_DESC[..9]])),
+    (get_config(34), Reply::data(&[&CONFIG1_DESC])),
+    (GET_DEVICE_STATUS, Reply::data(&[&[0, 0]])),
+    (SET_CONFIGURATION, Reply::status()),
+    (SET_IDLE, Reply::status()),
+    // Windows asks for 64 bytes more than the HID descriptor lists
+    (
+      get_report_descriptor(HID_REPORT_DESC_SIZE as u16 + 64),
+      Reply::chunked(&HID_REPORT_DESC, PACKET),
+    ),
+  ]);
+
+  assert_eq!(host.usb.address, Some(12));
+  assert_configured(&mut host);
+}
+
+#[test]
+fn macos_enumeration() {
+  let mut host = Host::new();
+  let serial = serial_string();
+
+  host.replay(&[(get_device(8), Reply::data(&[&DEVICE_DESCRIPTOR[..8]]))]);
+  host.bus_reset();
+
+  host.replay(&[
+    (SET_ADDRESS, Reply::status()),
+    (get_device(18), Reply::data(&[&DEVICE_DESCRIPTOR])),
+    (get_config(9), Reply::data(&[&CONFIG1_DESC[..9]])),
+    (get_config(34), Reply::data(&[&CONFIG1_DESC])),
+    // Strings are read twice, for their length and then in full
+    (get_string(0, 2), Reply::data(&[&LANGUAGES[..2]])),
+    (get_string(0, 4), Reply::data(&[&LANGUAGES])),
+    (get_string(2, 2), Reply::data(&[&PRODUCT[..2]])),
+    (get_string(2, PRODUCT.len() as u16), Reply::data(&[&PRODUCT])),
+    (get_string(1, 2), Reply::data(&[&MANUFACTURER[..2]])),
+    (get_string(1, MANUFACTURER.len() as u16), Reply::data(&[&MANUFACTURER])),
+    (get_string(3, 2), Reply::data(&[&serial[..2]])),
+    (get_string(3, serial.len() as u16), Reply::data(&[&serial])),
+    (GET_DEVICE_STATUS, Reply::data(&[&[0, 0]])),
+    (SET_CONFIGURATION, Reply::status()),
+    (SET_IDLE, Reply::status()),
+    (
+      get_report_descriptor(HID_REPORT_DESC_SIZE as u16),
+      Reply::chunked(&HID_REPORT_DESC, PACKET),
+    ),
+  ]);
+
+  assert_configured(&mut host);
+}
+
+#[test]
+fn ps3_probing() {
+  let mut host = Host::new();
+
+  host.replay(&[
+    (SET_ADDRESS, Reply::status()),
+    (get_device(18), Reply::data(&[&DEVICE_DESCRIPTOR])),
+    (get_config(9), Reply::data(&[&CONFIG1_DESC[..9]])),
+    (get_config(34), Reply::data(&[&CONFIG1_DESC])),
+    (SET_CONFIGURATION, Reply::status()),
+    (
+      get_report_descriptor(HID_REPORT_DESC_SIZE as u16),
+      Reply::chunked(&HID_REPORT_DESC, PACKET),
+    ),
+    // GET_REPORT for feature report 0
+    ([0xA1, 1, 0x00, 0x03, 0, 0, 8, 0], Reply::data(&[&INIT_BYTES])),
+  ]);
+
+  assert_configured(&mut host);
+}
+
+#[test]
+fn switch_probing() {
+  let mut host = Host::new();
+
+  host.replay(&[
+    (get_device(64), Reply::data(&[&DEVICE_DESCRIPTOR])),
+    (SET_ADDRESS, Reply::status()),
+    (get_device(18), Reply::data(&[&DEVICE_DESCRIPTOR])),
+    (get_config(34), Reply::data(&[&CONFIG1_DESC])),
+    (get_string(0, 255), Reply::data(&[&LANGUAGES])),
+    (get_string(1, 255), Reply::data(&[&MANUFACTURER])),
+    (get_string(2, 255), Reply::data(&[&PRODUCT])),
+    (get_string(3, 255), Reply::data(&[&serial_string()])),
+    (SET_CONFIGURATION, Reply::status()),
+    (GET_CONFIGURATION, Reply::data(&[&[1]])),
+    (get_descriptor(0x81, 0x21, 0, 0, 9), Reply::data(&[&HID])),
+    (
+      get_report_descriptor(HID_REPORT_DESC_SIZE as u16),
+      Reply::chunked(&HID_REPORT_DESC, PACKET),
+    ),
+    (SET_IDLE, Reply::status()),
+  ]);
+
+  assert_configured(&mut host);
+}
+
+#[test]
+fn full_packet_ends_with_zero_length_packet() {
+  let mut host = Host::new();
+  assert_eq!(
+    host.setup(get_report_descriptor(PACKET as u16)),
+    Reply::data(&[&HID_REPORT_DESC[..PACKET], &[]])
+  );
+}
+
+#[test]
+fn unknown_requests_stall() {
+  let mut host = Host::new();
+
+  host.replay(&[
+    // Vendor request
+    ([0xC0, 0x33, 0, 0, 0, 0, 2, 0], Reply::Stall),
+    // SET_CONFIGURATION addressed to the interface
+    ([0x01, 9, 1, 0, 0, 0, 0, 0], Reply::Stall),
+    // GET_CONFIGURATION with a host to device direction
+    ([0x00, 8, 0, 0, 0, 0, 1, 0], Reply::Stall),
+    // A stall only lasts until the next setup packet
+    (get_device(18), Reply::data(&[&DEVICE_DESCRIPTOR])),
+  ]);
+
+  assert_eq!(host.state.configuration(), 0);
+}
+
+#[test]
+fn bus_reset_unconfigures() {
+  let mut host = Host::new();
+
+  host.replay(&[
+    (SET_ADDRESS, Reply::status()),
+    (SET_CONFIGURATION, Reply::status()),
+    (GET_CONFIGURATION, Reply::data(&[&[1]])),
+  ]);
+  host.bus_reset();
+
+  assert_eq!(host.state.configuration(), 0);
+  let endpoint = host.usb.endpoint(0);
+  assert_eq!(endpoint.control, EPEN);
+  assert_eq!(endpoint.config, (0, 0x32));
+  assert_eq!(endpoint.interrupts, RXSTPE);
+  assert_eq!(host.setup(GET_CONFIGURATION), Reply::data(&[&[0]]));
+}
+
+#[test]
+fn request_types() {
+  let cases = [
+    ((0x80, 6, 0), RequestType::GetDescriptor),
+    ((0x81, 6, 0), RequestType::GetDescriptor),
+    ((0x00, 5, 0), RequestType::SetAddress),
+    ((0x00, 9, 0), RequestType::SetConfiguration),
+    ((0x80, 8, 0), RequestType::GetConfiguration),
+    ((0x80, 0, 0), RequestType::GetStatus),
+    ((0x82, 0, 0x81), RequestType::GetStatus),
+    ((0x02, 1, 0x81), RequestType::ClearFeature),
+    ((0x02, 3, 0x81), RequestType::SetFeature),
+    ((0x81, 10, 0), RequestType::GetInterface),
+    ((0x01, 11, 0), RequestType::SetInterface),
+    // Standard requests with the wrong direction or recipient
+    ((0x80, 5, 0), RequestType::Stall),
+    ((0x00, 6, 0), RequestType::Stall),
+    ((0xA1, 1, 0), RequestType::HidGetReport),
+    ((0xA1, 2, 0), RequestType::HidGetIdle),
+    ((0xA1, 3, 0), RequestType::HidGetProtocol),
+    ((0x21, 9, 0), RequestType::HidSetReport),
+    ((0x21, 10, 0), RequestType::HidSetIdle),
+    ((0x21, 11, 0), RequestType::HidSetProtocol),
+    // Class requests for an interface the device does not have
+    ((0x21, 10, 1), RequestType::Stall),
+    ((0x21, 10, 0x100), RequestType::Stall),
+    ((0xC0, 0x33, 0), RequestType::Stall),
+  ];
+
+  for ((request_type, request, index), expected) in cases.iter() {
+    assert_eq!(
+      RequestType::from_u8(*request_type, *request, *index),
+      *expected,
+      "{:02x} {:02x} {}",
+      request_type,
+      request,
+      index
+    );
+  }
+}
@@ -0,0 +1,184 @@
+//! HID 1.11 class requests on the gamepad interface.
+
+mod common;
+
+use common::{Host, Reply};
+use ofs_support::descriptors::INIT_BYTES;
+use ofs_support::fightstick::FightstickDescriptor;
+use ofs_support::usb::{FEATURE_REPORT, INPUT_REPORT, OUTPUT_REPORT};
+
+fn get_report(report_type: u8, report_id: u8, length: u16) -> [u8; 8] {
+  [0xA1, 1, report_id, report_type, 0, 0, length as u8, (length >> 8) as u8]
+}
+
+fn set_report(report_type: u8, report_id: u8, length: u16) -> [u8; 8] {
+  [0x21, 9, report_id, report_type, 0, 0, length as u8, (length >> 8) as u8]
+}
+
+fn get_idle(report_id: u8) -> [u8; 8] {
+  [0xA1, 2, report_id, 0, 0, 0, 1, 0]
+}
+
+fn set_idle(duration: u8, report_id: u8) -> [u8; 8] {
+  [0x21, 10, report_id, duration, 0, 0, 0, 0]
+}
+
+const GET_PROTOCOL: [u8; 8] = [0xA1, 3, 0, 0, 0, 0, 1, 0];
+
+fn set_protocol(protocol: u8) -> [u8; 8] {
+  [0x21, 11, protocol, 0, 0, 0, 0, 0]
+}
+
+#[test]
+fn get_input_report_returns_live_report() {
+  let mut host = Host::configured();
+  host.report = FightstickDescriptor([0x80, 0x7F, 0x01, 0xFF, 0x03, 0b1010_0101, 0b0000_0011, 0x01]);
+
+  assert_eq!(
+    host.setup(get_report(INPUT_REPORT, 0, 8)),
+    Reply::data(&[&[0x80, 0x7F, 0x01, 0xFF, 0x03, 0b1010_0101, 0b0000_0011, 0x01]])
+  );
+
+  host.report = FightstickDescriptor::default();
+  assert_eq!(host.setup(get_report(INPUT_REPORT, 0, 8)), Reply::data(&[&[0; 8]]));
+}
+
+#[test]
+fn get_report_is_cut_to_the_requested_length() {
+  let mut host = Host::configured();
+  host.report = FightstickDescriptor([1, 2, 3, 4, 5, 6, 7, 8]);
+
+  assert_eq!(host.setup(get_report(INPUT_REPORT, 0, 3)), Reply::data(&[&[1, 2, 3]]));
+  assert_eq!(
+    host.setup(get_report(FEATURE_REPORT, 0, 4)),
+    Reply::data(&[&INIT_BYTES[..4]])
+  );
+}
+
+#[test]
+fn get_feature_report_returns_ps3_report() {
+  let mut host = Host::configured();
+  assert_eq!(
+    host.setup(get_report(FEATURE_REPORT, 0, 8)),
+    Reply::data(&[&INIT_BYTES])
+  );
+}
+
+#[test]
+fn get_report_stalls_for_missing_reports() {
+  let mut host = Host::configured();
+
+  host.replay(&[
+    (get_report(OUTPUT_REPORT, 0, 8), Reply::Stall),
+    (get_report(INPUT_REPORT, 1, 8), Reply::Stall),
+    (get_report(FEATURE_REPORT, 0xF2, 8), Reply::Stall),
+    (get_report(0, 0, 8), Reply::Stall),
+  ]);
+}
+
+#[test]
+fn set_feature_report_reads_the_data_stage() {
+  let mut host = Host::configured();
+
+  assert_eq!(
+    host.setup_out(set_report(FEATURE_REPORT, 0xF4, 4), &[&[0x42, 0x0C, 0x00, 0x00]]),
+    Reply::status()
+  );
+
+  let mut long_report = [0u8; 70];
+  for (n, byte) in long_report.iter_mut().enumerate() {
+    *byte = n as u8;
+  }
+  assert_eq!(
+    host.setup_out(
+      set_report(FEATURE_REPORT, 0, 70),
+      &[&long_report[..64], &long_report[64..]]
+    ),
+    Reply::status()
+  );
+}
+
+#[test]
+fn set_report_stalls_for_input_and_output_reports() {
+  let mut host = Host::configured();
+
+  host.replay(&[
+    (set_report(OUTPUT_REPORT, 0, 0), Reply::Stall),
+    (set_report(INPUT_REPORT, 0, 0), Reply::Stall),
+  ]);
+}
+
+#[test]
+fn idle_rate_round_trips() {
+  let mut host = Host::configured();
+
+  host.replay(&[
+    (get_idle(0), Reply::data(&[&[0]])),
+    (set_idle(125, 0), Reply::status()),
+    (get_idle(0), Reply::data(&[&[125]])),
+    (set_idle(0, 0), Reply::status()),
+    (get_idle(0), Reply::data(&[&[0]])),
+  ]);
+}
+
+#[test]
+fn idle_requests_stall_for_report_ids() {
+  let mut host = Host::configured();
+
+  host.replay(&[
+    (set_idle(125, 0), Reply::status()),
+    (set_idle(10, 1), Reply::Stall),
+    (get_idle(1), Reply::Stall),
+    (get_idle(0), Reply::data(&[&[125]])),
+  ]);
+}
+
+#[test]
+fn protocol_requests_stall_without_a_boot_interface() {
+  let mut host = Host::configured();
+
+  host.replay(&[
+    (GET_PROTOCOL, Reply::Stall),
+    (set_protocol(0), Reply::Stall),
+    (set_protocol(1), Reply::Stall),
+    (GET_PROTOCOL, Reply::Stall),
+  ]);
+}
+
+#[test]
+fn bus_reset_restores_hid_defaults() {
+  let mut host = Host::configured();
+
+  host.replay(&[(set_idle(125, 0), Reply::status())]);
+  host.bus_reset();
+
+  assert_eq!(host.state.idle(), 0);
+}
+
+#[test]
+fn class_requests_for_other_interfaces_stall() {
+  let mut host = Host::configured();
+
+  host.replay(&[
+    ([0xA1, 1, 0, INPUT_REPORT, 1, 0, 8, 0], Reply::Stall),
+    ([0xA1, 2, 0, 0, 1, 0, 1, 0], Reply::Stall),
+    ([0x21, 10, 0, 0, 1, 0, 0, 0], Reply::Stall),
+    ([0xA1, 3, 0, 0, 1, 0, 1, 0], Reply::Stall),
+    ([0x21, 11, 0, 0, 1, 0, 0, 0], Reply::Stall),
+  ]);
+}
+
+#[test]
+fn class_requests_check_the_whole_interface_number() {
+  let mut host = Host::configured();
+
+  // Interface 0x100, which is not the gamepad interface in its low byte
+  host.replay(&[
+    ([0xA1, 1, 0, INPUT_REPORT, 0, 1, 8, 0], Reply::Stall),
+    ([0xA1, 2, 0, 0, 0, 1, 1, 0], Reply::Stall),
+    ([0x21, 10, 0, 0, 0, 1, 0, 0], Reply::Stall),
+    ([0xA1, 3, 0, 0, 0, 1, 1, 0], Reply::Stall),
+    ([0x21, 11, 0, 0, 0, 1, 0, 0], Reply::Stall),
+  ]);
+  assert_eq!(host.state.idle(), 0);
+}
@@ -0,0 +1,85 @@
+//! When input reports are sent under the idle rate set with SET_IDLE.
+
+use ofs_support::fightstick::FightstickDescriptor;
+use ofs_support::time::{Clock, Duration, Instant, MockClock};
+use ofs_support::usb::{idle_period, ReportCache};
+
+fn at(millis: u32) -> Instant {
+  Instant::from_millis(millis)
+}
+
+fn pressed() -> FightstickDescriptor {
+  FightstickDescriptor([0, 0, 0, 0, 0, 0b0000_0001, 0, 0])
+}
+
+#[test]
+fn first_report_is_sent() {
+  let cache = ReportCache::new();
+  assert!(cache.is_due(&FightstickDescriptor::default(), 0, at(0)));
+}
+
+#[test]
+fn unchanged_report_is_held_for_idle_zero() {
+  let mut cache = ReportCache::new();
+  cache.sent(FightstickDescriptor::default(), at(0));
+
+  assert!(!cache.is_due(&FightstickDescriptor::default(), 0, at(16)));
+  assert!(!cache.is_due(&FightstickDescriptor::default(), 0, at(60_000)));
+}
+
+#[test]
+fn changed_report_is_sent_straight_away() {
+  let mut cache = ReportCache::new();
+  cache.sent(FightstickDescriptor::default(), at(0));
+
+  assert!(cache.is_due(&pressed(), 0, at(1)));
+  assert!(cache.is_due(&pressed(), 125, at(1)));
+}
+
+#[test]
+fn unchanged_report_repeats_at_the_idle_rate() {
+  let mut cache = ReportCache::new();
+  cache.sent(pressed(), at(100));
+
+  // 125 * 4 ms
+  assert_eq!(idle_period(125).as_millis(), 500);
+  assert!(!cache.is_due(&pressed(), 125, at(599)));
+  assert!(cache.is_due(&pressed(), 125, at(600)));
+
+  cache.sent(pressed(), at(600));
+  assert!(!cache.is_due(&pressed(), 125, at(1099)));
+  assert!(cache.is_due(&pressed(), 125, at(1100)));
+}
+
+#[test]
+fn relative_motion_is_always_sent() {
+  let mut moving = FightstickDescriptor::default();
+  moving.set_dial(1);
+
+  let mut cache = ReportCache::new();
+  cache.sent(moving.clone(), at(0));
+  assert!(cache.is_due(&moving, 0, at(16)));
+}
+
+#[test]
+fn clear_sends_the_next_report() {
+  let mut cache = ReportCache::new();
+  cache.sent(pressed(), at(0));
+  cache.clear();
+
+  assert!(cache.is_due(&pressed(), 0, at(16)));
+}
+
+#[test]
+fn idle_rate_holds_across_the_clock_wrapping() {
+  let clock = MockClock::new(Instant::from_millis(u32::MAX - 100));
+  let mut cache = ReportCache::new();
+  cache.sent(pressed(), clock.now());
+
+  clock.advance(Duration::from_millis(499));
+  assert!(!cache.is_due(&pressed(), 125, clock.now()));
+  clock.advance(Duration::from_millis(1));
+  assert!(cache.is_due(&pressed(), 125, clock.now()));
+  // Past the wrap
+  assert_eq!(clock.now(), Instant::from_millis(399));
+}
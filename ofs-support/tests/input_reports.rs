@@ -0,0 +1,175 @@
+//! Input reports on the gamepad endpoint, sent without waiting on the host.
+
+mod common;
+
+use std::cell::{Cell, RefCell};
+use std::vec::Vec;
+
+use common::{Host, Reply, SET_CONFIGURATION};
+use ofs_support::descriptors::GAMEPAD_ENDPOINT;
+use ofs_support::fightstick::FightstickDescriptor;
+use ofs_support::time::Instant;
+use ofs_support::usb::{send_report, ReportCache};
+
+const POLL_PERIOD_MS: u32 = 16;
+
+fn buttons(bits: u8) -> FightstickDescriptor {
+  FightstickDescriptor([0, 0, 0, 0, 0, bits, 0, 0])
+}
+
+/// A configured host that only takes a report when the test says so.
+fn slow_host() -> Host {
+  let mut host = Host::configured();
+  host.usb.host_takes_in = false;
+  host.usb.take_in(GAMEPAD_ENDPOINT);
+  host
+}
+
+/// Runs one poll with `report` as the latest report, returning whether it was
+/// sent and whether it was taken.
+fn poll(host: &mut Host, cache: &mut ReportCache, report: &FightstickDescriptor, millis: u32) -> (bool, bool) {
+  let taken = Cell::new(false);
+  let sent = send_report(
+    &mut host.usb,
+    &host.state,
+    cache,
+    Instant::from_millis(millis),
+    || report.clone(),
+    || {
+      taken.set(true);
+      report.clone()
+    },
+  );
+  (sent, taken.get())
+}
+
+fn sent(host: &mut Host) -> Vec<Vec<u8>> {
+  host.usb.endpoint(GAMEPAD_ENDPOINT).sent.clone()
+}
+
+#[test]
+fn nothing_is_sent_until_configured() {
+  let mut host = Host::new();
+  let mut cache = ReportCache::new();
+
+  assert_eq!(poll(&mut host, &mut cache, &buttons(1), 0), (false, false));
+  assert!(sent(&mut host).is_empty());
+}
+
+#[test]
+fn report_waits_for_a_free_bank() {
+  let mut host = slow_host();
+  let mut cache = ReportCache::new();
+
+  assert_eq!(poll(&mut host, &mut cache, &buttons(1), 0), (true, true));
+  // The second bank takes a report while the host has yet to read the first
+  assert_eq!(poll(&mut host, &mut cache, &buttons(2), 16), (true, true));
+  // Both banks are full, the third is left untaken
+  assert_eq!(poll(&mut host, &mut cache, &buttons(3), 32), (false, false));
+
+  assert!(host.usb.take_in(GAMEPAD_ENDPOINT));
+  assert_eq!(poll(&mut host, &mut cache, &buttons(3), 48), (true, true));
+  assert_eq!(
+    sent(&mut host),
+    vec![buttons(1).0.to_vec(), buttons(2).0.to_vec(), buttons(3).0.to_vec()]
+  );
+}
+
+#[test]
+fn unchanged_report_is_not_repeated() {
+  let mut host = slow_host();
+  let mut cache = ReportCache::new();
+
+  poll(&mut host, &mut cache, &buttons(1), 0);
+  host.usb.take_in(GAMEPAD_ENDPOINT);
+
+  assert_eq!(poll(&mut host, &mut cache, &buttons(1), 16), (false, false));
+  assert_eq!(sent(&mut host).len(), 1);
+}
+
+#[test]
+fn halted_endpoint_is_not_written() {
+  let mut host = Host::new();
+  let mut cache = ReportCache::new();
+  host.replay(&[
+    (SET_CONFIGURATION, Reply::status()),
+    ([0x02, 3, 0, 0, 0x80 | GAMEPAD_ENDPOINT, 0, 0, 0], Reply::status()),
+  ]);
+  host.usb.log.clear();
+
+  assert_eq!(poll(&mut host, &mut cache, &buttons(1), 0), (false, false));
+  assert!(host.usb.log.is_empty());
+}
+
+/// Buttons change every poll while the host only reads every third one. Each
+/// report sent is the latest at the time, none is sent twice, and the last
+/// one reaches the host once it catches up.
+#[test]
+fn slow_host_gets_the_latest_report() {
+  let mut host = slow_host();
+  let mut cache = ReportCache::new();
+  let mut expected = Vec::new();
+
+  for n in 0..30u32 {
+    let report = buttons(n as u8 / 2);
+    if n % 3 == 0 {
+      host.usb.take_in(GAMEPAD_ENDPOINT);
+    }
+    if poll(&mut host, &mut cache, &report, n * POLL_PERIOD_MS).0 {
+      expected.push(report.0.to_vec());
+    }
+  }
+
+  let last = buttons(29 / 2);
+  for n in 30..33u32 {
+    host.usb.take_in(GAMEPAD_ENDPOINT);
+    if poll(&mut host, &mut cache, &last, n * POLL_PERIOD_MS).0 {
+      expected.push(last.0.to_vec());
+    }
+  }
+
+  let sent = sent(&mut host);
+  assert_eq!(sent, expected);
+  assert_eq!(sent.last(), Some(&last.0.to_vec()));
+  assert!(sent.windows(2).all(|pair| pair[0] != pair[1]));
+}
+
+/// Spinner motion is only taken with a report that is sent, so it all
+/// reaches the host however long the bank stays full.
+#[test]
+fn relative_motion_is_not_lost() {
+  let mut host = slow_host();
+  let mut cache = ReportCache::new();
+  let motion = RefCell::new(FightstickDescriptor::default());
+  let mut total = 0i32;
+
+  for n in 0..40u32 {
+    {
+      let mut motion = motion.borrow_mut();
+      let dial = motion.0[4] as i8 + 1;
+      motion.set_dial(dial);
+      total += 1;
+    }
+    if n % 4 == 0 {
+      host.usb.take_in(GAMEPAD_ENDPOINT);
+    }
+
+    send_report(
+      &mut host.usb,
+      &host.state,
+      &mut cache,
+      Instant::from_millis(n * POLL_PERIOD_MS),
+      || motion.borrow().clone(),
+      || {
+        let mut motion = motion.borrow_mut();
+        let report = motion.clone();
+        motion.clear_relative();
+        report
+      },
+    );
+  }
+
+  let delivered: i32 = sent(&mut host).iter().map(|packet| packet[4] as i8 as i32).sum();
+  let pending = motion.borrow().0[4] as i8 as i32;
+  assert_eq!(delivered + pending, total);
+}
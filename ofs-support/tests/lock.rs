@@ -0,0 +1,101 @@
+//! Tournament lock: the long hold that toggles it, and what it masks and
+//! refuses while engaged.
+
+use ofs_support::fightstick::{Button, Fightstick, STATUS_LOCKED, STATUS_REMAPPING};
+use ofs_support::input::{PhysicalButtons, MAX_PHYSICAL_BUTTONS};
+use ofs_support::lock::{LockConfig, TournamentLock};
+use ofs_support::settings::{Profile, Settings};
+
+const U_D: u8 = 3;
+const D_D: u8 = 7;
+const START: u8 = 8;
+
+const SCAN_MS: u16 = 10;
+
+const CONFIG: LockConfig = LockConfig {
+  combo: PhysicalButtons::from_indices(&[START, U_D, D_D]),
+  hold_ms: 5000,
+  blocked_buttons: Button::mask(&[Button::Start, Button::Select, Button::Home]),
+};
+
+/// Feeds `buttons` for `ms`, one scan at a time, returning every toggle.
+fn hold(lock: &mut TournamentLock, buttons: PhysicalButtons, ms: u16) -> Vec<bool> {
+  (0..ms / SCAN_MS).filter_map(|_| lock.update(buttons, SCAN_MS)).collect()
+}
+
+fn combo() -> PhysicalButtons {
+  CONFIG.combo
+}
+
+#[test]
+fn long_hold_toggles_once_per_hold() {
+  let mut lock = TournamentLock::new(CONFIG);
+
+  assert_eq!(hold(&mut lock, combo(), CONFIG.hold_ms - SCAN_MS), []);
+  assert!(!lock.is_locked());
+  assert_eq!(hold(&mut lock, combo(), SCAN_MS), [true]);
+  assert!(lock.is_locked());
+
+  // Still held, it does not toggle back
+  assert_eq!(hold(&mut lock, combo(), 2 * CONFIG.hold_ms), []);
+  assert!(lock.is_locked());
+
+  hold(&mut lock, PhysicalButtons::NONE, SCAN_MS);
+  assert_eq!(hold(&mut lock, combo(), CONFIG.hold_ms), [false]);
+  assert!(!lock.is_locked());
+}
+
+#[test]
+fn broken_hold_starts_over() {
+  let mut lock = TournamentLock::new(CONFIG);
+
+  assert_eq!(hold(&mut lock, combo(), CONFIG.hold_ms - SCAN_MS), []);
+  assert_eq!(hold(&mut lock, combo().with(0, true), SCAN_MS), []);
+  assert_eq!(hold(&mut lock, combo(), CONFIG.hold_ms - SCAN_MS), []);
+  assert!(!lock.is_locked());
+}
+
+#[test]
+fn locked_masks_system_buttons_and_sets_status() {
+  let mut lock = TournamentLock::new(CONFIG);
+  let mut fightstick = Fightstick::default();
+  for &button in [Button::Start, Button::Select, Button::Home, Button::LightPunch].iter() {
+    fightstick.set_button(button, true);
+  }
+  fightstick.status = STATUS_REMAPPING;
+
+  let mut unlocked = fightstick;
+  lock.apply(&mut unlocked);
+  assert_eq!(unlocked.buttons, fightstick.buttons);
+  assert_eq!(unlocked.status, STATUS_REMAPPING);
+
+  lock.set_locked(true);
+  let mut locked = fightstick;
+  lock.apply(&mut locked);
+  assert_eq!(locked.buttons, Button::LightPunch.bit());
+  assert_eq!(locked.status, STATUS_REMAPPING | STATUS_LOCKED);
+
+  lock.set_locked(false);
+  lock.apply(&mut locked);
+  assert_eq!(locked.status, STATUS_REMAPPING);
+}
+
+#[test]
+fn locked_refuses_configuration() {
+  let mut lock = TournamentLock::new(CONFIG);
+  assert!(lock.allows_configuration());
+
+  hold(&mut lock, combo(), CONFIG.hold_ms);
+  assert!(!lock.allows_configuration());
+}
+
+#[test]
+fn lock_is_persisted() {
+  let mut settings = Settings::new(Profile::new([None; MAX_PHYSICAL_BUTTONS]));
+  settings.locked = true;
+
+  let restored = Settings::from_bytes(&settings.to_bytes()).unwrap();
+  let mut lock = TournamentLock::new(CONFIG);
+  lock.set_locked(restored.locked);
+  assert!(lock.is_locked());
+}
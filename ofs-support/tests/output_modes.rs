@@ -0,0 +1,70 @@
+//! The button numbering of each output mode.
+
+use ofs_support::fightstick::{Button, Fightstick, OutputMode, BUTTON_COUNT, UNREADABLE_FIGHTSTICK};
+
+const GAMEPAD_MODES: [OutputMode; 3] = [OutputMode::Pc, OutputMode::Ps3, OutputMode::Switch];
+
+#[test]
+fn every_button_has_its_own_index() {
+  for &mode in GAMEPAD_MODES.iter() {
+    let mut used = 0u16;
+    for &button in Button::ALL.iter() {
+      let index = mode.usage(button).unwrap();
+      assert!(index < BUTTON_COUNT, "{:?} {:?} is reported at {}", mode, button, index);
+      assert_eq!(used & (1 << index), 0, "{:?} {:?} shares index {}", mode, button, index);
+      used |= 1 << index;
+    }
+  }
+}
+
+#[test]
+fn pc_mode_keeps_the_original_numbering() {
+  // What sticks reported before buttons had names, for the default profile
+  let original = [
+    (Button::LightPunch, 0),  // U_A
+    (Button::MediumPunch, 1), // U_B
+    (Button::HeavyPunch, 4),  // U_C
+    (Button::L1, 2),          // U_D
+    (Button::LightKick, 9),   // D_A
+    (Button::MediumKick, 3),  // D_B
+    (Button::HeavyKick, 6),   // D_C
+    (Button::L2, 7),          // D_D
+    (Button::Start, 8),       // START
+  ];
+
+  for &(button, index) in original.iter() {
+    let mut fightstick = Fightstick::default();
+    fightstick.set_button(button, true);
+    assert_eq!(fightstick.report_buttons(OutputMode::Pc), 1 << index, "{:?}", button);
+  }
+}
+
+#[test]
+fn keyboard_mode_gives_each_key_its_own_usage() {
+  let mut used = Vec::new();
+  for &button in Button::ALL.iter() {
+    if let Some(usage) = OutputMode::Keyboard.usage(button) {
+      assert!(!used.contains(&usage), "{:?} shares usage {:#04x}", button, usage);
+      used.push(usage);
+    }
+  }
+
+  // Left Control, Start on 1 and Capture without a key
+  assert_eq!(OutputMode::Keyboard.usage(Button::LightPunch), Some(0xE0));
+  assert_eq!(OutputMode::Keyboard.usage(Button::Start), Some(0x1E));
+  assert_eq!(OutputMode::Keyboard.usage(Button::Capture), None);
+}
+
+#[test]
+fn keyboard_mode_reports_no_gamepad_buttons() {
+  let mut fightstick = Fightstick::default();
+  for &button in Button::ALL.iter() {
+    fightstick.set_button(button, true);
+  }
+  assert_eq!(fightstick.report_buttons(OutputMode::Keyboard), 0);
+}
+
+#[test]
+fn unreadable_inputs_report_pc_button_1() {
+  assert_eq!(UNREADABLE_FIGHTSTICK.report_buttons(OutputMode::Pc), 1 << 1);
+}
@@ -0,0 +1,130 @@
+//! The quadrature decoder fed synthetic A/B waveforms.
+
+use ofs_support::quadrature::{QuadratureDecoder, Sensitivity};
+
+/// One full cycle of A/B levels turning forwards. Turning backwards is the
+/// same cycle in reverse.
+const FORWARD: [(bool, bool); 4] = [(true, false), (true, true), (false, true), (false, false)];
+
+fn turn(decoder: &mut QuadratureDecoder, steps: &[(bool, bool)]) -> Vec<i8> {
+  steps.iter().map(|&(a, b)| decoder.update(a, b)).collect()
+}
+
+fn forward(decoder: &mut QuadratureDecoder, cycles: usize) {
+  for _ in 0..cycles {
+    turn(decoder, &FORWARD);
+  }
+}
+
+fn reverse(decoder: &mut QuadratureDecoder, cycles: usize) {
+  let mut steps = FORWARD;
+  steps.reverse();
+  // Back through 01, 11 and 10 to 00
+  steps.rotate_left(1);
+  for _ in 0..cycles {
+    turn(decoder, &steps);
+  }
+}
+
+#[test]
+fn forward_counts_up_every_edge() {
+  let mut decoder = QuadratureDecoder::new();
+  assert_eq!(turn(&mut decoder, &FORWARD), [1, 1, 1, 1]);
+  assert_eq!(decoder.pending(), 4);
+}
+
+#[test]
+fn reverse_counts_down_every_edge() {
+  let mut decoder = QuadratureDecoder::new();
+  assert_eq!(
+    turn(&mut decoder, &[(false, true), (true, true), (true, false), (false, false)]),
+    [-1, -1, -1, -1]
+  );
+  assert_eq!(decoder.pending(), -4);
+
+  forward(&mut decoder, 2);
+  reverse(&mut decoder, 1);
+  assert_eq!(decoder.pending(), 0);
+}
+
+#[test]
+fn repeated_levels_do_not_count() {
+  let mut decoder = QuadratureDecoder::new();
+  assert_eq!(turn(&mut decoder, &[(true, false), (true, false), (true, false)]), [1, 0, 0]);
+}
+
+#[test]
+fn skipped_states_are_ignored() {
+  let mut decoder = QuadratureDecoder::new();
+  // 00 to 11 and 11 back to 00 change both lines at once
+  assert_eq!(turn(&mut decoder, &[(true, true), (false, false)]), [0, 0]);
+  // As do 01 to 10 and back
+  assert_eq!(turn(&mut decoder, &[(false, true), (true, false), (false, true)]), [-1, 0, 0]);
+  assert_eq!(decoder.pending(), -1);
+
+  // Decoding picks up from the level it was left at
+  assert_eq!(turn(&mut decoder, &[(false, false)]), [1]);
+}
+
+#[test]
+fn unit_sensitivity_reports_counts() {
+  let mut decoder = QuadratureDecoder::new();
+  forward(&mut decoder, 3);
+  assert_eq!(decoder.take_report(Sensitivity::UNIT), 12);
+  assert_eq!(decoder.take_report(Sensitivity::UNIT), 0);
+
+  reverse(&mut decoder, 1);
+  assert_eq!(decoder.take_report(Sensitivity::UNIT), -4);
+}
+
+#[test]
+fn fine_motion_carries_its_remainder() {
+  let quarter = Sensitivity {
+    numerator: 1,
+    denominator: 4,
+  };
+  let mut decoder = QuadratureDecoder::new();
+
+  // Six counts make one unit with two left over
+  turn(&mut decoder, &FORWARD);
+  turn(&mut decoder, &FORWARD[..2]);
+  assert_eq!(decoder.take_report(quarter), 1);
+  assert_eq!(decoder.take_report(quarter), 0);
+
+  // Two more complete the next unit
+  turn(&mut decoder, &FORWARD[2..]);
+  assert_eq!(decoder.take_report(quarter), 1);
+
+  reverse(&mut decoder, 1);
+  turn(&mut decoder, &[(false, true), (true, true), (true, false)]);
+  assert_eq!(decoder.take_report(quarter), -1);
+  assert_eq!(decoder.take_report(quarter), 0);
+  turn(&mut decoder, &[(false, false)]);
+  assert_eq!(decoder.take_report(quarter), -1);
+}
+
+#[test]
+fn coarse_sensitivity_multiplies() {
+  let triple = Sensitivity {
+    numerator: 3,
+    denominator: 2,
+  };
+  let mut decoder = QuadratureDecoder::new();
+
+  turn(&mut decoder, &FORWARD[..3]);
+  // 9 halves, 4 units and a half left over
+  assert_eq!(decoder.take_report(triple), 4);
+  turn(&mut decoder, &FORWARD[3..]);
+  assert_eq!(decoder.take_report(triple), 2);
+}
+
+#[test]
+fn fast_motion_spills_into_the_next_report() {
+  let mut decoder = QuadratureDecoder::new();
+  forward(&mut decoder, 50);
+
+  // 200 counts
+  assert_eq!(decoder.take_report(Sensitivity::UNIT), 127);
+  assert_eq!(decoder.take_report(Sensitivity::UNIT), 73);
+  assert_eq!(decoder.take_report(Sensitivity::UNIT), 0);
+}
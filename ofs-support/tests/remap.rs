@@ -0,0 +1,155 @@
+//! Remap mode driven through whole interactions, scan by scan.
+
+use ofs_support::fightstick::Button;
+use ofs_support::input::{PhysicalButtons, MAX_PHYSICAL_BUTTONS};
+use ofs_support::remap::{RemapConfig, RemapEvent, RemapMachine, RemapState};
+use ofs_support::settings::{Profile, Settings};
+
+const U_A: u8 = 0;
+const U_B: u8 = 1;
+const D_A: u8 = 4;
+const START: u8 = 8;
+
+const SCAN_MS: u16 = 10;
+
+const CONFIG: RemapConfig = RemapConfig {
+  combo: PhysicalButtons::from_indices(&[START, U_A]),
+  cancel: START,
+  hold_ms: 3000,
+  timeout_ms: 10000,
+};
+
+fn profile() -> Profile {
+  let mut map = [None; MAX_PHYSICAL_BUTTONS];
+  map[U_A as usize] = Some(Button::LightPunch);
+  map[U_B as usize] = Some(Button::MediumPunch);
+  map[D_A as usize] = Some(Button::LightKick);
+  map[START as usize] = Some(Button::Start);
+  Profile::new(map)
+}
+
+fn held(indices: &[u8]) -> PhysicalButtons {
+  PhysicalButtons::from_indices(indices)
+}
+
+/// Feeds `buttons` for `ms`, one scan at a time, returning the events seen.
+fn hold(remap: &mut RemapMachine, profile: &Profile, buttons: PhysicalButtons, ms: u16) -> Vec<RemapEvent> {
+  (0..ms / SCAN_MS)
+    .filter_map(|_| remap.update(buttons, SCAN_MS, profile))
+    .collect()
+}
+
+/// Presses and releases one button.
+fn tap(remap: &mut RemapMachine, profile: &Profile, index: u8) -> Vec<RemapEvent> {
+  let mut events = hold(remap, profile, held(&[index]), SCAN_MS);
+  events.extend(hold(remap, profile, PhysicalButtons::NONE, SCAN_MS));
+  events
+}
+
+fn enter(remap: &mut RemapMachine, profile: &Profile) {
+  assert_eq!(
+    hold(remap, profile, held(&[START, U_A]), CONFIG.hold_ms + SCAN_MS),
+    [RemapEvent::Entered]
+  );
+  hold(remap, profile, PhysicalButtons::NONE, SCAN_MS);
+  assert_eq!(remap.state(), RemapState::AwaitSource);
+}
+
+#[test]
+fn remaps_a_button_and_saves_it() {
+  let mut settings = Settings::new(profile());
+  let mut remap = RemapMachine::new(CONFIG);
+  enter(&mut remap, settings.active_profile());
+  assert!(remap.is_active());
+
+  // D_A should report as light punch
+  assert_eq!(
+    tap(&mut remap, settings.active_profile(), D_A),
+    [RemapEvent::Selected { physical: D_A }]
+  );
+  assert_eq!(remap.state(), RemapState::AwaitTarget { source: D_A });
+  let events = tap(&mut remap, settings.active_profile(), U_A);
+  assert_eq!(
+    events,
+    [RemapEvent::Remapped {
+      physical: D_A,
+      logical: Button::LightPunch,
+    }]
+  );
+  assert_eq!(remap.state(), RemapState::AwaitSource);
+
+  if let RemapEvent::Remapped { physical, logical } = events[0] {
+    settings.active_profile_mut().assign(physical, logical);
+  }
+  let saved = Settings::from_bytes(&settings.to_bytes()).unwrap();
+  assert_eq!(saved.active_profile().logical(D_A), Some(Button::LightPunch));
+  assert_eq!(saved.active_profile().logical(U_A), Some(Button::LightPunch));
+  assert_eq!(saved.active_profile().logical(U_B), Some(Button::MediumPunch));
+
+  assert_eq!(tap(&mut remap, saved.active_profile(), START), [RemapEvent::Exited]);
+  assert!(!remap.is_active());
+}
+
+#[test]
+fn releasing_early_does_not_enter() {
+  let profile = profile();
+  let mut remap = RemapMachine::new(CONFIG);
+
+  assert_eq!(hold(&mut remap, &profile, held(&[START, U_A]), CONFIG.hold_ms - 100), []);
+  assert_eq!(remap.state(), RemapState::Arming);
+  assert_eq!(hold(&mut remap, &profile, held(&[START]), SCAN_MS), []);
+  assert_eq!(remap.state(), RemapState::Idle);
+
+  // The hold starts over
+  assert_eq!(hold(&mut remap, &profile, held(&[START, U_A]), CONFIG.hold_ms - 100), []);
+  assert!(!remap.is_active());
+
+  // Extra buttons held with the combo do not count
+  let mut remap = RemapMachine::new(CONFIG);
+  assert_eq!(
+    hold(&mut remap, &profile, held(&[START, U_A, U_B]), CONFIG.hold_ms + SCAN_MS),
+    []
+  );
+  assert_eq!(remap.state(), RemapState::Idle);
+}
+
+#[test]
+fn cancel_drops_the_selection_then_exits() {
+  let profile = profile();
+  let mut remap = RemapMachine::new(CONFIG);
+  enter(&mut remap, &profile);
+
+  assert_eq!(tap(&mut remap, &profile, D_A), [RemapEvent::Selected { physical: D_A }]);
+  assert_eq!(tap(&mut remap, &profile, START), [RemapEvent::SelectionCancelled]);
+  assert_eq!(remap.state(), RemapState::AwaitSource);
+
+  assert_eq!(tap(&mut remap, &profile, START), [RemapEvent::Exited]);
+  assert_eq!(remap.state(), RemapState::Idle);
+}
+
+#[test]
+fn unmapped_target_cancels_the_selection() {
+  let profile = profile();
+  let mut remap = RemapMachine::new(CONFIG);
+  enter(&mut remap, &profile);
+
+  tap(&mut remap, &profile, D_A);
+  assert_eq!(tap(&mut remap, &profile, 15), [RemapEvent::SelectionCancelled]);
+}
+
+#[test]
+fn times_out_without_presses() {
+  let profile = profile();
+  let mut remap = RemapMachine::new(CONFIG);
+  enter(&mut remap, &profile);
+
+  // Each press starts the timeout over
+  assert_eq!(hold(&mut remap, &profile, PhysicalButtons::NONE, CONFIG.timeout_ms - 100), []);
+  assert_eq!(tap(&mut remap, &profile, D_A), [RemapEvent::Selected { physical: D_A }]);
+  assert_eq!(hold(&mut remap, &profile, PhysicalButtons::NONE, CONFIG.timeout_ms - 100), []);
+  assert!(remap.is_active());
+
+  assert_eq!(hold(&mut remap, &profile, PhysicalButtons::NONE, 100), [RemapEvent::TimedOut]);
+  assert_eq!(remap.state(), RemapState::Idle);
+  assert!(!remap.is_active());
+}
@@ -0,0 +1,147 @@
+//! HID report descriptors from the builder, and the parser reading them back.
+
+use std::vec::Vec;
+
+use ofs_support::descriptors::{HID_REPORT_DESC, HID_REPORT_DESC_SIZE};
+use ofs_support::fightstick::{Fightstick, OutputMode, FIGHTSTICK_DESCRIPTOR_SIZE};
+use ofs_support::report_descriptor::{
+  items, parse, read_item, Collection, Item, ParseError, ReportDescriptorBuilder, Reports, BUTTON, GENERIC_DESKTOP,
+  INPUT, LOGICAL_MAXIMUM, LOGICAL_MINIMUM, USAGE_PAGE, VARIABLE, VENDOR_DEFINED,
+};
+
+/// The report descriptor as it was written out by hand.
+const HAND_WRITTEN: [u8; 79] = [
+  0x05, 0x01, 0x09, 0x04, 0xA1, 0x01, 0xA1, 0x02, 0x15, 0x80, 0x25, 0x7F, 0x05, 0x01, 0x75, 0x08, 0x95, 0x04, 0x09,
+  0x30, 0x09, 0x31, 0x09, 0x32, 0x09, 0x35, 0x81, 0x02, 0xC0, 0x05, 0x01, 0x09, 0x37, 0x15, 0x81, 0x25, 0x7F, 0x75,
+  0x08, 0x95, 0x01, 0x81, 0x06, 0xA1, 0x02, 0x05, 0x09, 0x25, 0x01, 0x15, 0x00, 0x19, 0x01, 0x29, 0x10, 0x95, 0x10,
+  0x75, 0x01, 0x81, 0x02, 0xC0, 0x06, 0x00, 0xFF, 0x09, 0x01, 0x15, 0x00, 0x26, 0xFF, 0x00, 0x75, 0x08, 0x95, 0x01,
+  0x81, 0x02, 0xC0,
+];
+
+const MODES: [OutputMode; 4] = [OutputMode::Pc, OutputMode::Ps3, OutputMode::Switch, OutputMode::Keyboard];
+
+#[test]
+fn builder_matches_the_hand_written_descriptor() {
+  assert_eq!(HID_REPORT_DESC_SIZE, HAND_WRITTEN.len());
+  assert_eq!(HID_REPORT_DESC, HAND_WRITTEN);
+}
+
+#[test]
+fn declared_report_is_the_report_sent() {
+  let reports = parse(&HID_REPORT_DESC).unwrap();
+  assert_eq!(
+    reports,
+    Reports {
+      input_bits: 8 * FIGHTSTICK_DESCRIPTOR_SIZE as u32,
+      output_bits: 0,
+      feature_bits: 0,
+    }
+  );
+
+  let fightstick = Fightstick::default();
+  for &mode in MODES.iter() {
+    assert_eq!(fightstick.to_descriptor(mode).0.len(), reports.input_bytes());
+  }
+  // The UART message is the command and then the report
+  let message = fightstick.to_descriptor(OutputMode::Pc).build_send_data_message();
+  assert_eq!(message.len(), 1 + reports.input_bytes());
+}
+
+#[test]
+fn items_use_the_smallest_size() {
+  const fn report<const N: usize>() -> ReportDescriptorBuilder<N> {
+    ReportDescriptorBuilder::new()
+      .usage_page(VENDOR_DEFINED)
+      .logical_minimum(-128)
+      .logical_maximum(255)
+      .logical_minimum(-32769)
+      .logical_maximum(70000)
+  }
+  const SIZE: usize = report::<0>().len();
+  const BYTES: [u8; SIZE] = report().build();
+
+  assert_eq!(
+    BYTES,
+    [
+      0x06, 0x00, 0xFF, // USAGE_PAGE (Vendor Defined Page 1)
+      0x15, 0x80, // LOGICAL_MINIMUM (-128)
+      0x26, 0xFF, 0x00, // LOGICAL_MAXIMUM (255)
+      0x17, 0xFF, 0x7F, 0xFF, 0xFF, // LOGICAL_MINIMUM (-32769)
+      0x27, 0x70, 0x11, 0x01, 0x00, // LOGICAL_MAXIMUM (70000)
+    ]
+  );
+
+  let items: Vec<Item> = items(&BYTES).map(Result::unwrap).collect();
+  let prefixes: Vec<u8> = items.iter().map(|item| item.prefix).collect();
+  assert_eq!(
+    prefixes,
+    [USAGE_PAGE, LOGICAL_MINIMUM, LOGICAL_MAXIMUM, LOGICAL_MINIMUM, LOGICAL_MAXIMUM]
+  );
+  let values: Vec<i32> = items[1..].iter().map(Item::signed).collect();
+  assert_eq!(values, [-128, 255, -32769, 70000]);
+}
+
+#[test]
+fn parser_sums_fields() {
+  const fn report<const N: usize>() -> ReportDescriptorBuilder<N> {
+    ReportDescriptorBuilder::new()
+      .usage_page(GENERIC_DESKTOP)
+      .collection(Collection::Application)
+      .usage_page(BUTTON)
+      .report_size(1)
+      .report_count(5)
+      .input(VARIABLE)
+      // Padding to the byte
+      .report_count(3)
+      .input(0x01)
+      .report_size(8)
+      .report_count(2)
+      .output(VARIABLE)
+      .feature(VARIABLE)
+      .end_collection()
+  }
+  const BYTES: [u8; report::<0>().len()] = report().build();
+
+  assert_eq!(
+    parse(&BYTES),
+    Ok(Reports {
+      input_bits: 8,
+      output_bits: 16,
+      feature_bits: 16,
+    })
+  );
+}
+
+#[test]
+fn malformed_descriptors_are_rejected() {
+  // LOGICAL_MAXIMUM with two bytes of data and only one left
+  assert_eq!(parse(&[0xA1, 0x01, 0x26, 0xFF]), Err(ParseError::Truncated { at: 2 }));
+  assert_eq!(parse(&[0xFE, 0x00, 0x00]), Err(ParseError::LongItem { at: 0 }));
+  assert_eq!(
+    parse(&[0xA1, 0x01, 0xC0, 0xC0]),
+    Err(ParseError::UnbalancedCollection { at: 3 })
+  );
+  assert_eq!(
+    parse(&[0xA1, 0x01, 0xA1, 0x02, 0xC0]),
+    Err(ParseError::UnbalancedCollection { at: 5 })
+  );
+  assert_eq!(parse(&[0xA1, 0x01, 0x85, 0x01, 0xC0]), Err(ParseError::ReportId { at: 2 }));
+  assert_eq!(parse(&[0xA4]), Err(ParseError::Unsupported { at: 0 }));
+}
+
+#[test]
+fn items_stop_after_an_error() {
+  let items: Vec<Result<Item, ParseError>> = items(&[0x81, 0x02, 0x26, 0xFF]).collect();
+  assert_eq!(
+    items,
+    [
+      Ok(Item {
+        prefix: INPUT,
+        data: 0x02,
+        size: 1,
+      }),
+      Err(ParseError::Truncated { at: 2 }),
+    ]
+  );
+  assert_eq!(read_item(&[0xC0], 0).map(|(item, next)| (item.size, next)), Ok((0, 1)));
+}
@@ -0,0 +1,107 @@
+//! The report layout table, packed into reports and turned into report
+//! descriptors.
+
+use ofs_support::fightstick::{
+  Button, Fightstick, OutputMode, FIGHTSTICK_DESCRIPTOR_SIZE, REPORT_LAYOUT, STATUS_OVER_BUDGET,
+};
+use ofs_support::report_descriptor::{parse, RX, X, Y};
+use ofs_support::report_layout::{
+  clear_relative, field_offset, has_relative, pack, report_descriptor, report_size, Field, FieldKind,
+};
+use ofs_support::usart::UsartCommand;
+
+fn fightstick() -> Fightstick {
+  let mut fightstick = Fightstick {
+    x: -128,
+    y: 127,
+    rx: -1,
+    ry: 1,
+    dial: -5,
+    status: 0xA5,
+    ..Default::default()
+  };
+  fightstick.set_button(Button::LightKick, true);
+  fightstick.set_button(Button::Home, true);
+  fightstick
+}
+
+#[test]
+fn fightstick_packs_in_table_order() {
+  let fightstick = fightstick();
+  let buttons = fightstick.report_buttons(OutputMode::Pc);
+  assert_eq!(buttons, (1 << 9) | (1 << 12));
+
+  let report = fightstick.to_descriptor(OutputMode::Pc);
+  assert_eq!(report.0, [0x80, 0x7F, 0xFF, 0x01, 0xFB, buttons as u8, (buttons >> 8) as u8, 0xA5]);
+  assert_eq!(FIGHTSTICK_DESCRIPTOR_SIZE, report_size(&REPORT_LAYOUT));
+
+  let message = report.build_send_data_message();
+  assert_eq!(message[0], u8::from(UsartCommand::SendData));
+  assert_eq!(&message[1..], &report.0[..]);
+}
+
+#[test]
+fn relative_fields_are_cleared() {
+  let mut report = fightstick().to_descriptor(OutputMode::Pc);
+  assert!(report.has_relative());
+
+  report.clear_relative();
+  assert!(!report.has_relative());
+  assert_eq!(report.0[4], 0);
+  // Everything else is left as it was
+  assert_eq!(report.0[7], 0xA5);
+
+  report.set_dial(3);
+  assert!(report.has_relative());
+}
+
+#[test]
+fn status_bits_add_to_the_controller_ones() {
+  let mut report = fightstick().to_descriptor(OutputMode::Pc);
+  report.add_status(STATUS_OVER_BUDGET);
+  assert_eq!(report.0[7], 0xA5 | STATUS_OVER_BUDGET);
+  assert_eq!(report.0[..7], fightstick().to_descriptor(OutputMode::Pc).0[..7]);
+}
+
+/// A layout with an axis and buttons added, each one line.
+const EXTENDED: [Field; 5] = [
+  Field {
+    kind: FieldKind::Axis(X),
+    value: |fightstick, _| fightstick.x as u8 as u32,
+  },
+  Field {
+    kind: FieldKind::Axis(Y),
+    value: |fightstick, _| fightstick.y as u8 as u32,
+  },
+  Field {
+    kind: FieldKind::Axis(RX),
+    value: |fightstick, _| fightstick.rx as u8 as u32,
+  },
+  Field {
+    kind: FieldKind::Buttons(10),
+    value: |fightstick, mode| fightstick.report_buttons(mode) as u32 & 0x3FF,
+  },
+  Field {
+    kind: FieldKind::Relative(0x37),
+    value: |fightstick, _| fightstick.dial as u8 as u32,
+  },
+];
+
+#[test]
+fn descriptor_follows_the_layout() {
+  const SIZE: usize = report_descriptor::<0>(&EXTENDED).len();
+  const DESCRIPTOR: [u8; SIZE] = report_descriptor(&EXTENDED).build();
+
+  // Ten buttons are padded out to two bytes
+  assert_eq!(report_size(&EXTENDED), 3 + 2 + 1);
+  assert_eq!(parse(&DESCRIPTOR).unwrap().input_bits, 8 * 6);
+  assert_eq!(field_offset(&EXTENDED, FieldKind::Relative(0x37)), 5);
+  assert_eq!(field_offset(&EXTENDED, FieldKind::Axis(0x35)), 6);
+
+  let mut report = [0; 6];
+  pack(&EXTENDED, &fightstick(), OutputMode::Pc, &mut report);
+  assert_eq!(report, [0x80, 0x7F, 0xFF, 0x00, 0x02, 0xFB]);
+  assert!(has_relative(&EXTENDED, &report));
+  clear_relative(&EXTENDED, &mut report);
+  assert_eq!(report, [0x80, 0x7F, 0xFF, 0x00, 0x02, 0x00]);
+}
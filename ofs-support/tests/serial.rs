@@ -0,0 +1,100 @@
+//! The per-stick serial number, its EEPROM image and the vendor request that
+//! sets it.
+
+mod common;
+
+use common::{Host, Reply, SERIAL};
+use ofs_support::descriptors::ENDPOINT0_SIZE;
+use ofs_support::serial::{is_usable_id, SerialNumber, SERIAL_IMAGE_SIZE, SERIAL_NUMBER_MAX, SET_SERIAL_NUMBER};
+
+const PACKET: usize = ENDPOINT0_SIZE as usize;
+
+const GET_SERIAL_STRING: [u8; 8] = [0x80, 6, 3, 3, 0x09, 0x04, 255, 0];
+
+fn set_serial_number(length: usize) -> [u8; 8] {
+  [0x40, SET_SERIAL_NUMBER, 0, 0, 0, 0, length as u8, (length >> 8) as u8]
+}
+
+fn string_descriptor(chars: &[u8]) -> Vec<u8> {
+  let mut descriptor = vec![2 + 2 * chars.len() as u8, 3];
+  for &c in chars.iter() {
+    descriptor.extend_from_slice(&[c, 0]);
+  }
+  descriptor
+}
+
+#[test]
+fn generated_serial_is_eight_hex_digits() {
+  // Lot, wafer and position bytes from two chips
+  let first = SerialNumber::from_id(&[0x59, 0x36, 0x34, 0x31, 0x34, 0xFF, 0x0C, 0x10, 0x0D, 0x10]);
+  let second = SerialNumber::from_id(&[0x59, 0x36, 0x34, 0x31, 0x34, 0xFF, 0x0C, 0x10, 0x0E, 0x10]);
+
+  for serial in [first, second].iter() {
+    assert_eq!(serial.as_bytes().len(), 8);
+    assert!(serial.as_bytes().iter().all(|c| b"0123456789ABCDEF".contains(c)));
+  }
+  assert_ne!(first, second);
+  assert_eq!(first, SerialNumber::from_id(&[0x59, 0x36, 0x34, 0x31, 0x34, 0xFF, 0x0C, 0x10, 0x0D, 0x10]));
+}
+
+#[test]
+fn blank_signature_rows_are_not_ids() {
+  assert!(!is_usable_id(&[0xFF; 10]));
+  assert!(!is_usable_id(&[0; 10]));
+  assert!(is_usable_id(&[0xFF, 0xFF, 0x00, 0xFF]));
+}
+
+#[test]
+fn serial_number_must_be_printable_ascii() {
+  assert!(SerialNumber::new(b"OFS-1").is_some());
+  assert!(SerialNumber::new(&[b'a'; SERIAL_NUMBER_MAX]).is_some());
+
+  assert_eq!(SerialNumber::new(b""), None);
+  assert_eq!(SerialNumber::new(&[b'a'; SERIAL_NUMBER_MAX + 1]), None);
+  assert_eq!(SerialNumber::new(b"tab\there"), None);
+  assert_eq!(SerialNumber::new("é".as_bytes()), None);
+}
+
+#[test]
+fn image_round_trips() {
+  let serial = SerialNumber::new(b"P2").unwrap();
+  let bytes = serial.to_bytes();
+  assert_eq!(SerialNumber::from_bytes(&bytes), Some(serial));
+
+  // Erased EEPROM
+  assert_eq!(SerialNumber::from_bytes(&[0xFF; SERIAL_IMAGE_SIZE]), None);
+
+  let mut corrupted = bytes;
+  corrupted[4] ^= 1;
+  assert_eq!(SerialNumber::from_bytes(&corrupted), None);
+}
+
+#[test]
+fn serial_string_is_answered() {
+  let mut host = Host::new();
+  assert_eq!(host.setup(GET_SERIAL_STRING), Reply::data(&[&string_descriptor(SERIAL)]));
+}
+
+#[test]
+fn vendor_request_sets_the_serial_number() {
+  let mut host = Host::new();
+
+  assert_eq!(host.setup_out(set_serial_number(4), &[b"P2-A"]), Reply::status());
+  assert_eq!(host.serial.as_bytes(), b"P2-A");
+  assert_eq!(host.setup(GET_SERIAL_STRING), Reply::data(&[&string_descriptor(b"P2-A")]));
+}
+
+#[test]
+fn invalid_serial_number_is_refused() {
+  let mut host = Host::new();
+
+  let long = [b'a'; PACKET + 1];
+  assert_eq!(
+    host.setup_out(set_serial_number(long.len()), &[&long[..PACKET], &long[PACKET..]]),
+    Reply::Stall
+  );
+  assert_eq!(host.setup_out(set_serial_number(0), &[]), Reply::Stall);
+  assert_eq!(host.setup_out(set_serial_number(2), &[&[b'a', 0]]), Reply::Stall);
+
+  assert_eq!(host.serial.as_bytes(), SERIAL);
+}
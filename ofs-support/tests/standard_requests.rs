@@ -0,0 +1,244 @@
+//! USB 2.0 chapter 9 standard requests.
+
+mod common;
+
+use common::{Host, Reply};
+use ofs_support::descriptors::{GAMEPAD_ENDPOINT, GAMEPAD_INTERFACE};
+use ofs_support::hal::{EPEN, RSTDT, STALLRQ, STALLRQC};
+use ofs_support::mock::UsbAccess;
+use ofs_support::usb::{DEVICE_REMOTE_WAKEUP, ENDPOINT_HALT};
+
+const GAMEPAD_IN: u8 = 0x80 | GAMEPAD_ENDPOINT;
+
+fn get_status(recipient: u8, index: u8) -> [u8; 8] {
+  [0x80 | recipient, 0, 0, 0, index, 0, 2, 0]
+}
+
+fn clear_feature(recipient: u8, feature: u16, index: u8) -> [u8; 8] {
+  [recipient, 1, feature as u8, (feature >> 8) as u8, index, 0, 0, 0]
+}
+
+fn set_feature(recipient: u8, feature: u16, index: u8) -> [u8; 8] {
+  [recipient, 3, feature as u8, (feature >> 8) as u8, index, 0, 0, 0]
+}
+
+fn get_interface(interface: u8) -> [u8; 8] {
+  [0x81, 10, 0, 0, interface, 0, 1, 0]
+}
+
+fn set_interface(interface: u8, alternate: u8) -> [u8; 8] {
+  [0x01, 11, alternate, 0, interface, 0, 0, 0]
+}
+
+#[test]
+fn device_status_is_bus_powered() {
+  let mut host = Host::new();
+  assert_eq!(host.setup(get_status(0, 0)), Reply::data(&[&[0, 0]]));
+
+  let mut host = Host::configured();
+  assert_eq!(host.setup(get_status(0, 0)), Reply::data(&[&[0, 0]]));
+}
+
+#[test]
+fn remote_wakeup_is_reported_in_device_status() {
+  let mut host = Host::configured();
+
+  host.replay(&[
+    (set_feature(0, DEVICE_REMOTE_WAKEUP, 0), Reply::status()),
+    (get_status(0, 0), Reply::data(&[&[0b10, 0]])),
+  ]);
+  assert!(host.state.remote_wakeup());
+
+  host.replay(&[
+    (clear_feature(0, DEVICE_REMOTE_WAKEUP, 0), Reply::status()),
+    (get_status(0, 0), Reply::data(&[&[0, 0]])),
+  ]);
+  assert!(!host.state.remote_wakeup());
+}
+
+#[test]
+fn bus_reset_disables_remote_wakeup() {
+  let mut host = Host::configured();
+  host.replay(&[(set_feature(0, DEVICE_REMOTE_WAKEUP, 0), Reply::status())]);
+
+  host.bus_reset();
+  assert!(!host.state.remote_wakeup());
+  assert_eq!(host.setup(get_status(0, 0)), Reply::data(&[&[0, 0]]));
+}
+
+#[test]
+fn interface_status_needs_a_configuration() {
+  let mut host = Host::new();
+  assert_eq!(host.setup(get_status(1, GAMEPAD_INTERFACE)), Reply::Stall);
+
+  let mut host = Host::configured();
+  host.replay(&[
+    (get_status(1, GAMEPAD_INTERFACE), Reply::data(&[&[0, 0]])),
+    (get_status(1, 1), Reply::Stall),
+  ]);
+}
+
+#[test]
+fn endpoint_status_reports_halt() {
+  let mut host = Host::configured();
+
+  host.replay(&[
+    (get_status(2, 0x00), Reply::data(&[&[0, 0]])),
+    (get_status(2, 0x80), Reply::data(&[&[0, 0]])),
+    (get_status(2, GAMEPAD_IN), Reply::data(&[&[0, 0]])),
+    (set_feature(2, ENDPOINT_HALT, GAMEPAD_IN), Reply::status()),
+    (get_status(2, GAMEPAD_IN), Reply::data(&[&[1, 0]])),
+    (clear_feature(2, ENDPOINT_HALT, GAMEPAD_IN), Reply::status()),
+    (get_status(2, GAMEPAD_IN), Reply::data(&[&[0, 0]])),
+  ]);
+}
+
+#[test]
+fn endpoint_status_stalls_for_missing_endpoints() {
+  let mut host = Host::new();
+  assert_eq!(host.setup(get_status(2, GAMEPAD_IN)), Reply::Stall);
+
+  let mut host = Host::configured();
+  host.replay(&[
+    // The gamepad endpoint is IN only
+    (get_status(2, GAMEPAD_ENDPOINT), Reply::Stall),
+    (get_status(2, 0x82), Reply::Stall),
+  ]);
+}
+
+#[test]
+fn halt_stalls_the_gamepad_endpoint() {
+  let mut host = Host::configured();
+
+  host.replay(&[(set_feature(2, ENDPOINT_HALT, GAMEPAD_IN), Reply::status())]);
+
+  assert!(host.state.is_halted(GAMEPAD_ENDPOINT));
+  assert_eq!(host.usb.endpoint(GAMEPAD_ENDPOINT).control, EPEN | STALLRQ);
+}
+
+#[test]
+fn clearing_halt_resets_the_data_toggle() {
+  let mut host = Host::configured();
+
+  host.replay(&[(set_feature(2, ENDPOINT_HALT, GAMEPAD_IN), Reply::status())]);
+  host.usb.log.clear();
+  host.replay(&[(clear_feature(2, ENDPOINT_HALT, GAMEPAD_IN), Reply::status())]);
+
+  assert!(!host.state.is_halted(GAMEPAD_ENDPOINT));
+  assert_eq!(host.usb.endpoint(GAMEPAD_ENDPOINT).control, EPEN | STALLRQC | RSTDT);
+  assert!(host.usb.log.contains(&UsbAccess::ResetEndpoints(1 << GAMEPAD_ENDPOINT)));
+  // The status stage still goes out on endpoint 0
+  assert_eq!(host.usb.endpoint, 0);
+}
+
+#[test]
+fn clearing_halt_on_a_running_endpoint_resets_the_data_toggle() {
+  let mut host = Host::configured();
+
+  host.replay(&[(clear_feature(2, ENDPOINT_HALT, GAMEPAD_IN), Reply::status())]);
+
+  assert_eq!(host.usb.endpoint(GAMEPAD_ENDPOINT).control, EPEN | STALLRQC | RSTDT);
+}
+
+#[test]
+fn endpoint_zero_does_not_halt() {
+  let mut host = Host::configured();
+
+  host.replay(&[
+    (set_feature(2, ENDPOINT_HALT, 0x00), Reply::Stall),
+    (clear_feature(2, ENDPOINT_HALT, 0x00), Reply::status()),
+    (get_status(2, 0x00), Reply::data(&[&[0, 0]])),
+  ]);
+}
+
+#[test]
+fn unsupported_features_stall() {
+  let mut host = Host::configured();
+
+  host.replay(&[
+    // Remote wakeup is a device feature
+    (set_feature(1, DEVICE_REMOTE_WAKEUP, GAMEPAD_INTERFACE), Reply::Stall),
+    // Test mode is for high speed devices
+    (set_feature(0, 2, 0), Reply::Stall),
+    // Interfaces have no features
+    (set_feature(1, 0, GAMEPAD_INTERFACE), Reply::Stall),
+    // Halt of an endpoint that does not exist
+    (set_feature(2, ENDPOINT_HALT, 0x82), Reply::Stall),
+  ]);
+}
+
+#[test]
+fn halt_needs_a_configuration() {
+  let mut host = Host::new();
+  assert_eq!(host.setup(set_feature(2, ENDPOINT_HALT, GAMEPAD_IN)), Reply::Stall);
+  assert!(!host.state.is_halted(GAMEPAD_ENDPOINT));
+}
+
+#[test]
+fn interface_has_alternate_setting_zero_only() {
+  let mut host = Host::configured();
+
+  host.replay(&[
+    (get_interface(GAMEPAD_INTERFACE), Reply::data(&[&[0]])),
+    (set_interface(GAMEPAD_INTERFACE, 0), Reply::status()),
+    (set_interface(GAMEPAD_INTERFACE, 1), Reply::Stall),
+    (get_interface(1), Reply::Stall),
+    (set_interface(1, 0), Reply::Stall),
+  ]);
+}
+
+#[test]
+fn set_interface_clears_halt() {
+  let mut host = Host::configured();
+
+  host.replay(&[
+    (set_feature(2, ENDPOINT_HALT, GAMEPAD_IN), Reply::status()),
+    (set_interface(GAMEPAD_INTERFACE, 0), Reply::status()),
+    (get_status(2, GAMEPAD_IN), Reply::data(&[&[0, 0]])),
+  ]);
+
+  assert_eq!(host.usb.endpoint(GAMEPAD_ENDPOINT).control, EPEN | STALLRQC | RSTDT);
+}
+
+#[test]
+fn interface_requests_need_a_configuration() {
+  let mut host = Host::new();
+
+  host.replay(&[
+    (get_interface(GAMEPAD_INTERFACE), Reply::Stall),
+    (set_interface(GAMEPAD_INTERFACE, 0), Reply::Stall),
+  ]);
+}
+
+#[test]
+fn configuration_zero_returns_to_the_address_state() {
+  let mut host = Host::configured();
+
+  host.replay(&[
+    (set_feature(2, ENDPOINT_HALT, GAMEPAD_IN), Reply::status()),
+    ([0x00, 9, 0, 0, 0, 0, 0, 0], Reply::status()),
+    ([0x80, 8, 0, 0, 0, 0, 1, 0], Reply::data(&[&[0]])),
+    (get_interface(GAMEPAD_INTERFACE), Reply::Stall),
+  ]);
+
+  assert!(!host.state.is_halted(GAMEPAD_ENDPOINT));
+  assert_eq!(host.usb.endpoint(GAMEPAD_ENDPOINT).control, 0);
+}
+
+#[test]
+fn missing_configurations_stall() {
+  let mut host = Host::new();
+
+  assert_eq!(host.setup([0x00, 9, 2, 0, 0, 0, 0, 0]), Reply::Stall);
+  assert_eq!(host.state.configuration(), 0);
+}
+
+#[test]
+fn bus_reset_clears_halt() {
+  let mut host = Host::configured();
+
+  host.replay(&[(set_feature(2, ENDPOINT_HALT, GAMEPAD_IN), Reply::status())]);
+  host.bus_reset();
+
+  assert!(!host.state.is_halted(GAMEPAD_ENDPOINT));
+}
@@ -0,0 +1,161 @@
+//! Suspend, resume and remote wakeup.
+
+mod common;
+
+use common::{Host, Reply};
+use ofs_support::hal::{EORSTE, EORSTI, SOFE, SOFI, SUSPE, SUSPI, WAKEUPE, WAKEUPI};
+use ofs_support::mock::UsbAccess;
+use ofs_support::usb::{take_bus_events, update_power, BusEvents, PowerChange, DEVICE_REMOTE_WAKEUP};
+
+const ACTIVE_ENABLES: u8 = EORSTE | SOFE | SUSPE;
+
+const SET_REMOTE_WAKEUP: [u8; 8] = [0x00, 3, DEVICE_REMOTE_WAKEUP as u8, 0, 0, 0, 0, 0];
+
+fn active_host() -> Host {
+  let mut host = Host::new();
+  host.usb.device_interrupt_enables = ACTIVE_ENABLES;
+  host
+}
+
+/// Raises `flags` and runs the general usb interrupt.
+fn interrupt(host: &mut Host, flags: u8) -> BusEvents {
+  host.usb.device_interrupts |= flags;
+  take_bus_events(&mut host.usb, &host.state)
+}
+
+fn suspended_host() -> Host {
+  let mut host = active_host();
+  interrupt(&mut host, SUSPI);
+  assert_eq!(update_power(&mut host.usb, &host.state, false), Some(PowerChange::Suspended));
+  host
+}
+
+#[test]
+fn suspend_arms_wakeup() {
+  let mut host = active_host();
+
+  let events = interrupt(&mut host, SUSPI);
+  assert_eq!(
+    events,
+    BusEvents {
+      suspend: true,
+      ..Default::default()
+    }
+  );
+  assert!(host.state.is_suspended());
+  assert_eq!(host.usb.device_interrupt_enables, EORSTE | SOFE | WAKEUPE);
+  assert_eq!(host.usb.device_interrupts, 0);
+}
+
+#[test]
+fn wakeup_arms_suspend() {
+  let mut host = suspended_host();
+
+  let events = interrupt(&mut host, WAKEUPI);
+  assert_eq!(
+    events,
+    BusEvents {
+      wakeup: true,
+      ..Default::default()
+    }
+  );
+  assert!(!host.state.is_suspended());
+  assert_eq!(host.usb.device_interrupt_enables, ACTIVE_ENABLES);
+}
+
+#[test]
+fn disabled_events_are_ignored() {
+  let mut host = active_host();
+  // WAKEUPI is raised by any bus activity, it only counts while suspended
+  assert_eq!(interrupt(&mut host, WAKEUPI), BusEvents::default());
+  assert!(!host.state.is_suspended());
+
+  let mut host = suspended_host();
+  assert_eq!(interrupt(&mut host, SUSPI), BusEvents::default());
+  assert!(host.state.is_suspended());
+}
+
+#[test]
+fn only_handled_flags_are_cleared() {
+  let mut host = active_host();
+  interrupt(&mut host, SUSPI | SOFI);
+  assert_eq!(host.usb.device_interrupts, SOFI);
+
+  // Raised while the bus was active, left for whoever looks at it
+  let mut host = active_host();
+  interrupt(&mut host, WAKEUPI | EORSTI);
+  assert_eq!(host.usb.device_interrupts, WAKEUPI);
+}
+
+#[test]
+fn wakeup_restarts_the_clock_before_clearing_its_flag() {
+  let mut host = suspended_host();
+  host.usb.log.clear();
+
+  interrupt(&mut host, WAKEUPI);
+  assert!(!host.usb.clock_frozen);
+  let position = |access| host.usb.log.iter().position(|&logged| logged == access).unwrap();
+  assert!(position(UsbAccess::FreezeClock(false)) < position(UsbAccess::ClearDeviceInterrupts(WAKEUPI)));
+}
+
+#[test]
+fn bus_reset_is_reported() {
+  let mut host = active_host();
+  assert_eq!(
+    interrupt(&mut host, EORSTI),
+    BusEvents {
+      reset: true,
+      ..Default::default()
+    }
+  );
+}
+
+#[test]
+fn clock_follows_the_bus() {
+  let mut host = active_host();
+  assert_eq!(update_power(&mut host.usb, &host.state, false), None);
+  assert!(!host.usb.clock_frozen);
+
+  let mut host = suspended_host();
+  assert!(host.usb.clock_frozen);
+  assert_eq!(update_power(&mut host.usb, &host.state, false), None);
+
+  interrupt(&mut host, WAKEUPI);
+  assert_eq!(update_power(&mut host.usb, &host.state, false), Some(PowerChange::Resumed));
+  assert!(!host.usb.clock_frozen);
+}
+
+#[test]
+fn remote_wakeup_needs_the_host_to_enable_it() {
+  let mut host = suspended_host();
+  assert_eq!(update_power(&mut host.usb, &host.state, true), None);
+  assert_eq!(host.usb.remote_wakeups, 0);
+  assert!(host.usb.clock_frozen);
+}
+
+#[test]
+fn remote_wakeup_resumes_the_bus() {
+  let mut host = active_host();
+  host.replay(&[(SET_REMOTE_WAKEUP, Reply::status())]);
+  interrupt(&mut host, SUSPI);
+  update_power(&mut host.usb, &host.state, false);
+
+  assert_eq!(update_power(&mut host.usb, &host.state, true), Some(PowerChange::Resumed));
+  assert_eq!(host.usb.remote_wakeups, 1);
+  assert!(!host.usb.clock_frozen);
+  assert!(!host.state.is_suspended());
+
+  // The host's resume signalling re-arms suspend without another change
+  interrupt(&mut host, WAKEUPI);
+  assert_eq!(host.usb.device_interrupt_enables, ACTIVE_ENABLES);
+  assert_eq!(update_power(&mut host.usb, &host.state, false), None);
+}
+
+#[test]
+fn remote_wakeup_only_while_suspended() {
+  let mut host = active_host();
+  host.replay(&[(SET_REMOTE_WAKEUP, Reply::status())]);
+
+  assert_eq!(update_power(&mut host.usb, &host.state, true), None);
+  assert_eq!(host.usb.remote_wakeups, 0);
+}
@@ -0,0 +1,99 @@
+//! Millisecond time across the wrap of its counter, and the tick clock both
+//! firmwares keep.
+
+use ofs_support::clock::{TickClock, TickPosition, CRITICAL_SECTION_BUDGET_US, TICK_TIMER};
+use ofs_support::time::{Clock, Duration, Instant, MockClock};
+
+/// Ten milliseconds before the counter wraps to zero.
+const BEFORE_WRAP: Instant = Instant::from_millis(u32::MAX - 9);
+
+fn position(count: u8, pending: bool) -> TickPosition {
+  TickPosition { count, pending }
+}
+
+#[test]
+fn adding_wraps_the_counter() {
+  let after = BEFORE_WRAP + Duration::from_millis(25);
+  assert_eq!(after, Instant::from_millis(15));
+}
+
+#[test]
+fn subtraction_spans_the_wrap() {
+  let after = Instant::from_millis(15);
+  assert_eq!(after - BEFORE_WRAP, Duration::from_millis(25));
+  assert_eq!(after.duration_since(BEFORE_WRAP), Duration::from_millis(25));
+
+  // Backwards is no time at all, not most of the range
+  assert_eq!(BEFORE_WRAP - after, Duration::ZERO);
+}
+
+#[test]
+fn deadlines_pass_across_the_wrap() {
+  let deadline = BEFORE_WRAP + Duration::from_millis(20);
+
+  assert!(!deadline.has_passed(BEFORE_WRAP));
+  assert!(!deadline.has_passed(Instant::from_millis(9)));
+  assert!(deadline.has_passed(Instant::from_millis(10)));
+  assert!(deadline.has_passed(Instant::from_millis(1_000)));
+}
+
+#[test]
+fn elapsed_counts_across_the_wrap() {
+  let timeout = Duration::from_millis(16);
+
+  assert!(!BEFORE_WRAP.has_elapsed(Instant::from_millis(5), timeout));
+  assert!(BEFORE_WRAP.has_elapsed(Instant::from_millis(6), timeout));
+  // Before the start is not after it
+  assert!(!BEFORE_WRAP.has_elapsed(Instant::from_millis(u32::MAX - 20), timeout));
+}
+
+#[test]
+fn mock_clock_moves_when_told() {
+  let clock = MockClock::new(BEFORE_WRAP);
+  let start = clock.now();
+
+  clock.advance(Duration::from_millis(10));
+  assert_eq!(clock.now(), Instant::ZERO);
+  assert_eq!(clock.now() - start, Duration::from_millis(10));
+
+  clock.set(Instant::from_millis(500));
+  assert_eq!(clock.now(), Instant::from_millis(500));
+}
+
+#[test]
+fn tick_clock_counts_milliseconds() {
+  let mut clock = TickClock::new();
+  assert_eq!(clock.now(), Instant::ZERO);
+
+  for _ in 0..3 {
+    clock.tick();
+  }
+  assert_eq!(Clock::now(&clock), Instant::from_millis(3));
+}
+
+#[test]
+fn tick_clock_measures_critical_sections() {
+  let mut clock = TickClock::new();
+  clock.record_section(position(10, false), position(60, false));
+  // Across a compare match, the counter starting over
+  clock.record_section(position(200, false), position(40, true));
+  // A match already pending at the start does not count twice
+  clock.record_section(position(20, true), position(30, true));
+
+  let stats = clock.critical_sections();
+  assert_eq!(stats.sections, 3);
+  assert_eq!(stats.worst_ticks, TICK_TIMER.compare + 1 + 40 - 200);
+  // 4 us per tick at /64
+  assert_eq!(clock.worst_critical_section_us(), 90 * 4);
+}
+
+#[test]
+fn tick_clock_flags_sections_over_budget() {
+  let mut clock = TickClock::new();
+  clock.record_section(position(0, false), position(125, false));
+  assert_eq!(clock.worst_critical_section_us(), CRITICAL_SECTION_BUDGET_US);
+  assert!(!clock.is_over_budget());
+
+  clock.record_section(position(0, false), position(126, false));
+  assert!(clock.is_over_budget());
+}
@@ -0,0 +1,87 @@
+//! Timer configurations checked against the register values the firmwares
+//! were written with.
+
+use ofs_support::timing::{Prescaler, TimerConfig, CPU_HZ, TIMER16_TOP, TIMER8_TOP};
+
+fn config(prescaler: Prescaler, compare: u16) -> TimerConfig {
+  TimerConfig { prescaler, compare }
+}
+
+#[test]
+fn periods_match_the_known_register_values() {
+  let table = [
+    // Controller scan, every 32 ms
+    (32_000, TIMER16_TOP, config(Prescaler::Div8, 63_999)),
+    // Usb firmware poll on TIMER0, every 16.384 ms, before it moved to the
+    // millisecond clock
+    (16_384, TIMER8_TOP, config(Prescaler::Div1024, 255)),
+    // Usb firmware startup delay, 3.84 s
+    (3_840_000, TIMER16_TOP, config(Prescaler::Div1024, 59_999)),
+    // Millisecond clock tick
+    (1_000, TIMER8_TOP, config(Prescaler::Div64, 249)),
+  ];
+
+  for &(period_us, top, expected) in table.iter() {
+    let timer = TimerConfig::from_period_us(CPU_HZ, period_us, top);
+    assert_eq!(timer, expected, "{} us", period_us);
+    assert_eq!(timer.period_us(CPU_HZ), period_us, "{} us", period_us);
+  }
+}
+
+#[test]
+fn frequencies_match_the_known_register_values() {
+  let table = [
+    (1_000, TIMER8_TOP, config(Prescaler::Div64, 249)),
+    (8_000, TIMER16_TOP, config(Prescaler::Div1, 1_999)),
+    (50, TIMER16_TOP, config(Prescaler::Div8, 39_999)),
+  ];
+
+  for &(hz, top, expected) in table.iter() {
+    assert_eq!(TimerConfig::from_hz(CPU_HZ, hz, top), expected, "{} Hz", hz);
+  }
+}
+
+#[test]
+fn clock_select_and_tick_length() {
+  let codes: Vec<u8> = Prescaler::ALL.iter().map(|p| p.clock_select()).collect();
+  assert_eq!(codes, [1, 2, 3, 4, 5]);
+
+  assert_eq!(config(Prescaler::Div64, 249).tick_ns(CPU_HZ), 4_000);
+  assert_eq!(config(Prescaler::Div1, 0).tick_ns(CPU_HZ), 62);
+}
+
+#[test]
+#[should_panic]
+fn period_too_long_for_the_timer_fails() {
+  // Over four seconds at the largest prescaler, past a 16 bit timer
+  TimerConfig::from_period_us(CPU_HZ, 5_000_000, TIMER16_TOP);
+}
+
+#[test]
+#[should_panic]
+fn frequency_too_low_for_the_timer_fails() {
+  TimerConfig::from_hz(CPU_HZ, 10, TIMER8_TOP);
+}
+
+#[test]
+fn clocks_that_are_not_whole_megahertz() {
+  // A UART crystal, 14.7456 MHz
+  let crystal = 14_745_600;
+  let timer = TimerConfig::from_period_us(crystal, 1_000, TIMER8_TOP);
+  assert_eq!(timer, config(Prescaler::Div64, 229));
+  assert_eq!(timer.period_us(crystal), 998);
+  assert_eq!(timer.tick_ns(crystal), 4_340);
+
+  // Half of it, scanning every 32 ms
+  let crystal = 7_372_800;
+  let timer = TimerConfig::from_period_us(crystal, 32_000, TIMER16_TOP);
+  assert_eq!(timer, config(Prescaler::Div8, 29_490));
+  assert_eq!(timer.period_us(crystal), 31_999);
+
+  // Below 1 MHz
+  let slow = 500_000;
+  let timer = TimerConfig::from_period_us(slow, 1_000, TIMER8_TOP);
+  assert_eq!(timer, config(Prescaler::Div8, 62));
+  assert_eq!(timer.tick_ns(slow), 16_000);
+  assert_eq!(timer.period_us(slow), 1_008);
+}
@@ -1,6 +1,5 @@
 
 import yargs from "https://deno.land/x/yargs@v17.0.1-deno/deno.ts"
-import { Arguments } from 'https://deno.land/x/yargs@v17.0.1-deno/deno-types.ts'
 import { dirname, basename, join, fromFileUrl } from "https://deno.land/std@0.100.0/path/mod.ts"
 import Spinner from 'https://deno.land/x/cli_spinners@v0.0.2/mod.ts'
 import { sleep } from "https://deno.land/x/sleep@v1.2.0/mod.ts"
@@ -250,16 +249,6 @@ const handleDFUFlash = async (flashLibrary: boolean) => {
   Deno.exit(code)
 }
 
-const generateSliceNameString = async (str: string) => {
-  const byteArray = Array(str.length).fill(0).map((_, i) => str.charCodeAt(i))
-  const strArray = byteArray.map((b) => `0x${b.toString(16)}, 0x00`)
-  const descriptorSize = strArray.length * 2 + 2
-  let strBuild = `[${descriptorSize}, 3, ${strArray.join(',')}]`
-
-  console.log(strBuild)
-  Deno.exit(0)
-}
-
 yargs(Deno.args)
   .usage("Usage: ofs.ts <command>")
   .command("buildc", "build controller library", () => handleBuildCommand('controller'))
@@ -267,9 +256,6 @@ yargs(Deno.args)
   .command("buildusb", "build usb library", () => handleBuildCommand('usb-firmware'))
   .command("flashusb", "flash usb", () => handleDFUFlash(true))
   .command("restoreusb", "restore arduino back to standard firmware", () => handleDFUFlash(false))
-  .command("genstr <string...>", "generate slice for string descriptor", (yargs: any) => yargs.positional('string', {
-    describe: 'string to convert'
-  }), (argv: Arguments) => generateSliceNameString(argv.string.join(' ')))
   .command("fmt", "format projects", () => fmtProjects())
   .strictCommands()
   .demandCommand(1)
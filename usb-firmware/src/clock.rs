@@ -0,0 +1,80 @@
+use core::cell::RefCell;
+
+use avr_device::atmega8u2::TC0;
+use avr_device::interrupt;
+use avr_device::interrupt::{CriticalSection, Mutex};
+use ofs_support::clock::{TickClock, TickPosition, TICK_TIMER};
+use ofs_support::time::{Clock, Instant};
+
+const WGM01: u8 = 1 << 1;
+const OCF0A: u8 = 1 << 1;
+
+static G_TC0: Mutex<RefCell<Option<TC0>>> = Mutex::new(RefCell::new(None));
+static CLOCK: Mutex<RefCell<TickClock>> = Mutex::new(RefCell::new(TickClock::new()));
+
+/// The system clock, ticking every millisecond on TIMER0.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> Instant {
+    interrupt::free(now)
+  }
+}
+
+pub fn setup_clock(cs: &CriticalSection, tc0: TC0) {
+  // CTC mode, the counter clears itself on reaching OCR0A
+  tc0.ocr0a.write(|w| unsafe { w.bits(TICK_TIMER.compare as u8) });
+  tc0.tcnt0.write(|w| unsafe { w.bits(0) });
+  tc0.timsk0.write(|w| w.ocie0a().set_bit());
+  tc0.tccr0a.write(|w| unsafe { w.bits(WGM01) });
+  tc0
+    .tccr0b
+    .write(|w| unsafe { w.bits(TICK_TIMER.prescaler.clock_select()) });
+
+  G_TC0.borrow(cs).replace(Some(tc0));
+}
+
+pub fn now(cs: &CriticalSection) -> Instant {
+  CLOCK.borrow(cs).borrow().now()
+}
+
+/// Advances the clock by one tick, from TIMER0, returning the new time.
+pub fn tick(cs: &CriticalSection) -> Instant {
+  CLOCK.borrow(cs).borrow_mut().tick()
+}
+
+/// Position within the current tick, and whether a tick is pending.
+pub fn tick_position(cs: &CriticalSection) -> TickPosition {
+  G_TC0.borrow(cs).borrow().as_ref().map_or(
+    TickPosition {
+      count: 0,
+      pending: false,
+    },
+    |tc0| TickPosition {
+      count: tc0.tcnt0.read().bits(),
+      pending: tc0.tifr0.read().bits() & OCF0A != 0,
+    },
+  )
+}
+
+/// `interrupt::free` for the main loop, recording how long interrupts were
+/// held off.
+pub fn free<F, R>(f: F) -> R
+where
+  F: FnOnce(&CriticalSection) -> R,
+{
+  interrupt::free(|cs| {
+    let start = tick_position(cs);
+    let result = f(cs);
+    let end = tick_position(cs);
+
+    CLOCK.borrow(cs).borrow_mut().record_section(start, end);
+    result
+  })
+}
+
+/// Whether the main loop has ever held interrupts off past
+/// `CRITICAL_SECTION_BUDGET_US`, reported with `STATUS_OVER_BUDGET`.
+pub fn is_over_budget(cs: &CriticalSection) -> bool {
+  CLOCK.borrow(cs).borrow().is_over_budget()
+}
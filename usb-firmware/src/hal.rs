@@ -0,0 +1,188 @@
+use avr_device::atmega8u2::{pll, usb_device, PLL, PORTD, USART1, USB_DEVICE};
+use ofs_support::hal::{with_bit, Gpio, Uart, UsbController};
+
+/// `UDCON`, starts resume signalling.
+const RMWKUP: u8 = 1 << 1;
+/// `PLLCSR`, prescaler for the 16 MHz crystal.
+const PINDIV: u8 = 1 << 2;
+
+/// The usb device controller registers, and the PLL clocking them.
+pub struct AvrUsb {
+  registers: &'static usb_device::RegisterBlock,
+  pll: &'static pll::RegisterBlock,
+}
+
+impl AvrUsb {
+  pub fn new(_usb: USB_DEVICE, _pll: PLL) -> AvrUsb {
+    AvrUsb {
+      registers: unsafe { &*USB_DEVICE::ptr() },
+      pll: unsafe { &*PLL::ptr() },
+    }
+  }
+
+  /// Connects the pull-up on D+, so the host sees the device.
+  pub fn attach(&mut self) {
+    self.registers.udcon.write(|w| unsafe { w.bits(0) });
+  }
+
+  /// Starts the PLL and waits for it to lock.
+  fn start_pll(&mut self) {
+    self.pll.pllcsr.write(|w| unsafe { w.bits(PINDIV).plle().set_bit() });
+    while self.pll.pllcsr.read().plock().bit_is_clear() {}
+  }
+
+  /// A second handle on the registers for the interrupts, which may preempt
+  /// the main loop while it holds the one from `new`.
+  ///
+  /// # Safety
+  /// The caller may only touch interrupt flags and enables and restart a
+  /// frozen usb clock, and must put back the endpoint the main loop had
+  /// selected.
+  pub unsafe fn steal() -> AvrUsb {
+    AvrUsb {
+      registers: &*USB_DEVICE::ptr(),
+      pll: &*PLL::ptr(),
+    }
+  }
+}
+
+impl UsbController for AvrUsb {
+  fn selected_endpoint(&mut self) -> u8 {
+    self.registers.uenum.read().bits()
+  }
+
+  fn select_endpoint(&mut self, endpoint: u8) {
+    self.registers.uenum.write(|w| unsafe { w.bits(endpoint) });
+  }
+
+  fn read_fifo(&mut self) -> u8 {
+    self.registers.uedatx.read().bits()
+  }
+
+  fn write_fifo(&mut self, data: u8) {
+    self.registers.uedatx.write(|w| unsafe { w.bits(data) });
+  }
+
+  fn endpoint_flags(&mut self) -> u8 {
+    self.registers.ueintx.read().bits()
+  }
+
+  fn write_endpoint_flags(&mut self, flags: u8) {
+    self.registers.ueintx.write(|w| unsafe { w.bits(flags) });
+  }
+
+  fn endpoint_interrupts(&mut self) -> u8 {
+    self.registers.ueienx.read().bits()
+  }
+
+  fn set_endpoint_interrupts(&mut self, enables: u8) {
+    self.registers.ueienx.write(|w| unsafe { w.bits(enables) });
+  }
+
+  fn write_endpoint_control(&mut self, control: u8) {
+    self.registers.ueconx.write(|w| unsafe { w.bits(control) });
+  }
+
+  fn configure_endpoint(&mut self, config0: u8, config1: u8) {
+    self.registers.uecfg0x.write(|w| unsafe { w.bits(config0) });
+    self.registers.uecfg1x.write(|w| unsafe { w.bits(config1) });
+  }
+
+  fn reset_endpoints(&mut self, endpoints: u8) {
+    self.registers.uerst.write(|w| unsafe { w.bits(endpoints) });
+    self.registers.uerst.write(|w| unsafe { w.bits(0) });
+  }
+
+  fn set_address(&mut self, address: u8) {
+    self
+      .registers
+      .udaddr
+      .write(|w| w.uadd().bits(address).adden().set_bit());
+  }
+
+  fn frame_number(&mut self) -> u16 {
+    self.registers.udfnum.read().bits()
+  }
+
+  fn device_interrupts(&mut self) -> u8 {
+    self.registers.udint.read().bits()
+  }
+
+  fn clear_device_interrupts(&mut self, flags: u8) {
+    // Writing one leaves a flag as it is
+    self.registers.udint.write(|w| unsafe { w.bits(!flags) });
+  }
+
+  fn device_interrupt_enables(&mut self) -> u8 {
+    self.registers.udien.read().bits()
+  }
+
+  fn set_device_interrupt_enables(&mut self, enables: u8) {
+    self.registers.udien.write(|w| unsafe { w.bits(enables) });
+  }
+
+  fn is_clock_frozen(&mut self) -> bool {
+    self.registers.usbcon.read().frzclk().bit_is_set()
+  }
+
+  fn freeze_clock(&mut self, frozen: bool) {
+    if frozen {
+      self.registers.usbcon.modify(|_, w| w.frzclk().set_bit());
+      self.pll.pllcsr.write(|w| unsafe { w.bits(0) });
+    } else {
+      self.start_pll();
+      self.registers.usbcon.modify(|_, w| w.frzclk().clear_bit());
+    }
+  }
+
+  fn send_remote_wakeup(&mut self) {
+    self
+      .registers
+      .udcon
+      .modify(|r, w| unsafe { w.bits(r.bits() | RMWKUP) });
+  }
+}
+
+/// USART1, the link to the controller.
+pub struct AvrUsart(pub USART1);
+
+impl Uart for AvrUsart {
+  fn write(&mut self, data: u8) {
+    self.0.udr1.write(|w| unsafe { w.bits(data) });
+  }
+
+  fn read(&mut self) -> u8 {
+    self.0.udr1.read().bits()
+  }
+
+  fn ready_to_send(&mut self) -> bool {
+    self.0.ucsr1a.read().udre1().bit()
+  }
+}
+
+/// Port D, with the UART pins and the status LEDs.
+pub struct AvrPortD(pub PORTD);
+
+impl Gpio for AvrPortD {
+  fn set_output(&mut self, pin: u8, output: bool) {
+    self
+      .0
+      .ddrd
+      .modify(|r, w| unsafe { w.bits(with_bit(r.bits(), pin, output)) });
+  }
+
+  fn write(&mut self, pin: u8, high: bool) {
+    self
+      .0
+      .portd
+      .modify(|r, w| unsafe { w.bits(with_bit(r.bits(), pin, high)) });
+  }
+
+  fn is_set_high(&mut self, pin: u8) -> bool {
+    self.0.portd.read().bits() & (1 << pin) != 0
+  }
+
+  fn read(&mut self, pin: u8) -> bool {
+    self.0.pind.read().bits() & (1 << pin) != 0
+  }
+}
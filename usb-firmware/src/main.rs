@@ -1,23 +1,54 @@
 #![no_std]
 #![no_main]
 #![feature(abi_avr_interrupt)]
+#![feature(llvm_asm)]
 
 use core::cell::RefCell;
 
 use avr_device::asm::wdr;
-use avr_device::atmega8u2::{Peripherals, CPU, TC0, TC1};
+use avr_device::atmega8u2::{Peripherals, CPU, TC1};
 use avr_device::interrupt::{enable, free, CriticalSection, Mutex};
 use avr_device::{entry, interrupt};
+use clock::{now, setup_clock, tick};
+use hal::AvrPortD;
+use ofs_support::scheduler::{Scheduler, TaskId};
+use ofs_support::time::{Duration, Instant};
+use ofs_support::timing::{TimerConfig, CPU_HZ, TIMER16_TOP};
 use panic_halt as _;
-use usart::{ask_for_fighstick_data, handshake_controller, setup_usart};
-use usb::{send_gamepad_data, setup_usb};
+use serial::{handle_save, setup_serial};
+use usart::{ask_for_fighstick_data, handle_received, handshake_controller, next_received, setup_usart};
+use usb::{handle_bus_reset, handle_control, handle_power, send_gamepad_data, setup_usb, USB_STATE};
 
-pub mod descriptors;
+pub mod clock;
+pub mod hal;
+pub mod serial;
 pub mod usart;
 pub mod usb;
 
-static G_TC0: Mutex<RefCell<Option<TC0>>> = Mutex::new(RefCell::new(None));
+/// How often the controller is polled and the report offered to the host.
+const POLL_PERIOD: Duration = Duration::from_millis(16);
+/// Time given to the controller to start up before the handshake.
+const STARTUP_DELAY_TIMER: TimerConfig = TimerConfig::from_period_us(CPU_HZ, 3_840_000, TIMER16_TOP);
+
+const WGM12: u8 = 1 << 3;
+
+/// Tasks of the main loop, highest priority first. The host is strict about
+/// bus resets, suspend and control transfers, the controller link and
+/// reports can wait, and saving the serial number
+/// to EEPROM least of all.
+pub const BUS_RESET_TASK: TaskId = TaskId(0);
+pub const POWER_TASK: TaskId = TaskId(1);
+pub const CONTROL_TASK: TaskId = TaskId(2);
+pub const LINK_TASK: TaskId = TaskId(3);
+pub const HANDSHAKE_TASK: TaskId = TaskId(4);
+pub const POLL_TASK: TaskId = TaskId(5);
+pub const SAVE_TASK: TaskId = TaskId(6);
+
+pub static SCHEDULER: Scheduler = Scheduler::new();
+
 static G_TC1: Mutex<RefCell<Option<TC1>>> = Mutex::new(RefCell::new(None));
+/// When the controller is next polled, set once the startup delay is over.
+static NEXT_POLL: Mutex<RefCell<Option<Instant>>> = Mutex::new(RefCell::new(None));
 
 fn sei() {
   unsafe {
@@ -32,20 +63,14 @@ fn setup_cpu(_: &CriticalSection, cpu: CPU) {
   cpu.clkpr.write(|w| w.clkps().bits(0));
 }
 
-fn configure_timer(cs: &CriticalSection) {
-  let tc0 = G_TC0.borrow(cs).borrow();
-
-  tc0.as_ref().unwrap().ocr0a.write(|w| unsafe { w.bits(255) });
-  tc0.as_ref().unwrap().tcnt0.write(|w| unsafe { w.bits(0) });
-  tc0.as_ref().unwrap().timsk0.write(|w| w.ocie0a().set_bit());
-  tc0.as_ref().unwrap().tccr0b.write(|w| w.cs0().prescale_1024());
-}
-
 fn configure_usb_startup_delay(tc1: &TC1) {
-  tc1.ocr1a.write(|w| unsafe { w.bits(60000) });
+  tc1.ocr1a.write(|w| unsafe { w.bits(STARTUP_DELAY_TIMER.compare) });
   tc1.tcnt1.write(|w| unsafe { w.bits(0) });
   tc1.timsk1.write(|w| w.ocie1a().set_bit());
-  tc1.tccr1b.write(|w| w.cs1().prescale_1024());
+  tc1.tccr1a.write(|w| unsafe { w.bits(0) });
+  tc1
+    .tccr1b
+    .write(|w| unsafe { w.bits(WGM12 | STARTUP_DELAY_TIMER.prescaler.clock_select()) });
 }
 
 #[entry]
@@ -53,41 +78,68 @@ fn main() -> ! {
   let peripherals = Peripherals::take().unwrap();
 
   free(|cs| {
-    setup_usart(cs, peripherals.USART1, &peripherals.PORTD);
+    let mut portd = AvrPortD(peripherals.PORTD);
+    setup_usart(cs, peripherals.USART1, &mut portd);
     setup_cpu(cs, peripherals.CPU);
-    setup_usb(cs, peripherals.USB_DEVICE, peripherals.PLL, peripherals.PORTD);
+    setup_serial(cs, peripherals.EEPROM);
+    setup_usb(cs, peripherals.USB_DEVICE, peripherals.PLL, portd);
+    setup_clock(cs, peripherals.TC0);
     configure_usb_startup_delay(&peripherals.TC1);
 
-    G_TC0.borrow(cs).replace(Some(peripherals.TC0));
     G_TC1.borrow(cs).replace(Some(peripherals.TC1));
   });
 
   sei();
 
-  loop {}
+  loop {
+    SCHEDULER.run_next(|task| match task {
+      // The usb tasks own the usb registers and wait on the host with
+      // interrupts enabled
+      BUS_RESET_TASK => handle_bus_reset(),
+      POWER_TASK => handle_power(),
+      CONTROL_TASK => handle_control(),
+      LINK_TASK => {
+        while let Some(data) = clock::free(next_received) {
+          clock::free(|cs| handle_received(cs, data));
+        }
+      },
+      HANDSHAKE_TASK => clock::free(handshake_controller),
+      POLL_TASK => {
+        clock::free(ask_for_fighstick_data);
+        send_gamepad_data();
+      },
+      SAVE_TASK => handle_save(),
+      _ => {},
+    });
+  }
 }
 
+// Interrupts only record what happened, the work runs from the main loop
+
 #[interrupt(atmega8u2)]
 fn TIMER1_COMPA() {
   interrupt::free(|cs| {
-    configure_timer(cs);
-    handshake_controller(cs);
+    NEXT_POLL.borrow(cs).replace(Some(now(cs)));
     let tc1 = G_TC1.borrow(cs).borrow();
     tc1.as_ref().unwrap().tccr1b.write(|w| w.cs1().no_clock());
   });
+  SCHEDULER.signal(HANDSHAKE_TASK);
 }
 
 #[interrupt(atmega8u2)]
 fn TIMER0_COMPA() {
   interrupt::free(|cs| {
-    let tc0 = G_TC0.borrow(cs).borrow();
-
-    tc0.as_ref().unwrap().tccr0b.write(|w| w.cs0().no_clock());
-
-    ask_for_fighstick_data(cs);
-    send_gamepad_data(cs);
-
-    tc0.as_ref().unwrap().tcnt0.write(|w| unsafe { w.bits(0) });
-    tc0.as_ref().unwrap().tccr0b.write(|w| w.cs0().prescale_1024());
+    let now = tick(cs);
+
+    let mut next_poll = NEXT_POLL.borrow(cs).borrow_mut();
+    if let Some(poll_at) = *next_poll {
+      if poll_at.has_passed(now) {
+        *next_poll = Some(poll_at + POLL_PERIOD);
+        // The controller idles while the host sleeps
+        if !USB_STATE.is_suspended() {
+          SCHEDULER.signal(POLL_TASK);
+        }
+      }
+    }
   });
 }
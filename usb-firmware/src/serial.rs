@@ -0,0 +1,154 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use avr_device::atmega8u2::EEPROM;
+use avr_device::interrupt::CriticalSection;
+use ofs_support::resource::Resource;
+use ofs_support::serial::{is_usable_id, SerialNumber, SERIAL_IMAGE_SIZE};
+
+use crate::clock;
+use crate::{SAVE_TASK, SCHEDULER};
+
+/// Where the serial number image starts in EEPROM.
+const SERIAL_ADDRESS: u16 = 0;
+
+/// The chip's id in the signature row: lot, wafer and position on the wafer.
+const SIGNATURE_ID_ADDRESS: u16 = 0x0E;
+const SIGNATURE_ID_SIZE: usize = 10;
+
+/// SPMCSR, in I/O space.
+const SPMCSR: u8 = 0x37;
+const SIGRD: u8 = 1 << 5;
+const SPMEN: u8 = 1 << 0;
+
+/// The serial number reported to the host.
+pub static SERIAL: Resource<SerialNumber> = Resource::new();
+static SAVE: Resource<Save> = Resource::new();
+/// Set until the serial number is made from random bits, on the first bus
+/// reset, for chips without an id.
+static UNSEEDED: AtomicBool = AtomicBool::new(false);
+
+/// The EEPROM, and the image being written to it one byte at a time.
+struct Save {
+  eeprom: EEPROM,
+  image: [u8; SERIAL_IMAGE_SIZE],
+  next: usize,
+}
+
+fn read_byte(eeprom: &EEPROM, address: u16) -> u8 {
+  while eeprom.eecr.read().eepe().bit_is_set() {}
+  eeprom.eear.write(|w| unsafe { w.bits(address) });
+  eeprom.eecr.write(|w| w.eere().set_bit());
+  eeprom.eedr.read().bits()
+}
+
+fn read_signature_byte(_: &CriticalSection, address: u16) -> u8 {
+  let byte: u8;
+  unsafe {
+    llvm_asm!("out $1, $2\n\tlpm $0, Z"
+      : "=r"(byte)
+      : "I"(SPMCSR), "r"(SIGRD | SPMEN), "z"(address)
+      :
+      : "volatile");
+  }
+  byte
+}
+
+/// Loads the serial number from EEPROM, or makes one from the signature row
+/// and starts saving it.
+pub fn setup_serial(cs: &CriticalSection, eeprom: EEPROM) {
+  let mut image = [0; SERIAL_IMAGE_SIZE];
+  for (offset, byte) in image.iter_mut().enumerate() {
+    *byte = read_byte(&eeprom, SERIAL_ADDRESS + offset as u16);
+  }
+  let saved = SerialNumber::from_bytes(&image);
+
+  let mut id = [0; SIGNATURE_ID_SIZE];
+  for (offset, byte) in id.iter_mut().enumerate() {
+    *byte = read_signature_byte(cs, SIGNATURE_ID_ADDRESS + offset as u16);
+  }
+
+  // Without an id the serial number waits for the first bus reset, see
+  // `seed_serial`
+  let (serial, save) = match saved {
+    Some(serial) => (serial, false),
+    None if is_usable_id(&id) => (SerialNumber::from_id(&id), true),
+    None => {
+      UNSEEDED.store(true, Ordering::Release);
+      (SerialNumber::from_id(&id), false)
+    },
+  };
+
+  SERIAL.init(serial);
+  SAVE.init(Save {
+    eeprom,
+    image: serial.to_bytes(),
+    next: SERIAL_IMAGE_SIZE,
+  });
+  if save {
+    save_serial();
+  }
+}
+
+/// Makes the serial number from random bits on the first bus reset of a chip
+/// without an id. How long the host took to reset the bus varies from boot
+/// to boot.
+pub fn seed_serial(frame_number: u16) {
+  if !UNSEEDED.load(Ordering::Acquire) {
+    return;
+  }
+  UNSEEDED.store(false, Ordering::Release);
+
+  let (now, ticks) = clock::free(|cs| (clock::now(cs), clock::tick_position(cs).count));
+  let mut seed = [0; 7];
+  seed[..4].copy_from_slice(&now.as_millis().to_le_bytes());
+  seed[4] = ticks;
+  seed[5..].copy_from_slice(&frame_number.to_le_bytes());
+
+  let serial = SerialNumber::from_id(&seed);
+  SERIAL.with(|current| *current = serial);
+  save_serial();
+}
+
+/// Starts writing the serial number to EEPROM, in the background.
+pub fn save_serial() {
+  if let Some(serial) = SERIAL.with(|serial| *serial) {
+    SAVE.with(|save| {
+      save.image = serial.to_bytes();
+      save.next = 0;
+    });
+    SCHEDULER.signal(SAVE_TASK);
+  }
+}
+
+/// Writes the next byte of the serial number that differs from EEPROM. An
+/// EEPROM write takes a few milliseconds, so the task comes back rather than
+/// waiting on one.
+pub fn handle_save() {
+  let more = SAVE.with(|save| {
+    if save.eeprom.eecr.read().eepe().bit_is_set() {
+      return true;
+    }
+
+    while save.next < SERIAL_IMAGE_SIZE {
+      let address = SERIAL_ADDRESS + save.next as u16;
+      let byte = save.image[save.next];
+      save.next += 1;
+
+      if read_byte(&save.eeprom, address) != byte {
+        let eeprom = &save.eeprom;
+        eeprom.eedr.write(|w| unsafe { w.bits(byte) });
+        // EEPE must be set within four cycles of EEMPE
+        clock::free(|_| {
+          eeprom.eecr.write(|w| w.eempe().set_bit());
+          eeprom.eecr.write(|w| w.eempe().set_bit().eepe().set_bit());
+        });
+        return save.next < SERIAL_IMAGE_SIZE;
+      }
+    }
+    false
+  });
+
+  if more == Some(true) {
+    SCHEDULER.signal(SAVE_TASK);
+  }
+}
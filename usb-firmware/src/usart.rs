@@ -1,23 +1,36 @@
-use core::cell::{Ref, RefCell};
+use core::cell::RefCell;
 
-use avr_device::atmega8u2::{PORTD, USART1};
+use avr_device::atmega8u2::USART1;
 use avr_device::interrupt;
 use avr_device::interrupt::{CriticalSection, Mutex};
-use ofs_support::fightstick::{FightstickDescriptor, IDLE_FIGHTSTICK};
+use ofs_support::fightstick::{FightstickDescriptor, FIGHTSTICK_DESCRIPTOR_SIZE, IDLE_FIGHTSTICK, STATUS_OVER_BUDGET};
+use ofs_support::hal::{Gpio, Uart};
+use ofs_support::queue::ByteQueue;
 use ofs_support::usart::UsartCommand;
 
-static USART: Mutex<RefCell<Option<USART1>>> = Mutex::new(RefCell::new(None));
+use crate::clock;
+use crate::hal::{AvrPortD, AvrUsart};
+use crate::usb::request_wakeup;
+use crate::{LINK_TASK, SCHEDULER};
+
+const RXD: u8 = 2;
+const TXD: u8 = 3;
+
+static USART: Mutex<RefCell<Option<AvrUsart>>> = Mutex::new(RefCell::new(None));
 static SENT_INTRO: Mutex<RefCell<bool>> = Mutex::new(RefCell::new(false));
 static INTRO_COMPLETE: Mutex<RefCell<bool>> = Mutex::new(RefCell::new(false));
 static GETTING_DATA: Mutex<RefCell<bool>> = Mutex::new(RefCell::new(false));
 static FIGHTSTICK_TABLE_POINTER: Mutex<RefCell<u8>> = Mutex::new(RefCell::new(0));
 static STAGING_FIGHTSTICK: Mutex<RefCell<FightstickDescriptor>> =
-  Mutex::new(RefCell::new(FightstickDescriptor([0, 0, 0, 0])));
+  Mutex::new(RefCell::new(FightstickDescriptor([0; FIGHTSTICK_DESCRIPTOR_SIZE])));
 static FIGHTSTICK: Mutex<RefCell<FightstickDescriptor>> = Mutex::new(RefCell::new(IDLE_FIGHTSTICK));
+/// Bytes from the controller waiting for `LINK_TASK`.
+static RECEIVED: Mutex<RefCell<ByteQueue>> = Mutex::new(RefCell::new(ByteQueue::new()));
 
-pub fn setup_usart(cs: &CriticalSection, usart: USART1, portd: &PORTD) {
+pub fn setup_usart(cs: &CriticalSection, usart: USART1, portd: &mut AvrPortD) {
   usart.ubrr1.write(|w| unsafe { w.bits(103) });
-  portd.ddrd.write(|w| w.pd2().clear_bit().pd3().set_bit());
+  portd.set_output(RXD, false);
+  portd.set_output(TXD, true);
   usart.ucsr1c.write(|w| {
     w.umsel1()
             .usart_async() // Async USART
@@ -31,81 +44,116 @@ pub fn setup_usart(cs: &CriticalSection, usart: USART1, portd: &PORTD) {
   usart
     .ucsr1b
     .write(|w| w.rxen1().set_bit().txen1().set_bit().rxcie1().set_bit());
-  USART.borrow(cs).replace(Some(usart));
+  USART.borrow(cs).replace(Some(AvrUsart(usart)));
 }
 
-pub fn send_command(usart: &Ref<Option<USART1>>, command: UsartCommand) {
-  send_data(usart, command.into());
+pub fn send_command<T: Uart>(usart: &mut T, command: UsartCommand) {
+  usart.write(command.into());
 }
 
-pub fn send_data(usart: &Ref<Option<USART1>>, data: u8) {
-  usart.as_ref().unwrap().udr1.write(|w| unsafe { w.bits(data) });
+/// Sends `command` once the transmit buffer has room, which takes at most
+/// the time of the byte ahead of it.
+pub fn tell_controller(cs: &CriticalSection, command: UsartCommand) {
+  let mut usart = USART.borrow(cs).borrow_mut();
+  let usart = usart.as_mut().unwrap();
+  while !usart.ready_to_send() {}
+  send_command(usart, command);
 }
 
 pub fn handshake_controller(cs: &CriticalSection) {
   if let Ok(mut sent_intro) = SENT_INTRO.borrow(cs).try_borrow_mut() {
-    let usart = USART.borrow(cs).borrow();
+    let mut usart = USART.borrow(cs).borrow_mut();
 
     *sent_intro = true;
-    send_command(&usart, UsartCommand::Introduction);
+    send_command(usart.as_mut().unwrap(), UsartCommand::Introduction);
   }
 }
 
-pub fn get_fightstick_data(cs: &CriticalSection) -> FightstickDescriptor {
+/// Latest report from the controller, leaving it in place.
+pub fn fightstick_data(cs: &CriticalSection) -> FightstickDescriptor {
   FIGHTSTICK.borrow(cs).borrow().clone()
 }
 
+/// Latest report from the controller. Relative fields are only returned once,
+/// the report is resent with them zeroed until the controller sends another.
+pub fn take_fightstick_data(cs: &CriticalSection) -> FightstickDescriptor {
+  let mut fightstick = FIGHTSTICK.borrow(cs).borrow_mut();
+  let report = fightstick.clone();
+  fightstick.clear_relative();
+  report
+}
+
 pub fn introduction_complete(cs: &CriticalSection) -> bool {
   *INTRO_COMPLETE.borrow(cs).borrow()
 }
 
 pub fn ask_for_fighstick_data(cs: &CriticalSection) {
-  let usart = USART.borrow(cs).borrow();
-  let dre = usart.as_ref().unwrap().ucsr1a.read().udre1().bit();
+  let mut usart = USART.borrow(cs).borrow_mut();
+  let usart = usart.as_mut().unwrap();
+  let dre = usart.ready_to_send();
   let intro_complete = INTRO_COMPLETE.borrow(cs).borrow();
 
   if *intro_complete && dre {
-    send_command(&usart, UsartCommand::SendData);
+    send_command(usart, UsartCommand::SendData);
+  }
+}
+
+/// Takes the next byte received from the controller, if any.
+pub fn next_received(cs: &CriticalSection) -> Option<u8> {
+  RECEIVED.borrow(cs).borrow_mut().pop()
+}
+
+/// Feeds a byte from the controller through the protocol.
+pub fn handle_received(cs: &CriticalSection, data: u8) {
+  let mut sent_intro = SENT_INTRO.borrow(cs).borrow_mut();
+  let mut intro_complete = INTRO_COMPLETE.borrow(cs).borrow_mut();
+  let mut getting_data = GETTING_DATA.borrow(cs).borrow_mut();
+
+  let possible_command: UsartCommand = data.into();
+
+  if *getting_data {
+    // Payload bytes can take any value, including those of commands
+    let mut table_pointer = FIGHTSTICK_TABLE_POINTER.borrow(cs).borrow_mut();
+    let mut staging_table = STAGING_FIGHTSTICK.borrow(cs).borrow_mut();
+    let mut table = staging_table.clone();
+    table.0[*table_pointer as usize] = data;
+    *staging_table = table;
+    *table_pointer += 1;
+
+    if *table_pointer as usize >= FIGHTSTICK_DESCRIPTOR_SIZE {
+      *table_pointer = 0;
+      *getting_data = false;
+      let mut report = staging_table.clone();
+      if clock::is_over_budget(cs) {
+        report.add_status(STATUS_OVER_BUDGET);
+      }
+      FIGHTSTICK.borrow(cs).replace(report);
+    }
+    return;
+  }
+
+  match possible_command {
+    UsartCommand::Introduction => {
+      if *sent_intro {
+        *sent_intro = false;
+        *intro_complete = true;
+      }
+    },
+    UsartCommand::SendData => {
+      *getting_data = true;
+    },
+    UsartCommand::Wakeup => request_wakeup(),
+    // Acknowledgements, and commands only the usb firmware sends
+    _ => {}, // noop
   }
 }
 
 #[interrupt(atmega8u2)]
 fn USART1_RX() {
   interrupt::free(|cs| {
-    let mut sent_intro = SENT_INTRO.borrow(cs).borrow_mut();
-    let mut intro_complete = INTRO_COMPLETE.borrow(cs).borrow_mut();
-    let mut getting_data = GETTING_DATA.borrow(cs).borrow_mut();
-
-    let usart = USART.borrow(cs).borrow();
-    let data = usart.as_ref().unwrap().udr1.read().bits();
-    let possible_command: UsartCommand = data.into();
-
-    match possible_command {
-      UsartCommand::Introduction => {
-        if *sent_intro {
-          *sent_intro = false;
-          *intro_complete = true;
-        }
-      },
-      UsartCommand::SendData => {
-        *getting_data = true;
-      },
-      UsartCommand::Unknown => {
-        if *getting_data {
-          let mut table_pointer = FIGHTSTICK_TABLE_POINTER.borrow(cs).borrow_mut();
-          let mut staging_table = STAGING_FIGHTSTICK.borrow(cs).borrow_mut();
-          let mut table = staging_table.clone();
-          table.0[*table_pointer as usize] = data;
-          *staging_table = table;
-          *table_pointer += 1;
-
-          if *table_pointer >= 4 {
-            *table_pointer = 0;
-            *getting_data = false;
-            FIGHTSTICK.borrow(cs).replace(staging_table.clone());
-          }
-        }
-      },
-    }
+    let mut usart = USART.borrow(cs).borrow_mut();
+    let data = usart.as_mut().unwrap().read();
+    RECEIVED.borrow(cs).borrow_mut().push(data);
   });
+  SCHEDULER.signal(LINK_TASK);
 }
@@ -1,361 +1,164 @@
-use core::cell::{Ref, RefCell};
+use core::sync::atomic::{AtomicBool, Ordering};
 
-use avr_device::atmega8u2::{PLL, PORTD, USB_DEVICE};
+use avr_device::atmega8u2::{PLL, USB_DEVICE};
 use avr_device::interrupt;
-use avr_device::interrupt::{free, CriticalSection, Mutex};
-
-use crate::descriptors::{
-  DESCRIPTOR_LIST, ENDPOINT0_SIZE, ENDPOINT_TABLE, GAMEPAD_ENDPOINT, GAMEPAD_INTERFACE, INIT_BYTES,
+use avr_device::interrupt::CriticalSection;
+use ofs_support::hal::{Gpio, UsbController, EORSTE, RXSTPE, SOFE, SUSPE};
+use ofs_support::resource::Resource;
+use ofs_support::usart::UsartCommand;
+use ofs_support::usb::{
+  send_report, take_bus_events, update_power, ControlEndpoint, PowerChange, ReportCache, RequestType, UsbState,
 };
-use crate::usart::get_fightstick_data;
-
-pub static PORTD: Mutex<RefCell<Option<PORTD>>> = Mutex::new(RefCell::new(None));
-pub static USB_DEVICE: Mutex<RefCell<Option<USB_DEVICE>>> = Mutex::new(RefCell::new(None));
-pub static USB_CONFIGURED: Mutex<RefCell<u8>> = Mutex::new(RefCell::new(0));
-pub static USB_IDLE_CONFIG: Mutex<RefCell<u8>> = Mutex::new(RefCell::new(0));
-pub static USB_PROTOCOL: Mutex<RefCell<u8>> = Mutex::new(RefCell::new(1));
-
-pub enum RequestType {
-  GetStatus,
-  ClearFeature,
-  SetFeature,
-  SetAddress,
-  GetDescriptor,
-  GetConfiguration,
-  SetConfiguration,
-  GetInterface,
-  SetInterface,
-  HidGetReport,
-  HidSetReport,
-  HidGetIdle,
-  HidSetIdle,
-  HidGetProtocol,
-  HidSetProtocol,
-  Stall,
-}
-
-impl RequestType {
-  pub fn from_u8(request_type: u8, request_num: u8, index: u8) -> RequestType {
-    match (request_type, request_num, index) {
-      (0x80, 8, _) => RequestType::GetConfiguration,
-      (0, 9, _) => RequestType::SetConfiguration,
-      (0xA1, 1, GAMEPAD_INTERFACE) => RequestType::HidGetProtocol,
-      (0xA1, 2, GAMEPAD_INTERFACE) => RequestType::HidGetIdle,
-      (0xA1, 3, GAMEPAD_INTERFACE) => RequestType::HidGetProtocol,
-      (0x21, 9, GAMEPAD_INTERFACE) => RequestType::HidSetReport,
-      (0x21, 10, GAMEPAD_INTERFACE) => RequestType::HidSetIdle,
-      (0x21, 11, GAMEPAD_INTERFACE) => RequestType::HidSetProtocol,
-      (_, 0, _) => RequestType::GetStatus,
-      (_, 5, _) => RequestType::SetAddress,
-      (_, 6, _) => RequestType::GetDescriptor,
-      _ => RequestType::Stall,
-    }
-  }
-}
 
-pub fn setup_usb(cs: &CriticalSection, usb: USB_DEVICE, pll: PLL, portd: PORTD) {
+use crate::clock;
+use crate::hal::{AvrPortD, AvrUsb};
+use crate::serial::{save_serial, seed_serial, SERIAL};
+use crate::usart::{fightstick_data, take_fightstick_data, tell_controller};
+use crate::{BUS_RESET_TASK, CONTROL_TASK, POWER_TASK, SCHEDULER};
+
+const CONTROL_LED: u8 = 4;
+const REPORT_LED: u8 = 5;
+
+// Owned by the main loop, so control transfers can wait on the host with
+// interrupts enabled. The interrupts use `AvrUsb::steal`.
+pub static PORTD: Resource<AvrPortD> = Resource::new();
+pub static USB_DEVICE: Resource<AvrUsb> = Resource::new();
+pub static USB_STATE: UsbState = UsbState::new();
+static REPORT_CACHE: Resource<ReportCache> = Resource::new();
+/// Set when the controller asks to wake the host, until `POWER_TASK` runs.
+static WAKEUP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+pub fn setup_usb(_cs: &CriticalSection, usb: USB_DEVICE, pll: PLL, mut portd: AvrPortD) {
   usb.usbcon.write(|w| w.frzclk().set_bit().usbe().set_bit());
+  let mut usb = AvrUsb::new(usb, pll);
+  usb.freeze_clock(false);
 
-  pll.pllcsr.write(|w| unsafe { w.bits(1 << 2).plle().set_bit() });
+  usb.attach();
+  usb.set_device_interrupt_enables(EORSTE | SOFE | SUSPE);
 
-  while pll.pllcsr.read().plock().bit_is_clear() {}
-
-  usb.usbcon.write(|w| w.usbe().set_bit());
-  usb.udcon.write(|w| unsafe { w.bits(0) });
-  usb.udien.write(|w| w.eorste().set_bit().sofe().set_bit());
+  for led in [CONTROL_LED, REPORT_LED].iter() {
+    portd.set_output(*led, true);
+    portd.write(*led, true);
+  }
 
-  portd.ddrd.write(|w| w.pd5().set_bit().pd4().set_bit());
-  portd.portd.write(|w| w.pd5().set_bit().pd4().set_bit());
+  USB_DEVICE.init(usb);
+  PORTD.init(portd);
+  REPORT_CACHE.init(ReportCache::new());
+}
 
-  USB_DEVICE.borrow(cs).replace(Some(usb));
-  PORTD.borrow(cs).replace(Some(portd));
+/// Whether the host has reset or suspended the bus, abandoning any transfer
+/// in progress.
+fn transfer_abandoned() -> bool {
+  SCHEDULER.is_pending(BUS_RESET_TASK) || USB_STATE.is_suspended()
 }
 
 #[interrupt(atmega8u2)]
 fn USB_GEN() {
-  free(|cs| {
-    let usb = USB_DEVICE.borrow(cs).borrow();
-
-    let eorsti = usb.as_ref().unwrap().udint.read().eorsti().bit();
-    usb.as_ref().unwrap().udint.write(|w| unsafe { w.bits(0) });
-
-    if eorsti {
-      usb.as_ref().unwrap().uenum.write(|w| unsafe { w.bits(0) });
-      usb.as_ref().unwrap().ueconx.write(|w| unsafe { w.bits(1) });
-      usb.as_ref().unwrap().uecfg0x.write(|w| w.eptype().bits(0));
-      usb.as_ref().unwrap().uecfg1x.write(|w| {
-        w.epsize()
-                    .bits(0x3) // 64 Bytes
-                    .alloc()
-                    .set_bit()
-      });
-      usb.as_ref().unwrap().ueienx.write(|w| w.rxstpe().set_bit());
-      *USB_CONFIGURED.borrow(cs).borrow_mut() = 0;
-    }
-  });
-}
-
-fn usb_send_in(_cs: &CriticalSection, usb: &Ref<Option<USB_DEVICE>>) {
-  usb.as_ref().unwrap().ueintx.modify(|_, w| w.txini().clear_bit());
-}
+  // Only the device interrupt flags and enables are touched, and the clock
+  // restarted on wakeup
+  let mut usb = unsafe { AvrUsb::steal() };
 
-fn usb_wait_in_ready(_cs: &CriticalSection, usb: &Ref<Option<USB_DEVICE>>) {
-  loop {
-    let txini = usb.as_ref().unwrap().ueintx.read().txini().bit();
-    if txini {
-      break;
-    }
+  let events = take_bus_events(&mut usb, &USB_STATE);
+  if events.reset {
+    SCHEDULER.signal(BUS_RESET_TASK);
   }
-}
-
-fn wait_for_host_ready(_cs: &CriticalSection, usb: &Ref<Option<USB_DEVICE>>) {
-  loop {
-    let txini = usb.as_ref().unwrap().ueintx.read().txini().bit();
-    let rxouti = usb.as_ref().unwrap().ueintx.read().rxouti().bit();
-    if !txini || !rxouti {
-      return;
-    }
+  if events.suspend || events.wakeup {
+    SCHEDULER.signal(POWER_TASK);
   }
 }
 
-fn usb_wait_receive_out(_cs: &CriticalSection, usb: &Ref<Option<USB_DEVICE>>) {
-  loop {
-    let rxouti = usb.as_ref().unwrap().ueintx.read().rxouti().bit();
-    if !rxouti {
-      return;
-    }
+/// Sets endpoint 0 back up after the host resets the bus.
+pub fn handle_bus_reset() {
+  let frame_number = USB_DEVICE.with(|usb| {
+    ofs_support::usb::handle_bus_reset(usb, &USB_STATE);
+    usb.frame_number()
+  });
+  if let Some(frame_number) = frame_number {
+    seed_serial(frame_number);
   }
 }
 
-fn usb_ack_out(_cs: &CriticalSection, usb: &Ref<Option<USB_DEVICE>>) {
-  usb
-    .as_ref()
-    .unwrap()
-    .ueintx
-    .write(|w| unsafe { w.bits(u8::max_value()).rxouti().clear_bit() });
-}
-
-pub fn send_gamepad_data(cs: &CriticalSection) {
-  let usb = USB_DEVICE.borrow(cs).borrow();
-  let config = USB_CONFIGURED.borrow(cs).borrow();
-
-  if *config == 0 {
-    return;
-  }
-
-  if let Some(usb) = usb.as_ref() {
-    PORTD
-      .borrow(cs)
-      .borrow()
-      .as_ref()
-      .unwrap()
-      .portd
-      .modify(|r, w| w.pd5().bit(!r.pd5().bit()));
-
-    usb.uenum.write(|w| unsafe { w.bits(GAMEPAD_ENDPOINT) });
-    let timeout: u16 = usb.udfnum.read().bits() + 50;
-
-    loop {
-      let rwal = usb.ueintx.read().rwal().bit();
-      if rwal {
-        break;
-      }
-
-      if *config == 0 {
-        return;
-      }
-
-      let udfnum = usb.udfnum.read().bits();
-      if udfnum >= timeout {
-        return;
-      }
-    }
-
-    for data in get_fightstick_data(cs).0.iter() {
-      usb.uedatx.write(|w| unsafe { w.bits(*data) });
-    }
-
-    usb.ueintx.write(|w| unsafe { w.bits(0x3A) });
-  }
+/// Asks for the host to be woken, once the controller sees a button pressed
+/// while the bus is suspended.
+pub fn request_wakeup() {
+  WAKEUP_REQUESTED.store(true, Ordering::Release);
+  SCHEDULER.signal(POWER_TASK);
 }
 
-fn get_descriptor(cs: &CriticalSection, usb: &Ref<Option<USB_DEVICE>>, value: u16, index: u16, length: u16) {
-  let descriptor_option = DESCRIPTOR_LIST.iter().find(|f| f.value == value && f.index == index);
-  if let Some(descriptor) = descriptor_option {
-    let mut len = (length.min(255) as u8).min(descriptor.data.len() as u8);
-    let mut table_index: u8 = 0;
-    loop {
-      wait_for_host_ready(cs, usb);
-      if usb.as_ref().unwrap().ueintx.read().rxouti().bit() {
-        return;
-      }
-      let n = ENDPOINT0_SIZE.min(len);
-      for _ in 0..n {
-        let data = descriptor.data[table_index as usize];
-        usb.as_ref().unwrap().uedatx.write(|w| unsafe { w.bits(data) });
-        table_index += 1;
-      }
-      len -= n;
-      usb_send_in(cs, usb);
-
-      if !(len > 0 || n == ENDPOINT0_SIZE) {
-        break;
-      }
-    }
-    return;
+/// Freezes the usb clock while the bus is suspended, letting the controller
+/// idle too, and brings both back when the host resumes the bus or is woken.
+pub fn handle_power() {
+  let wakeup = WAKEUP_REQUESTED.load(Ordering::Acquire);
+  WAKEUP_REQUESTED.store(false, Ordering::Release);
+
+  let change = USB_DEVICE.with(|usb| update_power(usb, &USB_STATE, wakeup)).flatten();
+  match change {
+    Some(PowerChange::Suspended) => {
+      // The LEDs are active low
+      PORTD.with(|portd| {
+        portd.write(CONTROL_LED, true);
+        portd.write(REPORT_LED, true);
+      });
+      clock::free(|cs| tell_controller(cs, UsartCommand::Suspend));
+    },
+    Some(PowerChange::Resumed) => {
+      // The host may have missed reports sent before it slept
+      REPORT_CACHE.with(|cache| cache.clear());
+      clock::free(|cs| tell_controller(cs, UsartCommand::Resume));
+    },
+    None => {},
   }
-  stall(cs, usb);
 }
 
-fn set_address(cs: &CriticalSection, usb: &Ref<Option<USB_DEVICE>>, value: u16) {
-  usb_send_in(cs, usb);
-  usb_wait_in_ready(cs, usb);
-  usb
-    .as_ref()
-    .unwrap()
-    .udaddr
-    .write(|w| w.uadd().bits(value as u8).adden().set_bit());
-}
-
-fn set_configuration(cs: &CriticalSection, usb: &Ref<Option<USB_DEVICE>>, value: u16) {
-  *USB_CONFIGURED.borrow(cs).borrow_mut() = value as u8;
-  usb_send_in(cs, usb);
-  let mut table_index = 0;
-  for i in 1..5 {
-    let en = ENDPOINT_TABLE[table_index];
-    usb.as_ref().unwrap().uenum.write(|w| unsafe { w.bits(i as u8) });
-    usb.as_ref().unwrap().ueconx.write(|w| unsafe { w.bits(en) });
-    table_index += 1;
-    if en > 0 {
-      usb
-        .as_ref()
-        .unwrap()
-        .uecfg0x
-        .write(|w| unsafe { w.bits(ENDPOINT_TABLE[table_index]) });
-      table_index += 1;
-      usb
-        .as_ref()
-        .unwrap()
-        .uecfg1x
-        .write(|w| unsafe { w.bits(ENDPOINT_TABLE[table_index]) });
-      table_index += 1;
-    }
+/// Sends the report to the host if it changed, or if the idle period set by
+/// the host has passed since it was last sent. Never waits on the host, a
+/// report the endpoint has no room for is offered again at the next poll.
+pub fn send_gamepad_data() {
+  let now = clock::free(clock::now);
+  let peek = || clock::free(fightstick_data);
+  let take = || clock::free(take_fightstick_data);
+
+  let sent = USB_DEVICE
+    .with(|usb| REPORT_CACHE.with(|cache| send_report(usb, &USB_STATE, cache, now, peek, take)))
+    .flatten();
+  if sent == Some(true) {
+    PORTD.with(|portd| portd.toggle(REPORT_LED));
   }
-  usb.as_ref().unwrap().uerst.write(|w| unsafe { w.bits(0x1E) });
-  usb.as_ref().unwrap().uerst.write(|w| unsafe { w.bits(0) });
-}
-
-fn stall(_cs: &CriticalSection, usb: &Ref<Option<USB_DEVICE>>) {
-  usb
-    .as_ref()
-    .unwrap()
-    .ueconx
-    .write(|w| w.stallrq().set_bit().epen().set_bit());
 }
 
 #[interrupt(atmega8u2)]
 fn USB_COM() {
-  free(|cs| {
-    let usb = USB_DEVICE.borrow(cs).borrow();
-
-    usb.as_ref().unwrap().uenum.write(|w| unsafe { w.bits(0) });
-    let rxstpi = usb.as_ref().unwrap().ueintx.read().rxstpi().bit();
-
-    if rxstpi {
-      let request_type = usb.as_ref().unwrap().uedatx.read().bits();
-      let request = usb.as_ref().unwrap().uedatx.read().bits();
-
-      let mut value = usb.as_ref().unwrap().uedatx.read().bits() as u16;
-      value |= (usb.as_ref().unwrap().uedatx.read().bits() as u16) << 8;
-
-      let mut index = usb.as_ref().unwrap().uedatx.read().bits() as u16;
-      index |= (usb.as_ref().unwrap().uedatx.read().bits() as u16) << 8;
-
-      let mut length = usb.as_ref().unwrap().uedatx.read().bits() as u16;
-      length |= (usb.as_ref().unwrap().uedatx.read().bits() as u16) << 8;
-
-      usb.as_ref().unwrap().ueintx.write(|w| unsafe {
-        w.bits(u8::max_value())
-          .rxstpi()
-          .clear_bit()
-          .rxouti()
-          .clear_bit()
-          .txini()
-          .clear_bit()
-      });
-
-      PORTD
-        .borrow(cs)
-        .borrow()
-        .as_ref()
-        .unwrap()
-        .portd
-        .modify(|r, w| w.pd4().bit(!r.pd4().bit()));
-
-      match RequestType::from_u8(request_type, request, index as u8) {
-        RequestType::GetDescriptor => {
-          get_descriptor(cs, &usb, value, index, length);
-        },
-        RequestType::SetAddress => set_address(cs, &usb, value),
-        RequestType::SetConfiguration => {
-          if request_type == 0 {
-            set_configuration(cs, &usb, value);
-          } else {
-            stall(cs, &usb);
-          }
-        },
-        RequestType::GetConfiguration => {
-          if request_type == 0x80 {
-            usb_wait_in_ready(cs, &usb);
-            let config = USB_CONFIGURED.borrow(cs).borrow();
-            usb.as_ref().unwrap().uedatx.write(|w| unsafe { w.bits(*config) });
-            usb_send_in(cs, &usb);
-          } else {
-            stall(cs, &usb)
-          }
-        },
-        RequestType::GetStatus => {
-          usb_wait_in_ready(cs, &usb);
-          usb.as_ref().unwrap().uedatx.write(|w| unsafe { w.bits(0) });
-          usb.as_ref().unwrap().uedatx.write(|w| unsafe { w.bits(0) });
-          usb_send_in(cs, &usb);
-        },
-        RequestType::HidGetIdle => {
-          let idle = USB_IDLE_CONFIG.borrow(cs).borrow();
-          usb_wait_in_ready(cs, &usb);
-          usb.as_ref().unwrap().uedatx.write(|w| unsafe { w.bits(*idle) });
-          usb_send_in(cs, &usb);
-        },
-        RequestType::HidGetProtocol => {
-          let protocol = USB_IDLE_CONFIG.borrow(cs).borrow();
-          usb_wait_in_ready(cs, &usb);
-          usb.as_ref().unwrap().uedatx.write(|w| unsafe { w.bits(*protocol) });
-          usb_send_in(cs, &usb);
-        },
-        RequestType::HidGetReport => {
-          usb_wait_in_ready(cs, &usb);
-          for data in INIT_BYTES.iter() {
-            usb.as_ref().unwrap().uedatx.write(|w| unsafe { w.bits(*data) });
-          }
+  // Only the interrupt enables are touched, and the endpoint is put back
+  let mut usb = unsafe { AvrUsb::steal() };
+
+  // Masked until the setup packet is handled, or the interrupt would fire
+  // again straight away
+  let endpoint = usb.selected_endpoint();
+  usb.select_endpoint(0);
+  let enables = usb.endpoint_interrupts();
+  usb.set_endpoint_interrupts(enables & !RXSTPE);
+  usb.select_endpoint(endpoint);
+
+  SCHEDULER.signal(CONTROL_TASK);
+}
 
-          usb_send_in(cs, &usb);
-        },
-        RequestType::HidSetReport => {
-          usb_wait_receive_out(cs, &usb);
-          usb_ack_out(cs, &usb);
-          usb_send_in(cs, &usb);
-        },
-        RequestType::HidSetIdle => {
-          *USB_IDLE_CONFIG.borrow(cs).borrow_mut() = (value >> 8) as u8;
-          usb_send_in(cs, &usb);
-        },
-        RequestType::HidSetProtocol => {
-          *USB_PROTOCOL.borrow(cs).borrow_mut() = value as u8;
-          usb_send_in(cs, &usb);
-        },
-        RequestType::Stall => stall(cs, &usb),
-        _ => stall(cs, &usb),
+/// Answers a setup packet on endpoint 0. Runs with interrupts enabled, waits
+/// on the host give up if the bus is reset or suspended.
+pub fn handle_control() {
+  USB_DEVICE.with(|usb| {
+    let report = || clock::free(take_fightstick_data);
+    let setup = SERIAL
+      .with(|serial| ControlEndpoint::new(usb, &USB_STATE, serial, report, transfer_abandoned).handle_setup())
+      .flatten();
+    if let Some(setup) = setup {
+      PORTD.with(|portd| portd.toggle(CONTROL_LED));
+      if setup.request_type() == RequestType::VendorSetSerialNumber {
+        save_serial();
       }
     }
+
+    usb.select_endpoint(0);
+    let enables = usb.endpoint_interrupts();
+    usb.set_endpoint_interrupts(enables | RXSTPE);
   });
 }